@@ -3,8 +3,15 @@ use std::fs::create_dir_all;
 
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
-use cw20::{AllowanceResponse, BalanceResponse, TokenInfoResponse};
-use cw20_bonding::msg::{CurveInfoResponse, ExecuteMsg, InstantiateMsg, QueryMsg};
+use cw20::{
+    AllowanceResponse, BalanceResponse, DownloadLogoResponse, MarketingInfoResponse,
+    TokenInfoResponse,
+};
+use cw20_bonding::msg::{
+    BasketInfoResponse, BatchResponse, CurveInfoResponse, ExecuteMsg, HostedCurveInfo,
+    HostedCurvesResponse, InstantiateMsg, MigrateMsg, ObservationsResponse, OrdersResponse,
+    QueryMsg, StakingResponse, TwapResponse, VestingResponse, VestingSchedulesResponse,
+};
 
 fn main() {
     let mut out_dir = current_dir().unwrap();
@@ -15,8 +22,21 @@ fn main() {
     export_schema(&schema_for!(InstantiateMsg), &out_dir);
     export_schema(&schema_for!(ExecuteMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(MigrateMsg), &out_dir);
     export_schema(&schema_for!(AllowanceResponse), &out_dir);
     export_schema(&schema_for!(BalanceResponse), &out_dir);
+    export_schema(&schema_for!(BasketInfoResponse), &out_dir);
+    export_schema(&schema_for!(BatchResponse), &out_dir);
     export_schema(&schema_for!(CurveInfoResponse), &out_dir);
+    export_schema(&schema_for!(DownloadLogoResponse), &out_dir);
+    export_schema(&schema_for!(HostedCurveInfo), &out_dir);
+    export_schema(&schema_for!(HostedCurvesResponse), &out_dir);
+    export_schema(&schema_for!(MarketingInfoResponse), &out_dir);
+    export_schema(&schema_for!(ObservationsResponse), &out_dir);
+    export_schema(&schema_for!(OrdersResponse), &out_dir);
+    export_schema(&schema_for!(StakingResponse), &out_dir);
     export_schema(&schema_for!(TokenInfoResponse), &out_dir);
+    export_schema(&schema_for!(TwapResponse), &out_dir);
+    export_schema(&schema_for!(VestingResponse), &out_dir);
+    export_schema(&schema_for!(VestingSchedulesResponse), &out_dir);
 }
@@ -0,0 +1,283 @@
+use std::collections::HashSet;
+
+use cosmwasm_std::{Addr, BankMsg, Coin, Deps, MessageInfo, Response, StdResult, Uint128};
+use cw_utils::{may_pay, must_pay, PaymentError};
+
+use crate::error::ContractError;
+use crate::msg::{BasketHolding, BasketInfoResponse};
+use crate::state::{BasketComponent, CURVE_STATE};
+
+/// Builds the reserve basket from the instantiate message. Without an explicit basket,
+/// this is just `reserve_denom` with weight 1, which behaves like a plain bonding curve.
+pub fn init_basket(
+    reserve_denom: String,
+    basket: Option<Vec<BasketComponent>>,
+) -> Result<Vec<BasketComponent>, ContractError> {
+    let basket = match basket {
+        Some(basket) => basket,
+        None => {
+            return Ok(vec![BasketComponent {
+                denom: reserve_denom,
+                weight: Uint128::new(1),
+            }])
+        }
+    };
+
+    let mut denoms = HashSet::new();
+    let valid = basket.first().map(|c| c.denom == reserve_denom) == Some(true)
+        && basket
+            .iter()
+            .all(|c| !c.weight.is_zero() && denoms.insert(c.denom.as_str()));
+    if !valid {
+        return Err(ContractError::InvalidBasket {});
+    }
+    Ok(basket)
+}
+
+/// How many whole basket units were sent along. Fails on any denom outside the basket.
+pub fn may_pay_basket(
+    info: &MessageInfo,
+    basket: &[BasketComponent],
+) -> Result<Uint128, ContractError> {
+    if let [single] = basket {
+        return Ok(may_pay(info, &single.denom)? / single.weight);
+    }
+    if let Some(extra) = info
+        .funds
+        .iter()
+        .find(|coin| !basket.iter().any(|c| c.denom == coin.denom))
+    {
+        return Err(PaymentError::ExtraDenom(extra.denom.clone()).into());
+    }
+    let units = basket
+        .iter()
+        .map(|c| sent(info, &c.denom) / c.weight)
+        .min()
+        .unwrap_or_default();
+    Ok(units)
+}
+
+/// Like `may_pay_basket`, but requires at least one whole basket unit
+pub fn must_pay_basket(
+    info: &MessageInfo,
+    basket: &[BasketComponent],
+) -> Result<Uint128, ContractError> {
+    let units = match basket {
+        [single] => must_pay(info, &single.denom)? / single.weight,
+        _ if info.funds.is_empty() => return Err(PaymentError::NoFunds {}.into()),
+        _ => may_pay_basket(info, basket)?,
+    };
+    if units.is_zero() {
+        return Err(ContractError::IncompleteBasket {});
+    }
+    Ok(units)
+}
+
+fn sent(info: &MessageInfo, denom: &str) -> Uint128 {
+    info.funds
+        .iter()
+        .filter(|coin| coin.denom == denom)
+        .map(|coin| coin.amount)
+        .sum()
+}
+
+/// The coins making up `units` basket units
+pub fn basket_coins(basket: &[BasketComponent], units: Uint128) -> Vec<Coin> {
+    basket
+        .iter()
+        .map(|c| Coin {
+            denom: c.denom.clone(),
+            amount: units * c.weight,
+        })
+        .collect()
+}
+
+/// Everything that was sent beyond `units` basket units, which is refunded to the sender
+pub fn basket_refund(info: &MessageInfo, basket: &[BasketComponent], units: Uint128) -> Vec<Coin> {
+    basket
+        .iter()
+        .map(|c| Coin {
+            denom: c.denom.clone(),
+            amount: sent(info, &c.denom) - units * c.weight,
+        })
+        .filter(|coin| !coin.amount.is_zero())
+        .collect()
+}
+
+/// Sends the refund back, if there is any
+pub fn add_refund(res: Response, to: &Addr, refund: Vec<Coin>) -> Response {
+    if refund.is_empty() {
+        return res;
+    }
+    res.add_message(BankMsg::Send {
+        to_address: to.to_string(),
+        amount: refund,
+    })
+}
+
+/// Names the basket unit in messages, eg. `uatom+uosmo`
+pub fn basket_name(basket: &[BasketComponent]) -> String {
+    basket
+        .iter()
+        .map(|c| c.denom.as_str())
+        .collect::<Vec<_>>()
+        .join("+")
+}
+
+pub fn query_basket_info(deps: Deps) -> StdResult<BasketInfoResponse> {
+    let state = CURVE_STATE.load(deps.storage)?;
+    let reserve = state.reserve;
+    let components = state
+        .basket
+        .into_iter()
+        .map(|c| BasketHolding {
+            amount: reserve * c.weight,
+            denom: c.denom,
+            weight: c.weight,
+        })
+        .collect();
+    Ok(BasketInfoResponse {
+        reserve,
+        components,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::msg::{CurveType, ExecuteMsg, InstantiateMsg};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{coin, coins, DepsMut, SubMsg};
+    use cw20_base::contract::query_balance;
+
+    const CREATOR: &str = "creator";
+    const INVESTOR: &str = "investor";
+
+    fn component(denom: &str, weight: u128) -> BasketComponent {
+        BasketComponent {
+            denom: denom.to_string(),
+            weight: Uint128::new(weight),
+        }
+    }
+
+    fn basket_instantiate(basket: Vec<BasketComponent>) -> InstantiateMsg {
+        InstantiateMsg {
+            name: "Index".to_string(),
+            symbol: "INDEX".to_string(),
+            decimals: 6,
+            reserve_denom: "uatom".to_string(),
+            reserve_decimals: 6,
+            basket: Some(basket),
+            curve_type: CurveType::Constant {
+                value: Uint128::new(1),
+                scale: 0,
+            },
+            admin: None,
+            treasury: None,
+            batch: None,
+            vesting: None,
+            staking: None,
+            factory: None,
+            marketing: None,
+        }
+    }
+
+    // one basket unit is 1 uatom and 3 uosmo, priced at 1 INDEX per unit
+    fn setup_test(deps: DepsMut) {
+        let msg = basket_instantiate(vec![component("uatom", 1), component("uosmo", 3)]);
+        instantiate(deps, mock_env(), mock_info(CREATOR, &[]), msg).unwrap();
+    }
+
+    #[test]
+    fn invalid_baskets() {
+        let invalid = vec![
+            vec![],
+            // must start with reserve_denom
+            vec![component("uosmo", 1), component("uatom", 1)],
+            vec![component("uatom", 1), component("uosmo", 0)],
+            vec![component("uatom", 1), component("uatom", 2)],
+        ];
+        for basket in invalid {
+            let mut deps = mock_dependencies();
+            let msg = basket_instantiate(basket);
+            let err =
+                instantiate(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap_err();
+            assert_eq!(err, ContractError::InvalidBasket {});
+        }
+    }
+
+    #[test]
+    fn buy_and_burn_basket() {
+        let mut deps = mock_dependencies();
+        setup_test(deps.as_mut());
+
+        // only one side of the basket doesn't buy anything
+        let info = mock_info(INVESTOR, &coins(1_000, "uatom"));
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Buy {}).unwrap_err();
+        assert_eq!(err, ContractError::IncompleteBasket {});
+        // and other denoms are rejected
+        let info = mock_info(INVESTOR, &[coin(1_000, "uatom"), coin(1_000, "ujuno")]);
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Buy {}).unwrap_err();
+        assert_eq!(err, PaymentError::ExtraDenom("ujuno".to_string()).into());
+
+        // 1000 uatom and 3500 uosmo buy 1000 units, the extra 500 uosmo are refunded
+        let info = mock_info(INVESTOR, &[coin(1_000, "uatom"), coin(3_500, "uosmo")]);
+        let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Buy {}).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: INVESTOR.to_string(),
+                amount: coins(500, "uosmo"),
+            })]
+        );
+        let balance = query_balance(deps.as_ref(), INVESTOR.to_string()).unwrap();
+        assert_eq!(balance.balance, Uint128::new(1_000));
+
+        // and 1200 uatom with 3000 uosmo buy 1000 more, refunding 200 uatom
+        let info = mock_info(INVESTOR, &[coin(1_200, "uatom"), coin(3_000, "uosmo")]);
+        let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Buy {}).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: INVESTOR.to_string(),
+                amount: coins(200, "uatom"),
+            })]
+        );
+
+        let basket = query_basket_info(deps.as_ref()).unwrap();
+        assert_eq!(basket.reserve, Uint128::new(2_000));
+        assert_eq!(
+            basket.components,
+            vec![
+                BasketHolding {
+                    denom: "uatom".to_string(),
+                    weight: Uint128::new(1),
+                    amount: Uint128::new(2_000),
+                },
+                BasketHolding {
+                    denom: "uosmo".to_string(),
+                    weight: Uint128::new(3),
+                    amount: Uint128::new(6_000),
+                },
+            ]
+        );
+
+        // burning a quarter returns a quarter of every component
+        let info = mock_info(INVESTOR, &[]);
+        let burn = ExecuteMsg::Burn {
+            amount: Uint128::new(500),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, burn).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: INVESTOR.to_string(),
+                amount: vec![coin(500, "uatom"), coin(1_500, "uosmo")],
+            })]
+        );
+        let basket = query_basket_info(deps.as_ref()).unwrap();
+        assert_eq!(basket.reserve, Uint128::new(1_500));
+        assert_eq!(basket.components[1].amount, Uint128::new(4_500));
+    }
+}
@@ -0,0 +1,923 @@
+use cosmwasm_std::{
+    Addr, BankMsg, BlockInfo, Decimal256, Deps, DepsMut, Env, MessageInfo, Order as Ordering,
+    Response, StdError, StdResult, Storage, Uint128,
+};
+use cw20_base::contract::execute_burn;
+use cw20_base::state::BALANCES;
+use cw_storage_plus::Bound;
+use cw_utils::nonpayable;
+
+use crate::basket::basket_coins;
+use crate::curves::Curve;
+use crate::error::ContractError;
+use crate::msg::{BatchResponse, CurveFn, OrderInfo, OrdersResponse};
+use crate::oracle::update_price_accumulator;
+use crate::staking::rebalance_stake;
+use crate::state::{
+    Batch, BatchConfig, CurveState, Order, OrderSide, Settlement, BATCH, CURVE_STATE, ORDERS,
+    ORDER_COUNT, SETTLEMENT,
+};
+use crate::vesting::mint_purchase;
+
+// settings for pagination
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+
+// how many orders SettleBatch fills per call
+const MAX_SETTLE_LIMIT: u32 = 100;
+const DEFAULT_SETTLE_LIMIT: u32 = 50;
+
+/// Order limit of batches from before there were any
+pub const DEFAULT_MAX_ORDERS: u32 = 1000;
+
+/// Enables batch auction mode, with the first batch ending one epoch from now
+pub fn init_batch(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    config: BatchConfig,
+) -> Result<(), ContractError> {
+    if config.max_orders == 0 {
+        return Err(ContractError::InvalidBatch {});
+    }
+    let batch = Batch {
+        ends: config.epoch.after(block),
+        config,
+        id: 1,
+        total_buy: Uint128::zero(),
+        total_sell: Uint128::zero(),
+        orders: 0,
+    };
+    BATCH.save(storage, &batch)?;
+    ORDER_COUNT.save(storage, &0)?;
+    Ok(())
+}
+
+/// Checks that an order of `amount` may join the batch, and counts it
+fn add_order(
+    storage: &dyn Storage,
+    batch: &mut Batch,
+    side: OrderSide,
+    amount: Uint128,
+) -> Result<(), ContractError> {
+    if SETTLEMENT.may_load(storage)?.is_some() {
+        return Err(ContractError::SettlementInProgress {});
+    }
+    let min = match side {
+        OrderSide::Buy => batch.config.min_buy,
+        OrderSide::Sell => batch.config.min_sell,
+    };
+    if amount < min {
+        return Err(ContractError::OrderTooSmall { min });
+    }
+    if batch.orders >= batch.config.max_orders {
+        return Err(ContractError::BatchFull {
+            max_orders: batch.config.max_orders,
+        });
+    }
+    batch.orders += 1;
+    Ok(())
+}
+
+fn next_order_id(storage: &mut dyn Storage) -> StdResult<u64> {
+    let id = ORDER_COUNT.load(storage)? + 1;
+    ORDER_COUNT.save(storage, &id)?;
+    Ok(id)
+}
+
+/// Queues a buy order with the reserve tokens that were already paid in
+pub fn queue_buy(
+    deps: DepsMut,
+    mut batch: Batch,
+    buyer: Addr,
+    payment: Uint128,
+) -> Result<Response, ContractError> {
+    add_order(deps.storage, &mut batch, OrderSide::Buy, payment)?;
+    batch.total_buy += payment;
+    BATCH.save(deps.storage, &batch)?;
+
+    let order_id = next_order_id(deps.storage)?;
+    let order = Order {
+        owner: buyer.clone(),
+        recipient: buyer.clone(),
+        side: OrderSide::Buy,
+        amount: payment,
+    };
+    ORDERS.save(deps.storage, order_id, &order)?;
+
+    let res = Response::new()
+        .add_attribute("action", "buy")
+        .add_attribute("from", buyer)
+        .add_attribute("reserve", payment)
+        .add_attribute("batch_id", batch.id.to_string())
+        .add_attribute("order_id", order_id.to_string());
+    Ok(res)
+}
+
+/// Queues a sell order, moving `amount` supply tokens from `holder` into escrow
+/// (the contract's own balance). The owner may cancel it, and the recipient gets the reserve.
+pub fn queue_sell(
+    deps: DepsMut,
+    env: &Env,
+    holder: &Addr,
+    owner: Addr,
+    recipient: Addr,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    if amount.is_zero() {
+        return Err(cw20_base::ContractError::InvalidZeroAmount {}.into());
+    }
+    let mut batch = BATCH.load(deps.storage)?;
+    add_order(deps.storage, &mut batch, OrderSide::Sell, amount)?;
+
+    // tokens received with Send are already held by the contract
+    if holder != &env.contract.address {
+        move_balance(deps.storage, holder, &env.contract.address, amount)?;
+    }
+    batch.total_sell += amount;
+    BATCH.save(deps.storage, &batch)?;
+
+    let order_id = next_order_id(deps.storage)?;
+    let order = Order {
+        owner: owner.clone(),
+        recipient,
+        side: OrderSide::Sell,
+        amount,
+    };
+    ORDERS.save(deps.storage, order_id, &order)?;
+
+    let res = Response::new()
+        .add_attribute("from", owner)
+        .add_attribute("supply", amount)
+        .add_attribute("batch_id", batch.id.to_string())
+        .add_attribute("order_id", order_id.to_string());
+    Ok(res)
+}
+
+fn move_balance(
+    storage: &mut dyn Storage,
+    from: &Addr,
+    to: &Addr,
+    amount: Uint128,
+) -> Result<(), ContractError> {
+    BALANCES.update(storage, from, |balance| -> StdResult<_> {
+        Ok(balance.unwrap_or_default().checked_sub(amount)?)
+    })?;
+    BALANCES.update(storage, to, |balance| -> StdResult<_> {
+        Ok(balance.unwrap_or_default() + amount)
+    })?;
+    Ok(())
+}
+
+pub fn execute_cancel_order(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    order_id: u64,
+) -> Result<Response, ContractError> {
+    nonpayable(&info)?;
+    let mut batch = BATCH
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NotBatchMode {})?;
+    // orders being filled are already cleared
+    if SETTLEMENT.may_load(deps.storage)?.is_some() {
+        return Err(ContractError::SettlementInProgress {});
+    }
+    let order = ORDERS.load(deps.storage, order_id)?;
+    if order.owner != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    ORDERS.remove(deps.storage, order_id);
+    batch.orders -= 1;
+
+    let mut res = Response::new()
+        .add_attribute("action", "cancel_order")
+        .add_attribute("order_id", order_id.to_string())
+        .add_attribute("owner", &order.owner);
+    match order.side {
+        OrderSide::Buy => {
+            batch.total_buy -= order.amount;
+            let basket = CURVE_STATE.load(deps.storage)?.basket;
+            res = res
+                .add_message(BankMsg::Send {
+                    to_address: order.owner.to_string(),
+                    amount: basket_coins(&basket, order.amount),
+                })
+                .add_attribute("reserve", order.amount);
+        }
+        OrderSide::Sell => {
+            batch.total_sell -= order.amount;
+            move_balance(
+                deps.storage,
+                &env.contract.address,
+                &order.owner,
+                order.amount,
+            )?;
+            res = res.add_attribute("supply", order.amount);
+        }
+    }
+    BATCH.save(deps.storage, &batch)?;
+    Ok(res)
+}
+
+/// How a batch clears: buyers share `tokens_to_buyers` pro rata to the reserve they paid,
+/// and sellers share `reserve_to_sellers` pro rata to the tokens they sold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Clearing {
+    pub tokens_to_buyers: Uint128,
+    pub reserve_to_sellers: Uint128,
+}
+
+/// Nets all buys (`total_buy` reserve) against all sells (`total_sell` supply) at a single
+/// price. Only the net flow moves along the curve: if buys dominate, `d` tokens are minted,
+/// if sells dominate `d` tokens are burned, and the reserve changes by at most the curve
+/// integral over those `d` tokens. Everyone trades at the same price, which is the average
+/// price of the net flow, with rounding always in favour of the contract.
+pub fn clear_batch(
+    curve: &dyn Curve,
+    state: &CurveState,
+    total_buy: Uint128,
+    total_sell: Uint128,
+) -> StdResult<Clearing> {
+    let supply = state.supply;
+    if total_sell.is_zero() {
+        // only buys, this is just like execute_buy
+        let tokens = curve
+            .supply(state.reserve + total_buy)?
+            .checked_sub(supply)
+            .map_err(StdError::overflow)?;
+        return Ok(Clearing {
+            tokens_to_buyers: tokens,
+            reserve_to_sellers: Uint128::zero(),
+        });
+    }
+    if total_buy.is_zero() {
+        // only sells, this is just like do_sell
+        let remaining = curve.reserve(supply.checked_sub(total_sell)?)?;
+        let released = state
+            .reserve
+            .checked_sub(remaining)
+            .map_err(StdError::overflow)?;
+        return Ok(Clearing {
+            tokens_to_buyers: Uint128::zero(),
+            reserve_to_sellers: released,
+        });
+    }
+
+    let base = curve.reserve(supply)?;
+    // minting d tokens is possible, if buyers paying for d + total_sell tokens at the average
+    // price of those d tokens along the curve can afford it
+    // (a reserve beyond `Uint128` is more than any buyers can pay)
+    let mint_ok = |d: Uint128| match supply.checked_add(d).map(|s| curve.reserve(s)) {
+        Ok(Ok(reserve)) => (d + total_sell).full_mul(reserve - base) <= total_buy.full_mul(d),
+        _ => false,
+    };
+    // burning d tokens is possible, if sellers getting the average price of those d tokens
+    // along the curve for all their tokens is covered by the buyers and the released reserve
+    let burn_ok = |d: Uint128| match curve.reserve(supply - d) {
+        Ok(reserve) => {
+            d < total_sell && total_buy.full_mul(d) <= (total_sell - d).full_mul(base - reserve)
+        }
+        Err(_) => false,
+    };
+
+    let one = Uint128::new(1);
+    let clearing = if mint_ok(one) {
+        // the more we mint, the higher the price, so find the most we can mint
+        let mut low = one;
+        let mut high = Uint128::new(2);
+        while mint_ok(high) {
+            low = high;
+            high = high.checked_mul(Uint128::new(2))?;
+        }
+        let d = search_max(low, high, mint_ok);
+        let tokens = d + total_sell;
+        Clearing {
+            tokens_to_buyers: tokens,
+            reserve_to_sellers: total_buy.multiply_ratio(total_sell, tokens),
+        }
+    } else if burn_ok(one) {
+        // the more we burn, the higher the price, so find the most we can burn
+        let d = search_max(one, total_sell, burn_ok);
+        let tokens = total_sell - d;
+        Clearing {
+            tokens_to_buyers: tokens,
+            reserve_to_sellers: total_buy.multiply_ratio(total_sell, tokens),
+        }
+    } else {
+        // buys and sells match, so they just swap at the current price
+        Clearing {
+            tokens_to_buyers: total_sell,
+            reserve_to_sellers: total_buy,
+        }
+    };
+    Ok(clearing)
+}
+
+/// Returns the largest value in `[low, high)` for which `ok` holds.
+/// Requires `ok(low)` and that `ok` is true up to some point, and false afterwards.
+fn search_max<F: Fn(Uint128) -> bool>(mut low: Uint128, mut high: Uint128, ok: F) -> Uint128 {
+    while high - low > Uint128::new(1) {
+        let mid = low + (high - low) / Uint128::new(2);
+        if ok(mid) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
+
+/// Settles the batch once its epoch has ended, and fills up to `limit` of its orders. Anyone
+/// may call this again until all orders are filled, no orders are taken in the meantime.
+pub fn execute_settle_batch(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    curve_fn: CurveFn,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    nonpayable(&info)?;
+    let batch = BATCH
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NotBatchMode {})?;
+
+    let (settlement, res) = match SETTLEMENT.may_load(deps.storage)? {
+        Some(settlement) => {
+            let res = Response::new()
+                .add_attribute("action", "settle_batch")
+                .add_attribute("batch_id", settlement.batch_id.to_string());
+            (settlement, res)
+        }
+        None => {
+            if !batch.ends.is_expired(&env.block) {
+                return Err(ContractError::BatchNotEnded {});
+            }
+            clear_and_book(deps.branch(), &env, batch, curve_fn)?
+        }
+    };
+    fill_orders(deps, env, settlement, res, limit)
+}
+
+/// Clears the batch at a single price, and books that in the curve state right away. Sold
+/// tokens are burnt, while the orders are filled by `fill_orders`. Also starts the next batch.
+fn clear_and_book(
+    mut deps: DepsMut,
+    env: &Env,
+    mut batch: Batch,
+    curve_fn: CurveFn,
+) -> Result<(Settlement, Response), ContractError> {
+    let mut state = CURVE_STATE.load(deps.storage)?;
+    let curve = curve_fn(state.decimals);
+    update_price_accumulator(
+        deps.storage,
+        env.block.time,
+        curve.spot_price(state.supply)?,
+    )?;
+    // clear against the curve alone, any extra backing is scaled back in (see `to_curve`)
+    let curve_reserve = curve.reserve(state.supply)?;
+    let mut view = state.clone();
+    view.reserve = state.to_curve(state.reserve, curve_reserve);
+    let total_buy = state.to_curve(batch.total_buy, curve_reserve);
+    let mut clearing = clear_batch(curve.as_ref(), &view, total_buy, batch.total_sell)?;
+    clearing.reserve_to_sellers = state.from_curve(clearing.reserve_to_sellers, curve_reserve);
+
+    // all sold tokens are burnt, and buyers get freshly minted ones
+    if !batch.total_sell.is_zero() {
+        let contract_info = MessageInfo {
+            sender: env.contract.address.clone(),
+            funds: vec![],
+        };
+        execute_burn(deps.branch(), env.clone(), contract_info, batch.total_sell)?;
+    }
+    state.supply = state.supply - batch.total_sell + clearing.tokens_to_buyers;
+    state.reserve = state.reserve + batch.total_buy - clearing.reserve_to_sellers;
+    CURVE_STATE.save(deps.storage, &state)?;
+    // the sellers are paid from the liquid reserve
+    let stake = rebalance_stake(deps.storage, env, &state, clearing.reserve_to_sellers)?;
+
+    // report the price like spot_price, in whole reserve tokens per whole supply token
+    let price = if !clearing.tokens_to_buyers.is_zero() {
+        Decimal256::from_ratio(batch.total_buy, clearing.tokens_to_buyers)
+    } else if !batch.total_sell.is_zero() {
+        Decimal256::from_ratio(clearing.reserve_to_sellers, batch.total_sell)
+    } else {
+        Decimal256::zero()
+    };
+    let places = state.decimals;
+    let unit = |places: u32| {
+        Uint128::new(10)
+            .checked_pow(places)
+            .map_err(StdError::overflow)
+    };
+    let scale = Decimal256::from_ratio(unit(places.supply)?, unit(places.reserve)?);
+    let price = price.checked_mul(scale).map_err(StdError::overflow)?;
+
+    let settlement = Settlement {
+        batch_id: batch.id,
+        total_buy: batch.total_buy,
+        total_sell: batch.total_sell,
+        tokens_to_buyers: clearing.tokens_to_buyers,
+        reserve_to_sellers: clearing.reserve_to_sellers,
+        minted: Uint128::zero(),
+        paid: Uint128::zero(),
+    };
+    let res = Response::new()
+        .add_messages(stake)
+        .add_attribute("action", "settle_batch")
+        .add_attribute("batch_id", batch.id.to_string())
+        .add_attribute("total_buy", batch.total_buy)
+        .add_attribute("total_sell", batch.total_sell)
+        .add_attribute("clearing_price", price.to_string());
+
+    // start the next batch, which takes orders once this one is filled
+    batch.id += 1;
+    batch.ends = batch.config.epoch.after(&env.block);
+    batch.total_buy = Uint128::zero();
+    batch.total_sell = Uint128::zero();
+    batch.orders = 0;
+    BATCH.save(deps.storage, &batch)?;
+
+    Ok((settlement, res))
+}
+
+/// Fills up to `limit` orders of the settled batch, pro rata to the clearing. Once all are
+/// filled, what was lost to rounding is taken out of the books again.
+fn fill_orders(
+    mut deps: DepsMut,
+    env: Env,
+    mut settlement: Settlement,
+    res: Response,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let limit = limit.unwrap_or(DEFAULT_SETTLE_LIMIT).min(MAX_SETTLE_LIMIT) as usize;
+    let orders = ORDERS
+        .range(deps.storage, None, None, Ordering::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut state = CURVE_STATE.load(deps.storage)?;
+
+    let mut minted = Uint128::zero();
+    let mut paid = Uint128::zero();
+    let mut payouts = vec![];
+    for (order_id, order) in &orders {
+        ORDERS.remove(deps.storage, *order_id);
+        match order.side {
+            OrderSide::Buy => {
+                let tokens = settlement
+                    .tokens_to_buyers
+                    .multiply_ratio(order.amount, settlement.total_buy);
+                if !tokens.is_zero() {
+                    mint_purchase(deps.branch(), env.clone(), &order.recipient, tokens)?;
+                    minted += tokens;
+                }
+            }
+            OrderSide::Sell => {
+                let reserve = settlement
+                    .reserve_to_sellers
+                    .multiply_ratio(order.amount, settlement.total_sell);
+                if !reserve.is_zero() {
+                    payouts.push(BankMsg::Send {
+                        to_address: order.recipient.to_string(),
+                        amount: basket_coins(&state.basket, reserve),
+                    });
+                    paid += reserve;
+                }
+            }
+        }
+    }
+    settlement.minted += minted;
+    settlement.paid += paid;
+
+    let done = ORDERS
+        .keys(deps.storage, None, None, Ordering::Ascending)
+        .next()
+        .is_none();
+    if done {
+        state.supply -= settlement.tokens_to_buyers - settlement.minted;
+        state.reserve += settlement.reserve_to_sellers - settlement.paid;
+        CURVE_STATE.save(deps.storage, &state)?;
+        SETTLEMENT.remove(deps.storage);
+    } else {
+        SETTLEMENT.save(deps.storage, &settlement)?;
+    }
+
+    let res = res
+        .add_messages(payouts)
+        .add_attribute("filled", orders.len().to_string())
+        .add_attribute("minted", minted)
+        .add_attribute("released", paid)
+        .add_attribute("done", done.to_string());
+    Ok(res)
+}
+
+pub fn query_batch(deps: Deps) -> StdResult<BatchResponse> {
+    let batch = BATCH
+        .may_load(deps.storage)?
+        .ok_or_else(|| StdError::generic_err(ContractError::NotBatchMode {}.to_string()))?;
+    Ok(BatchResponse {
+        id: batch.id,
+        config: batch.config,
+        ends: batch.ends,
+        total_buy: batch.total_buy,
+        total_sell: batch.total_sell,
+        orders: batch.orders,
+        settlement: SETTLEMENT.may_load(deps.storage)?,
+    })
+}
+
+pub fn query_orders(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<OrdersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let orders = ORDERS
+        .range(deps.storage, start, None, Ordering::Ascending)
+        .take(limit)
+        .map(|item| {
+            item.map(|(order_id, order)| OrderInfo {
+                order_id,
+                owner: order.owner.into(),
+                recipient: order.recipient.into(),
+                side: order.side,
+                amount: order.amount,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(OrdersResponse { orders })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate, query_curve_info};
+    use crate::curves::{decimal, Constant, DecimalPlaces};
+    use crate::msg::{CurveType, ExecuteMsg, InstantiateMsg};
+    use crate::state::BasketComponent;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MOCK_CONTRACT_ADDR};
+    use cosmwasm_std::{attr, coins, SubMsg};
+    use cw20_base::contract::{query_balance, query_token_info};
+    use cw_utils::Duration;
+
+    const DENOM: &str = "satoshi";
+    const CREATOR: &str = "creator";
+    const ALICE: &str = "alice";
+    const BOB: &str = "bob";
+    const CARL: &str = "carl";
+
+    fn state(reserve: u128, supply: u128) -> CurveState {
+        CurveState {
+            reserve: Uint128::new(reserve),
+            supply: Uint128::new(supply),
+            basket: vec![BasketComponent {
+                denom: DENOM.to_string(),
+                weight: Uint128::new(1),
+            }],
+            decimals: DecimalPlaces::new(0, 0),
+        }
+    }
+
+    fn clearing(tokens_to_buyers: u128, reserve_to_sellers: u128) -> Clearing {
+        Clearing {
+            tokens_to_buyers: Uint128::new(tokens_to_buyers),
+            reserve_to_sellers: Uint128::new(reserve_to_sellers),
+        }
+    }
+
+    #[test]
+    fn clearing_on_constant_curve() {
+        // every token costs 2 reserve
+        let curve = Constant::new(decimal(2u128, 0), DecimalPlaces::new(0, 0));
+        let state = state(200, 100);
+        let clear = |buy: u128, sell: u128| {
+            clear_batch(&curve, &state, Uint128::new(buy), Uint128::new(sell)).unwrap()
+        };
+
+        // only buys or only sells trade along the curve
+        assert_eq!(clear(100, 0), clearing(50, 0));
+        assert_eq!(clear(0, 10), clearing(0, 20));
+        // buys dominate: 10 tokens come from sellers, 40 more are minted
+        assert_eq!(clear(100, 10), clearing(50, 20));
+        // sells dominate: 5 tokens go to buyers, 45 are burned
+        assert_eq!(clear(10, 50), clearing(5, 100));
+        // they match exactly
+        assert_eq!(clear(20, 10), clearing(10, 20));
+    }
+
+    fn batch_config(max_orders: u32) -> BatchConfig {
+        BatchConfig {
+            epoch: Duration::Height(10),
+            min_buy: Uint128::new(1_000_000),
+            min_sell: Uint128::new(10),
+            max_orders,
+        }
+    }
+
+    fn instantiate_batched(deps: DepsMut, config: BatchConfig) {
+        let msg = InstantiateMsg {
+            name: "Bonded".to_string(),
+            symbol: "EPOXY".to_string(),
+            decimals: 2,
+            reserve_denom: DENOM.to_string(),
+            reserve_decimals: 8,
+            basket: None,
+            curve_type: CurveType::Linear {
+                slope: Uint128::new(1),
+                scale: 1,
+            },
+            admin: None,
+            treasury: None,
+            batch: Some(config),
+            vesting: None,
+            staking: None,
+            factory: None,
+            marketing: None,
+        };
+        instantiate(deps, mock_env(), mock_info(CREATOR, &[]), msg).unwrap();
+    }
+
+    fn env_after(blocks: u64) -> Env {
+        let mut env = mock_env();
+        env.block.height += blocks;
+        env
+    }
+
+    fn balance(deps: Deps, addr: &str) -> Uint128 {
+        query_balance(deps, addr.to_string()).unwrap().balance
+    }
+
+    #[test]
+    fn batch_clears_at_single_price() {
+        let mut deps = mock_dependencies();
+        instantiate_batched(deps.as_mut(), batch_config(10));
+        let curve_fn = || {
+            CurveType::Linear {
+                slope: Uint128::new(1),
+                scale: 1,
+            }
+            .to_curve_fn()
+        };
+
+        // two buys are queued, but nothing is minted yet
+        let info = mock_info(ALICE, &coins(1_500_000_000, DENOM));
+        let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Buy {}).unwrap();
+        assert!(res.attributes.contains(&attr("order_id", "1")));
+        let info = mock_info(BOB, &coins(500_000_000, DENOM));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Buy {}).unwrap();
+        assert_eq!(balance(deps.as_ref(), ALICE), Uint128::zero());
+        let batch = query_batch(deps.as_ref()).unwrap();
+        assert_eq!(batch.total_buy, Uint128::new(2_000_000_000));
+
+        // cannot settle before the epoch is over
+        let settle = ExecuteMsg::SettleBatch { limit: None };
+        let info = mock_info(CARL, &[]);
+        let err = execute(deps.as_mut(), env_after(9), info.clone(), settle.clone()).unwrap_err();
+        assert_eq!(err, ContractError::BatchNotEnded {});
+
+        // anyone can settle afterwards, and both buyers pay the same price
+        execute(deps.as_mut(), env_after(10), info.clone(), settle.clone()).unwrap();
+        assert_eq!(balance(deps.as_ref(), ALICE), Uint128::new(1500));
+        assert_eq!(balance(deps.as_ref(), BOB), Uint128::new(500));
+        let batch = query_batch(deps.as_ref()).unwrap();
+        assert_eq!(batch.id, 2);
+        assert_eq!(batch.ends, Duration::Height(10).after(&env_after(10).block));
+        assert!(query_orders(deps.as_ref(), None, None)
+            .unwrap()
+            .orders
+            .is_empty());
+
+        // in the next batch, alice sells 10.00 EPOXY while carl buys with 10 BTC
+        let info = mock_info(ALICE, &[]);
+        let burn = ExecuteMsg::Burn {
+            amount: Uint128::new(1000),
+        };
+        execute(deps.as_mut(), env_after(11), info, burn).unwrap();
+        assert_eq!(balance(deps.as_ref(), ALICE), Uint128::new(500));
+        assert_eq!(
+            balance(deps.as_ref(), MOCK_CONTRACT_ADDR),
+            Uint128::new(1000)
+        );
+        let info = mock_info(CARL, &coins(1_000_000_000, DENOM));
+        execute(deps.as_mut(), env_after(11), info, ExecuteMsg::Buy {}).unwrap();
+
+        // bob queues a buy and a sell, and cancels them again
+        let info = mock_info(BOB, &coins(100_000_000, DENOM));
+        execute(deps.as_mut(), env_after(12), info, ExecuteMsg::Buy {}).unwrap();
+        let info = mock_info(BOB, &[]);
+        let sell = ExecuteMsg::SellTo {
+            amount: Uint128::new(200),
+            recipient: CARL.into(),
+        };
+        execute(deps.as_mut(), env_after(12), info, sell).unwrap();
+        assert_eq!(balance(deps.as_ref(), BOB), Uint128::new(300));
+        let orders = query_orders(deps.as_ref(), Some(3), None).unwrap().orders;
+        let ids: Vec<_> = orders.iter().map(|o| o.order_id).collect();
+        assert_eq!(ids, vec![4, 5, 6]);
+        assert_eq!(orders[2].side, OrderSide::Sell);
+        assert_eq!(orders[2].recipient, CARL);
+
+        let cancel = ExecuteMsg::CancelOrder { order_id: 5 };
+        let err = execute(
+            deps.as_mut(),
+            env_after(13),
+            mock_info(CARL, &[]),
+            cancel.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let res = execute(deps.as_mut(), env_after(13), mock_info(BOB, &[]), cancel).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: BOB.into(),
+                amount: coins(100_000_000, DENOM),
+            })]
+        );
+        let cancel = ExecuteMsg::CancelOrder { order_id: 6 };
+        execute(deps.as_mut(), env_after(13), mock_info(BOB, &[]), cancel).unwrap();
+        assert_eq!(balance(deps.as_ref(), BOB), Uint128::new(500));
+        let batch = query_batch(deps.as_ref()).unwrap();
+        assert_eq!(batch.total_buy, Uint128::new(1_000_000_000));
+        assert_eq!(batch.total_sell, Uint128::new(1000));
+
+        // sells dominate, so 438 tokens are burned and carl gets the other 562 from alice,
+        // everyone at 1.779 BTC, between the spot price before (2.0) and after (1.562)
+        let res = execute(deps.as_mut(), env_after(20), mock_info(CARL, &[]), settle).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: ALICE.into(),
+                amount: coins(1_779_359_430, DENOM),
+            })]
+        );
+        assert_eq!(balance(deps.as_ref(), CARL), Uint128::new(562));
+        assert_eq!(balance(deps.as_ref(), MOCK_CONTRACT_ADDR), Uint128::zero());
+
+        // the reserve is still fully backing the supply
+        let curve = query_curve_info(deps.as_ref(), curve_fn()).unwrap();
+        assert_eq!(curve.supply, Uint128::new(1562));
+        assert_eq!(curve.reserve, Uint128::new(1_220_640_570));
+        assert!(
+            curve_fn()(DecimalPlaces::new(2, 8))
+                .reserve(curve.supply)
+                .unwrap()
+                <= curve.reserve
+        );
+        let token = query_token_info(deps.as_ref()).unwrap();
+        assert_eq!(token.total_supply, curve.supply);
+    }
+
+    #[test]
+    fn batches_are_bounded_and_settled_in_pages() {
+        let mut deps = mock_dependencies();
+        instantiate_batched(deps.as_mut(), batch_config(3));
+        let buy = |deps: DepsMut, buyer: &str, amount: u128| {
+            let info = mock_info(buyer, &coins(amount, DENOM));
+            execute(deps, mock_env(), info, ExecuteMsg::Buy {})
+        };
+
+        // dust orders are refused
+        let err = buy(deps.as_mut(), ALICE, 999_999).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::OrderTooSmall {
+                min: Uint128::new(1_000_000)
+            }
+        );
+
+        // and a batch only takes so many orders, cancelled ones make room again
+        buy(deps.as_mut(), ALICE, 1_000_000_000).unwrap();
+        buy(deps.as_mut(), BOB, 500_000_000).unwrap();
+        buy(deps.as_mut(), BOB, 500_000_000).unwrap();
+        let err = buy(deps.as_mut(), CARL, 500_000_000).unwrap_err();
+        assert_eq!(err, ContractError::BatchFull { max_orders: 3 });
+        let cancel = ExecuteMsg::CancelOrder { order_id: 3 };
+        execute(deps.as_mut(), mock_env(), mock_info(BOB, &[]), cancel).unwrap();
+        buy(deps.as_mut(), CARL, 500_000_000).unwrap();
+        assert_eq!(query_batch(deps.as_ref()).unwrap().orders, 3);
+
+        // the first call clears the batch and fills two orders
+        let settle = ExecuteMsg::SettleBatch { limit: Some(2) };
+        let info = mock_info(CARL, &[]);
+        let res = execute(deps.as_mut(), env_after(10), info.clone(), settle.clone()).unwrap();
+        assert!(res.attributes.contains(&attr("filled", "2")));
+        assert!(res.attributes.contains(&attr("done", "false")));
+        assert_eq!(balance(deps.as_ref(), ALICE), Uint128::new(1000));
+        assert_eq!(balance(deps.as_ref(), BOB), Uint128::new(500));
+        assert_eq!(balance(deps.as_ref(), CARL), Uint128::zero());
+
+        // the next batch has started, but takes no orders until this one is filled
+        let batch = query_batch(deps.as_ref()).unwrap();
+        assert_eq!(batch.id, 2);
+        assert_eq!(batch.orders, 0);
+        let settlement = batch.settlement.unwrap();
+        assert_eq!(settlement.batch_id, 1);
+        assert_eq!(settlement.tokens_to_buyers, Uint128::new(2000));
+        assert_eq!(settlement.minted, Uint128::new(1500));
+        let err = buy(deps.as_mut(), ALICE, 1_000_000).unwrap_err();
+        assert_eq!(err, ContractError::SettlementInProgress {});
+        let cancel = ExecuteMsg::CancelOrder { order_id: 4 };
+        let err = execute(deps.as_mut(), mock_env(), mock_info(CARL, &[]), cancel).unwrap_err();
+        assert_eq!(err, ContractError::SettlementInProgress {});
+        // carl's tokens are already part of the supply
+        let curve_fn = CurveType::Linear {
+            slope: Uint128::new(1),
+            scale: 1,
+        }
+        .to_curve_fn();
+        let curve = query_curve_info(deps.as_ref(), curve_fn).unwrap();
+        assert_eq!(curve.supply, Uint128::new(2000));
+        assert_eq!(curve.reserve, Uint128::new(2_000_000_000));
+
+        // anyone can fill the rest
+        let res = execute(deps.as_mut(), env_after(11), info, settle).unwrap();
+        assert!(res.attributes.contains(&attr("filled", "1")));
+        assert!(res.attributes.contains(&attr("done", "true")));
+        assert_eq!(balance(deps.as_ref(), CARL), Uint128::new(500));
+        assert_eq!(query_batch(deps.as_ref()).unwrap().settlement, None);
+        let token = query_token_info(deps.as_ref()).unwrap();
+        assert_eq!(token.total_supply, Uint128::new(2000));
+        buy(deps.as_mut(), ALICE, 1_000_000).unwrap();
+    }
+
+    #[test]
+    fn settling_beyond_the_supported_decimals_fails() {
+        let mut deps = mock_dependencies();
+        instantiate_batched(deps.as_mut(), batch_config(10));
+        let info = mock_info(ALICE, &coins(1_000_000_000, DENOM));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Buy {}).unwrap();
+
+        // contracts from before the decimals were capped may have any number of them
+        let mut state = CURVE_STATE.load(&deps.storage).unwrap();
+        state.decimals = DecimalPlaces::new(2, 40);
+        CURVE_STATE.save(&mut deps.storage, &state).unwrap();
+
+        let settle = ExecuteMsg::SettleBatch { limit: None };
+        let info = mock_info(CREATOR, &[]);
+        execute(deps.as_mut(), env_after(10), info, settle).unwrap_err();
+    }
+
+    #[test]
+    fn batch_messages_need_batch_mode() {
+        let mut deps = mock_dependencies();
+        let mut msg = InstantiateMsg {
+            name: "Bonded".to_string(),
+            symbol: "EPOXY".to_string(),
+            decimals: 2,
+            reserve_denom: DENOM.to_string(),
+            reserve_decimals: 8,
+            basket: None,
+            curve_type: CurveType::Constant {
+                value: Uint128::new(1),
+                scale: 0,
+            },
+            admin: None,
+            treasury: None,
+            batch: None,
+            vesting: None,
+            staking: None,
+            factory: None,
+            marketing: None,
+        };
+        instantiate(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(CREATOR, &[]),
+            msg.clone(),
+        )
+        .unwrap();
+
+        let info = mock_info(ALICE, &[]);
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::SettleBatch { limit: None },
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::NotBatchMode {});
+        let cancel = ExecuteMsg::CancelOrder { order_id: 1 };
+        let err = execute(deps.as_mut(), mock_env(), info, cancel).unwrap_err();
+        assert_eq!(err, ContractError::NotBatchMode {});
+
+        // in batch mode, an empty batch can also be settled, which just starts the next one
+        let mut deps = mock_dependencies();
+        msg.batch = Some(BatchConfig {
+            epoch: Duration::Time(60),
+            ..batch_config(10)
+        });
+        instantiate(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap();
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(60);
+        execute(
+            deps.as_mut(),
+            env,
+            mock_info(ALICE, &[]),
+            ExecuteMsg::SettleBatch { limit: None },
+        )
+        .unwrap();
+        assert_eq!(query_batch(deps.as_ref()).unwrap().id, 2);
+    }
+}
@@ -1,33 +1,65 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    attr, coins, to_binary, Addr, BankMsg, Binary, Deps, DepsMut, Env, MessageInfo, Response,
-    StdError, StdResult, Uint128,
+    attr, from_binary, to_binary, Addr, BankMsg, Binary, Deps, DepsMut, Env, MessageInfo, Reply,
+    Response, StdError, StdResult, Uint128,
 };
 
-use cw2::set_contract_version;
+use cw2::{get_contract_version, set_contract_version};
+use cw20::{Cw20ReceiveMsg, MarketingInfoResponse};
 use cw20_base::allowances::{
     deduct_allowance, execute_decrease_allowance, execute_increase_allowance, execute_send_from,
     execute_transfer_from, query_allowance,
 };
 use cw20_base::contract::{
-    execute_burn, execute_mint, execute_send, execute_transfer, query_balance, query_token_info,
+    execute_burn, execute_send, execute_transfer, execute_update_marketing, execute_upload_logo,
+    query_balance, query_download_logo, query_marketing_info, query_token_info,
 };
-use cw20_base::state::{MinterData, TokenInfo, TOKEN_INFO};
+use cw20_base::state::{MinterData, TokenInfo, MARKETING_INFO, TOKEN_INFO};
 
-use crate::curves::DecimalPlaces;
+use crate::basket::{
+    add_refund, basket_coins, basket_name, basket_refund, init_basket, may_pay_basket,
+    must_pay_basket, query_basket_info,
+};
+use crate::batch::{
+    execute_cancel_order, execute_settle_batch, init_batch, query_batch, query_orders, queue_buy,
+    queue_sell,
+};
+use crate::curves::{DecimalPlaces, MAX_DECIMALS};
 use crate::error::ContractError;
-use crate::msg::{CurveFn, CurveInfoResponse, ExecuteMsg, InstantiateMsg, QueryMsg};
-use crate::state::{CurveState, CURVE_STATE, CURVE_TYPE};
-use cw_utils::{must_pay, nonpayable};
+use crate::factory::{
+    execute_buy_hosted, execute_create_curve, query_hosted_curve, query_hosted_curves,
+    receive_hosted, reply_token_instantiated,
+};
+use crate::marketing::init_marketing;
+use crate::migrations::{migrate_state, CURRENT_STATE_VERSION};
+use crate::msg::{
+    CurveFn, CurveInfoResponse, CurveType, ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg,
+    ReceiveMsg,
+};
+use crate::oracle::{
+    init_price_accumulator, query_observations, query_twap, update_price_accumulator,
+};
+use crate::staking::{
+    execute_collect_rewards, execute_distribute_rewards, init_staking, query_staking,
+    rebalance_stake,
+};
+use crate::state::{
+    CurveState, ADMIN, BATCH, CURVE_STATE, CURVE_TYPE, FACTORY, STATE_VERSION, TREASURY,
+};
+use crate::vesting::{
+    execute_claim_vested, init_vesting, mint_purchase, query_vesting, query_vesting_schedules,
+};
+use cw_utils::nonpayable;
+use semver::Version;
 
 // version info for migration info
-const CONTRACT_NAME: &str = "crates.io:cw20-bonding";
+pub(crate) const CONTRACT_NAME: &str = "crates.io:cw20-bonding";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     msg: InstantiateMsg,
@@ -43,21 +75,92 @@ pub fn instantiate(
         total_supply: Uint128::zero(),
         // set self as minter, so we can properly execute mint and burn
         mint: Some(MinterData {
-            minter: env.contract.address,
+            minter: env.contract.address.clone(),
             cap: None,
         }),
     };
     TOKEN_INFO.save(deps.storage, &data)?;
 
-    let places = DecimalPlaces::new(msg.decimals, msg.reserve_decimals);
-    let supply = CurveState::new(msg.reserve_denom, places);
+    let places = validate_decimals(msg.decimals, msg.reserve_decimals)?;
+    let basket = init_basket(msg.reserve_denom, msg.basket)?;
+    let supply = CurveState::new(basket, places);
     CURVE_STATE.save(deps.storage, &supply)?;
+    STATE_VERSION.save(deps.storage, &CURRENT_STATE_VERSION)?;
 
     CURVE_TYPE.save(deps.storage, &msg.curve_type)?;
+    init_price_accumulator(deps.storage, env.block.time)?;
+    if let Some(batch) = msg.batch {
+        init_batch(deps.storage, &env.block, batch)?;
+    }
+    if let Some(vesting) = msg.vesting {
+        init_vesting(deps.branch(), vesting)?;
+    }
+
+    let admin = msg
+        .admin
+        .map(|admin| deps.api.addr_validate(&admin))
+        .transpose()?;
+    ADMIN.set(deps.branch(), admin.clone())?;
+    if let Some(treasury) = msg.treasury {
+        TREASURY.save(deps.storage, &deps.api.addr_validate(&treasury)?)?;
+    }
+    if let Some(staking) = msg.staking {
+        init_staking(deps.branch(), &supply.basket, staking)?;
+    }
+    if let Some(factory) = msg.factory {
+        FACTORY.save(deps.storage, &factory)?;
+    }
+    // the curve owner manages how the token is presented, unless someone else is set
+    let owner = admin.unwrap_or(info.sender);
+    init_marketing(deps, &env, msg.marketing, &owner)?;
 
     Ok(Response::default())
 }
 
+/// The curve math is only checked for up to `MAX_DECIMALS` decimal places
+pub fn validate_decimals(supply: u8, reserve: u8) -> Result<DecimalPlaces, ContractError> {
+    if supply > MAX_DECIMALS || reserve > MAX_DECIMALS {
+        return Err(ContractError::TooManyDecimals { max: MAX_DECIMALS });
+    }
+    Ok(DecimalPlaces::new(supply, reserve))
+}
+
+/// Only migrates from (older versions of) this contract. The stored state is converted to the
+/// current layout, see `migrations`.
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let stored = get_contract_version(deps.storage)?;
+    if stored.contract != CONTRACT_NAME {
+        return Err(ContractError::CannotMigrate {
+            previous_contract: stored.contract,
+        });
+    }
+    let parse = |version: &str| {
+        version
+            .parse::<Version>()
+            .map_err(|err| StdError::generic_err(err.to_string()))
+    };
+    if parse(&stored.version)? > parse(CONTRACT_VERSION)? {
+        return Err(ContractError::CannotDowngrade {
+            previous_version: stored.version,
+        });
+    }
+
+    // contracts that predate marketing info have no admin to manage it either
+    if MARKETING_INFO.may_load(deps.storage)?.is_none() {
+        MARKETING_INFO.save(deps.storage, &MarketingInfoResponse::default())?;
+    }
+    let from = migrate_state(deps.storage, &env)?;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    let res = Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("from_version", stored.version)
+        .add_attribute("from_state_version", from.to_string())
+        .add_attribute("state_version", CURRENT_STATE_VERSION.to_string());
+    Ok(res)
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
     deps: DepsMut,
@@ -84,12 +187,47 @@ pub fn do_execute(
 ) -> Result<Response, ContractError> {
     match msg {
         ExecuteMsg::Buy {} => execute_buy(deps, env, info, curve_fn),
+        ExecuteMsg::ClaimVested { limit } => execute_claim_vested(deps, env, info, limit),
+        ExecuteMsg::CollectRewards {} => execute_collect_rewards(deps, env, info),
+        ExecuteMsg::_DistributeRewards {} => execute_distribute_rewards(deps, env, info),
+        ExecuteMsg::CancelOrder { order_id } => execute_cancel_order(deps, env, info, order_id),
+        ExecuteMsg::SettleBatch { limit } => execute_settle_batch(deps, env, info, curve_fn, limit),
+        // this always uses the stored CurveType, as that is what it replaces
+        ExecuteMsg::CreateCurve {
+            name,
+            symbol,
+            decimals,
+            reserve_denom,
+            reserve_decimals,
+            curve_type,
+            marketing,
+        } => execute_create_curve(
+            deps,
+            env,
+            info,
+            name,
+            symbol,
+            decimals,
+            reserve_denom,
+            reserve_decimals,
+            curve_type,
+            marketing,
+        ),
+        ExecuteMsg::BuyHosted { curve_id } => execute_buy_hosted(deps, info, curve_id),
+        ExecuteMsg::UpdateCurve {
+            curve_type,
+            sweep_surplus,
+        } => execute_update_curve(deps, env, info, curve_fn, curve_type, sweep_surplus),
 
         // we override these from cw20
         ExecuteMsg::Burn { amount } => Ok(execute_sell(deps, env, info, curve_fn, amount)?),
         ExecuteMsg::BurnFrom { owner, amount } => {
             Ok(execute_sell_from(deps, env, info, curve_fn, owner, amount)?)
         }
+        ExecuteMsg::SellTo { amount, recipient } => {
+            execute_sell_to(deps, env, info, curve_fn, recipient, amount)
+        }
+        ExecuteMsg::Receive(msg) => execute_receive(deps, env, info, curve_fn, msg),
 
         // these all come from cw20-base to implement the cw20 standard
         ExecuteMsg::Transfer { recipient, amount } => {
@@ -129,6 +267,19 @@ pub fn do_execute(
         } => Ok(execute_send_from(
             deps, env, info, owner, contract, amount, msg,
         )?),
+        ExecuteMsg::UpdateMarketing {
+            project,
+            description,
+            marketing,
+        } => Ok(execute_update_marketing(
+            deps,
+            env,
+            info,
+            project,
+            description,
+            marketing,
+        )?),
+        ExecuteMsg::UploadLogo(logo) => Ok(execute_upload_logo(deps, env, info, logo)?),
     }
 }
 
@@ -140,32 +291,118 @@ pub fn execute_buy(
 ) -> Result<Response, ContractError> {
     let mut state = CURVE_STATE.load(deps.storage)?;
 
-    let payment = must_pay(&info, &state.reserve_denom)?;
+    // payment is in basket units, anything beyond whole units goes back to the buyer
+    let payment = must_pay_basket(&info, &state.basket)?;
+    let refund = basket_refund(&info, &state.basket, payment);
+
+    // in batch mode, this is only settled at the end of the epoch
+    if let Some(batch) = BATCH.may_load(deps.storage)? {
+        let res = queue_buy(deps, batch, info.sender.clone(), payment)?;
+        return Ok(add_refund(res, &info.sender, refund));
+    }
 
     // calculate how many tokens can be purchased with this and mint them
     let curve = curve_fn(state.decimals);
-    state.reserve += payment;
-    let new_supply = curve.supply(state.reserve);
+    update_price_accumulator(
+        deps.storage,
+        env.block.time,
+        curve.spot_price(state.supply)?,
+    )?;
+    let curve_reserve = curve.reserve(state.supply)?;
+    let new_supply = curve.supply(curve_reserve + state.to_curve(payment, curve_reserve))?;
     let minted = new_supply
         .checked_sub(state.supply)
         .map_err(StdError::overflow)?;
+    state.reserve += payment;
     state.supply = new_supply;
     CURVE_STATE.save(deps.storage, &state)?;
 
-    // call into cw20-base to mint the token, call as self as no one else is allowed
-    let sub_info = MessageInfo {
-        sender: env.contract.address.clone(),
-        funds: vec![],
-    };
-    execute_mint(deps, env, sub_info, info.sender.to_string(), minted)?;
-
     // bond them to the validator
+    let stake = rebalance_stake(deps.storage, &env, &state, Uint128::zero())?;
+    mint_purchase(deps, env, &info.sender, minted)?;
+
     let res = Response::new()
+        .add_messages(stake)
         .add_attribute("action", "buy")
-        .add_attribute("from", info.sender)
+        .add_attribute("from", &info.sender)
         .add_attribute("reserve", payment)
         .add_attribute("supply", minted);
-    Ok(res)
+    Ok(add_refund(res, &info.sender, refund))
+}
+
+pub fn execute_update_curve(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    curve_fn: CurveFn,
+    curve_type: CurveType,
+    sweep_surplus: bool,
+) -> Result<Response, ContractError> {
+    ADMIN.assert_admin(deps.as_ref(), &info.sender)?;
+
+    // the old price was valid up to now
+    let mut state = CURVE_STATE.load(deps.storage)?;
+    let old_price = curve_fn(state.decimals).spot_price(state.supply)?;
+    update_price_accumulator(deps.storage, env.block.time, old_price)?;
+
+    // figure out how much reserve the new curve needs to back the current supply
+    let curve = curve_type.to_curve_fn()(state.decimals);
+    let required = curve.reserve(state.supply)?;
+    // unless swept, any surplus stays in the reserve and raises the backing of every token
+    let new_reserve = if sweep_surplus {
+        required
+    } else {
+        required.max(state.reserve)
+    };
+
+    let mut res = Response::new()
+        .add_attribute("action", "update_curve")
+        .add_attribute("old_reserve", state.reserve)
+        .add_attribute("new_reserve", new_reserve);
+
+    if required <= state.reserve {
+        nonpayable(&info)?;
+        if sweep_surplus {
+            // the new curve frees up reserve, which goes to the treasury
+            let surplus = state.reserve - required;
+            let treasury = TREASURY
+                .may_load(deps.storage)?
+                .unwrap_or_else(|| info.sender.clone());
+            if !surplus.is_zero() {
+                res = res.add_message(BankMsg::Send {
+                    to_address: treasury.to_string(),
+                    amount: basket_coins(&state.basket, surplus),
+                });
+            }
+            res = res
+                .add_attribute("surplus", surplus)
+                .add_attribute("treasury", treasury);
+        }
+    } else {
+        // the new curve needs more backing, which the admin must deposit
+        let shortfall = required - state.reserve;
+        let sent = may_pay_basket(&info, &state.basket)?;
+        if sent < shortfall {
+            return Err(ContractError::InsufficientDeposit {
+                required: shortfall,
+                sent,
+                denom: basket_name(&state.basket),
+            });
+        }
+        res = res
+            .add_attribute("deposit", shortfall)
+            .add_attribute("refund", sent - shortfall);
+        let refund = basket_refund(&info, &state.basket, shortfall);
+        res = add_refund(res, &info.sender, refund);
+    }
+
+    let payout = state.reserve.saturating_sub(new_reserve);
+    state.reserve = new_reserve;
+    CURVE_STATE.save(deps.storage, &state)?;
+    CURVE_TYPE.save(deps.storage, &curve_type)?;
+
+    let stake = rebalance_stake(deps.storage, &env, &state, payout)?;
+    Ok(res.add_messages(stake))
 }
 
 pub fn execute_sell(
@@ -221,47 +458,143 @@ pub fn execute_sell_from(
     Ok(res)
 }
 
+pub fn execute_sell_to(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    curve_fn: CurveFn,
+    recipient: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    nonpayable(&info)?;
+    let receiver = deps.api.addr_validate(&recipient)?;
+    let mut res = do_sell(deps, env, info, curve_fn, receiver.clone(), amount)?;
+
+    res.attributes.push(attr("action", "sell_to"));
+    res.attributes.push(attr("recipient", receiver));
+    Ok(res)
+}
+
+pub fn execute_receive(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    curve_fn: CurveFn,
+    wrapper: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    nonpayable(&info)?;
+    // besides our own supply token, which cw20-base already moved to our account,
+    // we only accept the tokens of hosted curves
+    if info.sender != env.contract.address {
+        return receive_hosted(deps, info, wrapper);
+    }
+    let sender = deps.api.addr_validate(&wrapper.sender)?;
+
+    match from_binary(&wrapper.msg)? {
+        ReceiveMsg::Sell { recipient } => {
+            let receiver = match recipient {
+                Some(recipient) => deps.api.addr_validate(&recipient)?,
+                None => sender.clone(),
+            };
+            // in batch mode, the tokens stay in escrow, but the sender can cancel the order
+            if BATCH.may_load(deps.storage)?.is_some() {
+                let contract = env.contract.address.clone();
+                let mut res = queue_sell(
+                    deps,
+                    &env,
+                    &contract,
+                    sender.clone(),
+                    receiver.clone(),
+                    wrapper.amount,
+                )?;
+                res.attributes.push(attr("action", "receive_sell"));
+                res.attributes.push(attr("recipient", receiver));
+                return Ok(res);
+            }
+            // burn the tokens we were just sent from our own balance
+            let contract_info = MessageInfo {
+                sender: env.contract.address.clone(),
+                funds: vec![],
+            };
+            let mut res = do_sell(
+                deps,
+                env,
+                contract_info,
+                curve_fn,
+                receiver.clone(),
+                wrapper.amount,
+            )?;
+
+            res.attributes.push(attr("action", "receive_sell"));
+            res.attributes.push(attr("sender", sender));
+            res.attributes.push(attr("recipient", receiver));
+            Ok(res)
+        }
+    }
+}
+
 fn do_sell(
     mut deps: DepsMut,
     env: Env,
     // info.sender is the one burning tokens
     info: MessageInfo,
     curve_fn: CurveFn,
-    // receiver is the one who gains (same for execute_sell, diff for execute_sell_from)
+    // receiver is the one who gains (the seller for execute_sell, the spender for
+    // execute_sell_from, and whoever was named in execute_sell_to or execute_receive)
     receiver: Addr,
     amount: Uint128,
 ) -> Result<Response, ContractError> {
+    // in batch mode, we just escrow the tokens until the end of the epoch
+    if BATCH.may_load(deps.storage)?.is_some() {
+        let seller = info.sender;
+        return queue_sell(deps, &env, &seller, seller.clone(), receiver, amount);
+    }
+
     // burn from the caller, this ensures there are tokens to cover this
-    execute_burn(deps.branch(), env, info.clone(), amount)?;
+    execute_burn(deps.branch(), env.clone(), info.clone(), amount)?;
 
     // calculate how many tokens can be purchased with this and mint them
     let mut state = CURVE_STATE.load(deps.storage)?;
     let curve = curve_fn(state.decimals);
+    update_price_accumulator(
+        deps.storage,
+        env.block.time,
+        curve.spot_price(state.supply)?,
+    )?;
+    let curve_reserve = curve.reserve(state.supply)?;
     state.supply = state
         .supply
         .checked_sub(amount)
         .map_err(StdError::overflow)?;
-    let new_reserve = curve.reserve(state.supply);
-    let released = state
+    let curve_released = curve_reserve - curve.reserve(state.supply)?;
+    let released = state.from_curve(curve_released, curve_reserve);
+    state.reserve = state
         .reserve
-        .checked_sub(new_reserve)
+        .checked_sub(released)
         .map_err(StdError::overflow)?;
-    state.reserve = new_reserve;
     CURVE_STATE.save(deps.storage, &state)?;
+    let stake = rebalance_stake(deps.storage, &env, &state, released)?;
 
-    // now send the tokens to the sender (TODO: for sell_from we do something else, right???)
+    // now send the released reserve to the receiver
     let msg = BankMsg::Send {
         to_address: receiver.to_string(),
-        amount: coins(released.u128(), state.reserve_denom),
+        amount: basket_coins(&state.basket, released),
     };
     let res = Response::new()
         .add_message(msg)
+        .add_messages(stake)
         .add_attribute("from", info.sender)
         .add_attribute("supply", amount)
         .add_attribute("reserve", released);
     Ok(res)
 }
 
+/// The only submessages we reply to are the ones instantiating the tokens of hosted curves
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+    reply_token_instantiated(deps, msg)
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     // default implementation stores curve info as enum, you can do something else in a derived
@@ -274,16 +607,38 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
 /// We pull out logic here, so we can import this from another contract and set a different Curve.
 /// This contacts sets a curve with an enum in InstantitateMsg and stored in state, but you may want
 /// to use custom math not included - make this easily reusable
-pub fn do_query(deps: Deps, _env: Env, msg: QueryMsg, curve_fn: CurveFn) -> StdResult<Binary> {
+pub fn do_query(deps: Deps, env: Env, msg: QueryMsg, curve_fn: CurveFn) -> StdResult<Binary> {
     match msg {
         // custom queries
         QueryMsg::CurveInfo {} => to_binary(&query_curve_info(deps, curve_fn)?),
+        QueryMsg::Twap { window_seconds } => {
+            to_binary(&query_twap(deps, env, curve_fn, window_seconds)?)
+        }
+        QueryMsg::Observations {} => to_binary(&query_observations(deps)?),
+        QueryMsg::Vesting { address } => to_binary(&query_vesting(deps, env, address)?),
+        QueryMsg::VestingSchedules {
+            address,
+            start_after,
+            limit,
+        } => to_binary(&query_vesting_schedules(deps, address, start_after, limit)?),
+        QueryMsg::BasketInfo {} => to_binary(&query_basket_info(deps)?),
+        QueryMsg::Staking {} => to_binary(&query_staking(deps, env)?),
+        QueryMsg::Batch {} => to_binary(&query_batch(deps)?),
+        QueryMsg::Orders { start_after, limit } => {
+            to_binary(&query_orders(deps, start_after, limit)?)
+        }
+        QueryMsg::HostedCurve { curve_id } => to_binary(&query_hosted_curve(deps, curve_id)?),
+        QueryMsg::HostedCurves { start_after, limit } => {
+            to_binary(&query_hosted_curves(deps, start_after, limit)?)
+        }
         // inherited from cw20-base
         QueryMsg::TokenInfo {} => to_binary(&query_token_info(deps)?),
         QueryMsg::Balance { address } => to_binary(&query_balance(deps, address)?),
         QueryMsg::Allowance { owner, spender } => {
             to_binary(&query_allowance(deps, owner, spender)?)
         }
+        QueryMsg::MarketingInfo {} => to_binary(&query_marketing_info(deps)?),
+        QueryMsg::DownloadLogo {} => to_binary(&query_download_logo(deps)?),
     }
 }
 
@@ -291,19 +646,20 @@ pub fn query_curve_info(deps: Deps, curve_fn: CurveFn) -> StdResult<CurveInfoRes
     let CurveState {
         reserve,
         supply,
-        reserve_denom,
+        basket,
         decimals,
+        ..
     } = CURVE_STATE.load(deps.storage)?;
 
     // This we can get from the local digits stored in instantiate
     let curve = curve_fn(decimals);
-    let spot_price = curve.spot_price(supply);
+    let spot_price = curve.spot_price(supply)?;
 
     Ok(CurveInfoResponse {
         reserve,
         supply,
         spot_price,
-        reserve_denom,
+        reserve_denom: basket[0].denom.clone(),
     })
 }
 
@@ -312,8 +668,12 @@ pub fn query_curve_info(deps: Deps, curve_fn: CurveFn) -> StdResult<CurveInfoRes
 mod tests {
     use super::*;
     use crate::msg::CurveType;
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::{coin, Decimal, OverflowError, OverflowOperation, StdError, SubMsg};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MOCK_CONTRACT_ADDR};
+    use cosmwasm_std::{
+        coin, coins, CosmosMsg, Decimal, OverflowError, OverflowOperation, StdError, SubMsg,
+        WasmMsg,
+    };
+    use cw_controllers::AdminError;
     use cw_utils::PaymentError;
 
     const DENOM: &str = "satoshi";
@@ -332,7 +692,15 @@ mod tests {
             decimals,
             reserve_denom: DENOM.to_string(),
             reserve_decimals,
+            basket: None,
             curve_type,
+            admin: Some(CREATOR.to_string()),
+            treasury: None,
+            batch: None,
+            vesting: None,
+            staking: None,
+            factory: None,
+            marketing: None,
         }
     }
 
@@ -391,6 +759,23 @@ mod tests {
         assert_eq!(get_balance(deps.as_ref(), &creator), Uint128::zero());
     }
 
+    #[test]
+    fn rejects_too_many_decimals() {
+        let mut deps = mock_dependencies();
+        let curve_type = CurveType::Constant {
+            value: Uint128::new(1),
+            scale: 0,
+        };
+        let info = mock_info(CREATOR, &[]);
+        for (decimals, reserve_decimals) in [(19, 6), (6, 19), (200, 200)] {
+            let msg = default_instantiate(decimals, reserve_decimals, curve_type.clone());
+            let err = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+            assert_eq!(err, ContractError::TooManyDecimals { max: 18 });
+        }
+        let msg = default_instantiate(18, 18, curve_type);
+        instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+    }
+
     #[test]
     fn buy_issues_tokens() {
         let mut deps = mock_dependencies();
@@ -633,4 +1018,288 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn sell_to_pays_recipient() {
+        let mut deps = mock_dependencies();
+        let curve_type = CurveType::Linear {
+            slope: Uint128::new(1),
+            scale: 1,
+        };
+        setup_test(deps.as_mut(), 2, 8, curve_type);
+
+        let info = mock_info(INVESTOR, &coins(2_000_000_000, DENOM));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Buy {}).unwrap();
+
+        // sell 1000 EPOXY, but send the 15 BTC to the buyer
+        let info = mock_info(INVESTOR, &[]);
+        let sell = ExecuteMsg::SellTo {
+            amount: Uint128::new(1000),
+            recipient: BUYER.into(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, sell).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: BUYER.into(),
+                amount: coins(1_500_000_000, DENOM),
+            })]
+        );
+
+        // only the seller's balance went down
+        assert_eq!(get_balance(deps.as_ref(), INVESTOR), Uint128::new(1000));
+        assert_eq!(get_balance(deps.as_ref(), BUYER), Uint128::zero());
+        let token = query_token_info(deps.as_ref()).unwrap();
+        assert_eq!(token.total_supply, Uint128::new(1000));
+    }
+
+    #[test]
+    fn send_to_contract_sells() {
+        let mut deps = mock_dependencies();
+        let curve_type = CurveType::Linear {
+            slope: Uint128::new(1),
+            scale: 1,
+        };
+        setup_test(deps.as_mut(), 2, 8, curve_type.clone());
+
+        let info = mock_info(INVESTOR, &coins(2_000_000_000, DENOM));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Buy {}).unwrap();
+
+        // send 1000 EPOXY to the bonding contract, selling to the buyer
+        let sell_msg = to_binary(&ReceiveMsg::Sell {
+            recipient: Some(BUYER.into()),
+        })
+        .unwrap();
+        let info = mock_info(INVESTOR, &[]);
+        let send = ExecuteMsg::Send {
+            contract: MOCK_CONTRACT_ADDR.into(),
+            amount: Uint128::new(1000),
+            msg: sell_msg.clone(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, send).unwrap();
+        assert_eq!(get_balance(deps.as_ref(), INVESTOR), Uint128::new(1000));
+        assert_eq!(
+            get_balance(deps.as_ref(), MOCK_CONTRACT_ADDR),
+            Uint128::new(1000)
+        );
+
+        // cw20-base calls back into us with the receive hook
+        let receive = match &res.messages[0].msg {
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr, msg, ..
+            }) => {
+                assert_eq!(contract_addr, MOCK_CONTRACT_ADDR);
+                from_binary::<ExecuteMsg>(msg).unwrap()
+            }
+            msg => panic!("unexpected message: {:?}", msg),
+        };
+        assert_eq!(
+            receive,
+            ExecuteMsg::Receive(Cw20ReceiveMsg {
+                sender: INVESTOR.into(),
+                amount: Uint128::new(1000),
+                msg: sell_msg.clone(),
+            })
+        );
+
+        // no one else can call the receive hook
+        let info = mock_info(INVESTOR, &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, receive.clone()).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+
+        // the hook burns the tokens and pays the buyer
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, receive).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: BUYER.into(),
+                amount: coins(1_500_000_000, DENOM),
+            })]
+        );
+        assert_eq!(
+            get_balance(deps.as_ref(), MOCK_CONTRACT_ADDR),
+            Uint128::zero()
+        );
+        let curve = query_curve_info(deps.as_ref(), curve_type.to_curve_fn()).unwrap();
+        assert_eq!(curve.reserve, Uint128::new(500_000_000));
+        assert_eq!(curve.supply, Uint128::new(1000));
+
+        // without a recipient, the reserve goes back to the one who sent the tokens
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        let receive = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: INVESTOR.into(),
+            amount: Uint128::new(1000),
+            msg: to_binary(&ReceiveMsg::Sell { recipient: None }).unwrap(),
+        });
+        // but we can only sell what we received
+        let err = execute(deps.as_mut(), mock_env(), info, receive).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::Base(cw20_base::ContractError::Std(StdError::overflow(
+                OverflowError::new(OverflowOperation::Sub, 0, 1000)
+            )))
+        );
+    }
+
+    #[test]
+    fn update_curve_rebalances_reserve() {
+        let mut deps = mock_dependencies();
+        let curve_type = CurveType::Linear {
+            slope: Uint128::new(1),
+            scale: 1,
+        };
+        let mut msg = default_instantiate(2, 8, curve_type);
+        msg.treasury = Some("treasury".to_string());
+        instantiate(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap();
+
+        // buy 2000 EPOXY for 20 BTC
+        let info = mock_info(INVESTOR, &coins(2_000_000_000, DENOM));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Buy {}).unwrap();
+
+        // only the admin can update the curve
+        let flatter = ExecuteMsg::UpdateCurve {
+            curve_type: CurveType::Linear {
+                slope: Uint128::new(5),
+                scale: 2,
+            },
+            sweep_surplus: true,
+        };
+        let info = mock_info(INVESTOR, &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, flatter.clone()).unwrap_err();
+        assert_eq!(err, ContractError::Admin(AdminError::NotAdmin {}));
+
+        // halving the slope frees half the reserve, which is swept to the treasury
+        let info = mock_info(CREATOR, &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, flatter).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: "treasury".into(),
+                amount: coins(1_000_000_000, DENOM),
+            })]
+        );
+        assert!(res.attributes.contains(&attr("surplus", "1000000000")));
+        let curve = query_curve_info(
+            deps.as_ref(),
+            CURVE_TYPE.load(&deps.storage).unwrap().to_curve_fn(),
+        )
+        .unwrap();
+        assert_eq!(curve.reserve, Uint128::new(1_000_000_000));
+        assert_eq!(curve.supply, Uint128::new(2000));
+        assert_eq!(curve.spot_price, Decimal::percent(100));
+
+        // going back to the old slope requires a deposit of the shortfall
+        let steeper = ExecuteMsg::UpdateCurve {
+            curve_type: CurveType::Linear {
+                slope: Uint128::new(1),
+                scale: 1,
+            },
+            sweep_surplus: false,
+        };
+        let info = mock_info(CREATOR, &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, steeper.clone()).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InsufficientDeposit {
+                required: Uint128::new(1_000_000_000),
+                sent: Uint128::zero(),
+                denom: DENOM.into(),
+            }
+        );
+
+        // anything above the shortfall is refunded
+        let info = mock_info(CREATOR, &coins(1_200_000_000, DENOM));
+        let res = execute(deps.as_mut(), mock_env(), info, steeper).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: CREATOR.into(),
+                amount: coins(200_000_000, DENOM),
+            })]
+        );
+        assert!(res.attributes.contains(&attr("deposit", "1000000000")));
+
+        // selling now releases reserve along the restored curve
+        let info = mock_info(INVESTOR, &[]);
+        let burn = ExecuteMsg::Burn {
+            amount: Uint128::new(1000),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, burn).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: INVESTOR.into(),
+                amount: coins(1_500_000_000, DENOM),
+            })]
+        );
+    }
+
+    #[test]
+    fn update_curve_keeps_surplus_unless_swept() {
+        let mut deps = mock_dependencies();
+        let curve_type = CurveType::Linear {
+            slope: Uint128::new(1),
+            scale: 1,
+        };
+        let mut msg = default_instantiate(2, 8, curve_type);
+        msg.treasury = Some("treasury".to_string());
+        instantiate(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap();
+
+        // buy 2000 EPOXY for 20 BTC
+        let info = mock_info(INVESTOR, &coins(2_000_000_000, DENOM));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Buy {}).unwrap();
+
+        // halving the slope leaves the freed up reserve in the contract
+        let flatter = ExecuteMsg::UpdateCurve {
+            curve_type: CurveType::Linear {
+                slope: Uint128::new(5),
+                scale: 2,
+            },
+            sweep_surplus: false,
+        };
+        let info = mock_info(CREATOR, &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, flatter).unwrap();
+        assert_eq!(res.messages, vec![]);
+        assert!(res.attributes.contains(&attr("new_reserve", "2000000000")));
+        let state = CURVE_STATE.load(&deps.storage).unwrap();
+        assert_eq!(state.reserve, Uint128::new(2_000_000_000));
+
+        // so holders still sell at the old backing
+        let info = mock_info(INVESTOR, &[]);
+        let burn = ExecuteMsg::Burn {
+            amount: Uint128::new(1000),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, burn).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: INVESTOR.into(),
+                amount: coins(1_500_000_000, DENOM),
+            })]
+        );
+    }
+
+    #[test]
+    fn curve_without_admin_is_fixed() {
+        let mut deps = mock_dependencies();
+        let curve_type = CurveType::Constant {
+            value: Uint128::new(15),
+            scale: 1,
+        };
+        let mut msg = default_instantiate(9, 6, curve_type.clone());
+        msg.admin = None;
+        instantiate(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap();
+
+        let update = ExecuteMsg::UpdateCurve {
+            curve_type: CurveType::Constant {
+                value: Uint128::new(1),
+                scale: 1,
+            },
+            sweep_surplus: true,
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), update).unwrap_err();
+        assert_eq!(err, ContractError::Admin(AdminError::NotAdmin {}));
+        assert_eq!(CURVE_TYPE.load(&deps.storage).unwrap(), curve_type);
+    }
 }
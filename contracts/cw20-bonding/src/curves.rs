@@ -1,12 +1,11 @@
-use integer_cbrt::IntegerCubeRoot;
-use integer_sqrt::IntegerSquareRoot;
-use rust_decimal::prelude::ToPrimitive;
-use rust_decimal::Decimal;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::str::FromStr;
+use std::convert::TryFrom;
 
-use cosmwasm_std::{Decimal as StdDecimal, Uint128};
+use cosmwasm_std::{Decimal as StdDecimal, Decimal256, StdError, StdResult, Uint128, Uint512};
+
+#[cfg(test)]
+mod legacy;
 
 /// This defines the curves we are using.
 ///
@@ -20,186 +19,370 @@ use cosmwasm_std::{Decimal as StdDecimal, Uint128};
 /// when you want to do more complex math than add and multiply `Uint128`, I decided to go the second
 /// route. That made the signatures quite complex and my final idea was to pass in `supply_decimal`
 /// and `reserve_decimal` in the curve constructors.
+///
+/// All math is done on integers: every formula is evaluated as one exact fraction in `Uint512`,
+/// and only the final division (or root) rounds. Rounding always favours the contract.
+/// `Uint256` is not enough, as the square root curve's reserve squares it first:
+/// `4 * slope^2 * supply^3` takes up to 384 bits for the supply alone.
+///
+/// Results that don't fit into a `Uint128` (or a `Decimal`) return a `ConversionOverflow` error.
 pub trait Curve {
-    /// Returns the spot price given the supply.
+    /// Returns the spot price given the supply (rounded down).
     /// `f(x)` from the README
-    fn spot_price(&self, supply: Uint128) -> StdDecimal;
+    fn spot_price(&self, supply: Uint128) -> StdResult<StdDecimal>;
 
-    /// Returns the total price paid up to purchase supply tokens (integral)
+    /// Returns the total price paid up to purchase supply tokens (integral).
+    /// This is what we charge for the supply, so it rounds up.
     /// `F(x)` from the README
-    fn reserve(&self, supply: Uint128) -> Uint128;
+    fn reserve(&self, supply: Uint128) -> StdResult<Uint128>;
 
     /// Inverse of reserve. Returns how many tokens would be issued
-    /// with a total paid amount of reserve.
+    /// with a total paid amount of reserve. This is what we mint, so it rounds down.
     /// `F^-1(x)` from the README
-    fn supply(&self, reserve: Uint128) -> Uint128;
+    fn supply(&self, reserve: Uint128) -> StdResult<Uint128>;
 }
 
 /// decimal returns an object = num * 10 ^ -scale
 /// We use this function in contract.rs rather than call the crate constructor
 /// itself, in case we want to swap out the implementation, we can do it only in this file.
-pub fn decimal<T: Into<u128>>(num: T, scale: u32) -> Decimal {
-    Decimal::from_i128_with_scale(num.into() as i128, scale)
+/// Anything beyond 18 decimal places is cut off.
+pub fn decimal<T: Into<u128>>(num: T, scale: u32) -> Decimal256 {
+    Decimal256::from_atomics(num.into(), scale).unwrap()
+}
+
+/// A curve parameter as the fraction `num * 10^-scale`, with trailing zeros removed from `num`,
+/// which keeps the intermediate products small
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Param {
+    num: Uint512,
+    scale: i32,
+}
+
+impl Param {
+    fn new(value: Decimal256) -> Self {
+        let mut scale = Decimal256::DECIMAL_PLACES as i32;
+        // slopes are usually small, so we try to strip the zeros on a u128 first
+        let num = match Uint128::try_from(value.atomics()) {
+            Ok(atomics) => {
+                let mut num = atomics.u128();
+                while scale > 0 && num != 0 && num % 10 == 0 {
+                    num /= 10;
+                    scale -= 1;
+                }
+                Uint512::from(num)
+            }
+            Err(_) => {
+                let ten = Uint512::from(10u8);
+                let mut num = Uint512::from(value.atomics());
+                while scale > 0 && (num % ten).is_zero() {
+                    num /= ten;
+                    scale -= 1;
+                }
+                num
+            }
+        };
+        Param { num, scale }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Rounding {
+    Down,
+    Up,
+}
+
+fn pow10(exp: u32) -> StdResult<Uint512> {
+    match 10u128.checked_pow(exp) {
+        Some(pow) => Ok(Uint512::from(pow)),
+        None => Uint512::from(10u8)
+            .checked_pow(exp)
+            .map_err(StdError::overflow),
+    }
+}
+
+/// `2^exp`, as `Uint512` cannot shift left
+fn pow2(exp: u32) -> Uint512 {
+    let mut bytes = [0u8; 64];
+    bytes[63 - exp as usize / 8] = 1 << (exp % 8);
+    Uint512::from_be_bytes(bytes)
+}
+
+/// Multiplies all factors, which may overflow for inputs far beyond the supported range
+fn product(factors: &[Uint512]) -> StdResult<Uint512> {
+    factors
+        .iter()
+        .try_fold(Uint512::one(), |acc, factor| Ok(acc.checked_mul(*factor)?))
+}
+
+/// Returns `num * 10^exp / den`, rounded as requested
+fn mul_div(num: Uint512, exp: i32, den: Uint512, rounding: Rounding) -> StdResult<Uint512> {
+    let (num, den) = if exp >= 0 {
+        (num.checked_mul(pow10(exp as u32)?)?, den)
+    } else {
+        (num, den.checked_mul(pow10(exp.unsigned_abs())?)?)
+    };
+    let quotient = num.checked_div(den)?;
+    Ok(match rounding {
+        Rounding::Up if !num.checked_rem(den)?.is_zero() => quotient + Uint512::one(),
+        _ => quotient,
+    })
+}
+
+/// Number of significant bits
+fn bits(x: Uint512) -> u32 {
+    let bytes = x.to_be_bytes();
+    match bytes.iter().position(|b| *b != 0) {
+        Some(i) => (64 - i as u32) * 8 - bytes[i].leading_zeros(),
+        None => 0,
+    }
+}
+
+/// Integer square root using Newton's method. The top 126 bits are first rooted on a `u128`,
+/// which is exact for small values, and otherwise a starting point just above the root,
+/// so only a few steps are left on `Uint512`.
+fn square_root(x: Uint512, rounding: Rounding) -> Uint512 {
+    if x.is_zero() {
+        return x;
+    }
+    let shift = bits(x).saturating_sub(126).div_ceil(2);
+    let top = Uint128::try_from(x >> (2 * shift)).unwrap().u128();
+    let mut root = Uint512::from(u128_root(top, 2));
+    if shift > 0 {
+        root = (root + Uint512::one()) * pow2(shift);
+        loop {
+            let next = (root + x / root) >> 1;
+            if next >= root {
+                break;
+            }
+            root = next;
+        }
+    }
+    match rounding {
+        Rounding::Up if root * root < x => root + Uint512::one(),
+        _ => root,
+    }
+}
+
+/// Integer cube root using Newton's method, starting like `square_root`
+fn cube_root(x: Uint512, rounding: Rounding) -> Uint512 {
+    if x.is_zero() {
+        return x;
+    }
+    let shift = bits(x).saturating_sub(126).div_ceil(3);
+    let top = Uint128::try_from(x >> (3 * shift)).unwrap().u128();
+    let mut root = Uint512::from(u128_root(top, 3));
+    if shift > 0 {
+        root = (root + Uint512::one()) * pow2(shift);
+        loop {
+            let next = (root + root + x / (root * root)) / Uint512::from(3u8);
+            if next >= root {
+                break;
+            }
+            root = next;
+        }
+    }
+    match rounding {
+        Rounding::Up if root * root * root < x => root + Uint512::one(),
+        _ => root,
+    }
+}
+
+/// Square (`n = 2`) or cube (`n = 3`) root of at most 126 bits, rounded down
+fn u128_root(x: u128, n: u32) -> u128 {
+    if x == 0 {
+        return 0;
+    }
+    let bits = 128 - x.leading_zeros();
+    let mut root = 1u128 << bits.div_ceil(n);
+    loop {
+        let next = ((n as u128 - 1) * root + x / root.pow(n - 1)) / n as u128;
+        if next >= root {
+            return root;
+        }
+        root = next;
+    }
+}
+
+fn to_uint128(x: Uint512) -> StdResult<Uint128> {
+    Ok(Uint128::try_from(x)?)
 }
 
-/// StdDecimal stores as a u128 with 18 decimal points of precision
-fn decimal_to_std(x: Decimal) -> StdDecimal {
-    // this seems straight-forward (if inefficient), converting via string representation
-    // TODO: execute errors better? Result?
-    StdDecimal::from_str(&x.to_string()).unwrap()
-
-    // // maybe a better approach doing math, not sure about rounding
-    //
-    // // try to preserve decimal points, max 9
-    // let digits = min(x.scale(), 9);
-    // let multiplier = 10u128.pow(digits);
-    //
-    // // we multiply up before we round off to u128,
-    // // let StdDecimal do its best to keep these decimal places
-    // let nominator = (x * decimal(multiplier, 0)).to_u128().unwrap();
-    // StdDecimal::from_ratio(nominator, multiplier)
+/// Spot prices are reported as `Decimal`, which stores atomics with 18 decimal places
+fn to_std_decimal(atomics: Uint512) -> StdResult<StdDecimal> {
+    Ok(StdDecimal::new(to_uint128(atomics)?))
 }
 
 /// spot price is always a constant value
 pub struct Constant {
-    pub value: Decimal,
+    pub value: Decimal256,
     pub normalize: DecimalPlaces,
+    param: Param,
 }
 
 impl Constant {
-    pub fn new(value: Decimal, normalize: DecimalPlaces) -> Self {
-        Self { value, normalize }
+    pub fn new(value: Decimal256, normalize: DecimalPlaces) -> Self {
+        Self {
+            value,
+            normalize,
+            param: Param::new(value),
+        }
     }
 }
 
 impl Curve for Constant {
     // we need to normalize value with the reserve decimal places
     // (eg 0.1 value would return 100_000 if reserve was uatom)
-    fn spot_price(&self, _supply: Uint128) -> StdDecimal {
+    fn spot_price(&self, _supply: Uint128) -> StdResult<StdDecimal> {
         // f(x) = self.value
-        decimal_to_std(self.value)
+        let Param { num, scale } = self.param;
+        to_std_decimal(mul_div(num, 18 - scale, Uint512::one(), Rounding::Down)?)
     }
 
     /// Returns total number of reserve tokens needed to purchase a given number of supply tokens.
     /// Note that both need to be normalized.
-    fn reserve(&self, supply: Uint128) -> Uint128 {
+    fn reserve(&self, supply: Uint128) -> StdResult<Uint128> {
         // f(x) = supply * self.value
-        let reserve = self.normalize.from_supply(supply) * self.value;
-        self.normalize.to_reserve(reserve)
+        let Param { num, scale } = self.param;
+        let (s, r) = self.normalize.exponents();
+        let reserve = mul_div(
+            product(&[num, supply.into()])?,
+            r - scale - s,
+            Uint512::one(),
+            Rounding::Up,
+        )?;
+        to_uint128(reserve)
     }
 
-    fn supply(&self, reserve: Uint128) -> Uint128 {
+    fn supply(&self, reserve: Uint128) -> StdResult<Uint128> {
         // f(x) = reserve / self.value
-        let supply = self.normalize.from_reserve(reserve) / self.value;
-        self.normalize.to_supply(supply)
+        let Param { num, scale } = self.param;
+        let (s, r) = self.normalize.exponents();
+        let supply = mul_div(Uint512::from(reserve), scale + s - r, num, Rounding::Down)?;
+        to_uint128(supply)
     }
 }
 
 /// spot_price is slope * supply
 pub struct Linear {
-    pub slope: Decimal,
+    pub slope: Decimal256,
     pub normalize: DecimalPlaces,
+    param: Param,
 }
 
 impl Linear {
-    pub fn new(slope: Decimal, normalize: DecimalPlaces) -> Self {
-        Self { slope, normalize }
+    pub fn new(slope: Decimal256, normalize: DecimalPlaces) -> Self {
+        Self {
+            slope,
+            normalize,
+            param: Param::new(slope),
+        }
     }
 }
 
 impl Curve for Linear {
-    fn spot_price(&self, supply: Uint128) -> StdDecimal {
+    fn spot_price(&self, supply: Uint128) -> StdResult<StdDecimal> {
         // f(x) = supply * self.value
-        let out = self.normalize.from_supply(supply) * self.slope;
-        decimal_to_std(out)
+        let Param { num, scale } = self.param;
+        let (s, _) = self.normalize.exponents();
+        let price = mul_div(
+            product(&[num, supply.into()])?,
+            18 - scale - s,
+            Uint512::one(),
+            Rounding::Down,
+        )?;
+        to_std_decimal(price)
     }
 
-    fn reserve(&self, supply: Uint128) -> Uint128 {
+    fn reserve(&self, supply: Uint128) -> StdResult<Uint128> {
         // f(x) = self.slope * supply * supply / 2
-        let normalized = self.normalize.from_supply(supply);
-        let square = normalized * normalized;
-        // Note: multiplying by 0.5 is much faster than dividing by 2
-        let reserve = square * self.slope * Decimal::new(5, 1);
-        self.normalize.to_reserve(reserve)
+        let Param { num, scale } = self.param;
+        let (s, r) = self.normalize.exponents();
+        let supply = Uint512::from(supply);
+        let reserve = mul_div(
+            product(&[num, supply, supply])?,
+            r - scale - 2 * s,
+            Uint512::from(2u8),
+            Rounding::Up,
+        )?;
+        to_uint128(reserve)
     }
 
-    fn supply(&self, reserve: Uint128) -> Uint128 {
+    fn supply(&self, reserve: Uint128) -> StdResult<Uint128> {
         // f(x) = (2 * reserve / self.slope) ^ 0.5
-        // note: use addition here to optimize 2* operation
-        let square = self.normalize.from_reserve(reserve + reserve) / self.slope;
-        let supply = square_root(square);
-        self.normalize.to_supply(supply)
+        // the floor of the root of the floored square is the floor of the exact root
+        let Param { num, scale } = self.param;
+        let (s, r) = self.normalize.exponents();
+        let reserve = Uint512::from(reserve);
+        let square = mul_div(reserve + reserve, scale + 2 * s - r, num, Rounding::Down)?;
+        to_uint128(square_root(square, Rounding::Down))
     }
 }
 
 /// spot_price is slope * (supply)^0.5
+///
+/// The intermediate results are squared (or cubed) slopes and supplies, so this only covers
+/// the full `Uint128` range for slopes with up to 18 significant digits.
 pub struct SquareRoot {
-    pub slope: Decimal,
+    pub slope: Decimal256,
     pub normalize: DecimalPlaces,
+    param: Param,
 }
 
 impl SquareRoot {
-    pub fn new(slope: Decimal, normalize: DecimalPlaces) -> Self {
-        Self { slope, normalize }
+    pub fn new(slope: Decimal256, normalize: DecimalPlaces) -> Self {
+        Self {
+            slope,
+            normalize,
+            param: Param::new(slope),
+        }
     }
 }
 
 impl Curve for SquareRoot {
-    fn spot_price(&self, supply: Uint128) -> StdDecimal {
-        // f(x) = self.slope * supply^0.5
-        let square = self.normalize.from_supply(supply);
-        let root = square_root(square);
-        decimal_to_std(root * self.slope)
+    fn spot_price(&self, supply: Uint128) -> StdResult<StdDecimal> {
+        // f(x) = self.slope * supply^0.5 = (self.slope^2 * supply)^0.5
+        let Param { num, scale } = self.param;
+        let (s, _) = self.normalize.exponents();
+        let square = mul_div(
+            product(&[num, num, supply.into()])?,
+            36 - 2 * scale - s,
+            Uint512::one(),
+            Rounding::Down,
+        )?;
+        to_std_decimal(square_root(square, Rounding::Down))
     }
 
-    fn reserve(&self, supply: Uint128) -> Uint128 {
-        // f(x) = self.slope * supply * supply^0.5 / 1.5
-        let normalized = self.normalize.from_supply(supply);
-        let root = square_root(normalized);
-        let reserve = self.slope * normalized * root / Decimal::new(15, 1);
-        self.normalize.to_reserve(reserve)
+    fn reserve(&self, supply: Uint128) -> StdResult<Uint128> {
+        // f(x) = self.slope * supply * supply^0.5 / 1.5 = (4 * self.slope^2 * supply^3 / 9)^0.5
+        let Param { num, scale } = self.param;
+        let (s, r) = self.normalize.exponents();
+        let supply = Uint512::from(supply);
+        let square = mul_div(
+            product(&[Uint512::from(4u8), num, num, supply, supply, supply])?,
+            2 * r - 2 * scale - 3 * s,
+            Uint512::from(9u8),
+            Rounding::Up,
+        )?;
+        to_uint128(square_root(square, Rounding::Up))
     }
 
-    fn supply(&self, reserve: Uint128) -> Uint128 {
-        // f(x) = (1.5 * reserve / self.slope) ^ (2/3)
-        let base = self.normalize.from_reserve(reserve) * Decimal::new(15, 1) / self.slope;
-        let squared = base * base;
-        let supply = cube_root(squared);
-        self.normalize.to_supply(supply)
+    fn supply(&self, reserve: Uint128) -> StdResult<Uint128> {
+        // f(x) = (1.5 * reserve / self.slope) ^ (2/3) = (9 * reserve^2 / (4 * self.slope^2)) ^ (1/3)
+        let Param { num, scale } = self.param;
+        let (s, r) = self.normalize.exponents();
+        let reserve = Uint512::from(reserve);
+        let cube = mul_div(
+            Uint512::from(9u8) * reserve * reserve,
+            2 * scale + 3 * s - 2 * r,
+            product(&[Uint512::from(4u8), num, num])?,
+            Rounding::Down,
+        )?;
+        to_uint128(cube_root(cube, Rounding::Down))
     }
 }
 
-// we multiply by 10^18, turn to int, take square root, then divide by 10^9 as we convert back to decimal
-fn square_root(square: Decimal) -> Decimal {
-    // must be even
-    // TODO: this can overflow easily at 18... what is a good value?
-    const EXTRA_DIGITS: u32 = 12;
-    let multiplier = 10u128.saturating_pow(EXTRA_DIGITS);
-
-    // multiply by 10^18 and turn to u128
-    let extended = square * decimal(multiplier, 0);
-    let extended = extended.floor().to_u128().unwrap();
-
-    // take square root, and build a decimal again
-    let root = extended.integer_sqrt();
-    decimal(root, EXTRA_DIGITS / 2)
-}
-
-// we multiply by 10^9, turn to int, take cube root, then divide by 10^3 as we convert back to decimal
-fn cube_root(cube: Decimal) -> Decimal {
-    // must be multiple of 3
-    // TODO: what is a good value?
-    const EXTRA_DIGITS: u32 = 9;
-    let multiplier = 10u128.saturating_pow(EXTRA_DIGITS);
-
-    // multiply out and turn to u128
-    let extended = cube * decimal(multiplier, 0);
-    let extended = extended.floor().to_u128().unwrap();
-
-    // take cube root, and build a decimal again
-    let root = extended.integer_cbrt();
-    decimal(root, EXTRA_DIGITS / 3)
-}
+/// The most decimal places the contract takes for supply and reserve tokens
+pub const MAX_DECIMALS: u8 = 18;
 
 /// DecimalPlaces should be passed into curve constructors
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema, Default)]
@@ -218,32 +401,40 @@ impl DecimalPlaces {
         }
     }
 
-    pub fn to_reserve(self, reserve: Decimal) -> Uint128 {
-        let factor = decimal(10u128.pow(self.reserve), 0);
-        let out = reserve * factor;
-        // TODO: execute overflow better? Result?
-        out.floor().to_u128().unwrap().into()
-    }
-
-    pub fn to_supply(self, supply: Decimal) -> Uint128 {
-        let factor = decimal(10u128.pow(self.supply), 0);
-        let out = supply * factor;
-        // TODO: execute overflow better? Result?
-        out.floor().to_u128().unwrap().into()
-    }
-
-    pub fn from_supply(&self, supply: Uint128) -> Decimal {
-        decimal(supply, self.supply)
+    /// The decimal places of supply and reserve, as powers of ten for the curve math
+    fn exponents(self) -> (i32, i32) {
+        (self.supply as i32, self.reserve as i32)
     }
+}
 
-    pub fn from_reserve(&self, reserve: Uint128) -> Decimal {
-        decimal(reserve, self.reserve)
-    }
+/// Any curve type, with slopes and values from 0.001 to 1000. Shared by the curve and the
+/// contract property tests
+#[cfg(test)]
+pub(crate) fn any_curve() -> impl proptest::strategy::Strategy<Value = crate::msg::CurveType> {
+    use crate::msg::CurveType;
+    use proptest::prelude::*;
+
+    let param = 1u128..=1_000_000;
+    prop_oneof![
+        param.clone().prop_map(|value| CurveType::Constant {
+            value: Uint128::new(value),
+            scale: 3,
+        }),
+        param.clone().prop_map(|slope| CurveType::Linear {
+            slope: Uint128::new(slope),
+            scale: 3,
+        }),
+        param.prop_map(|slope| CurveType::SquareRoot {
+            slope: Uint128::new(slope),
+            scale: 3,
+        }),
+    ]
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::msg::CurveType;
     // TODO: test DecimalPlaces return proper decimals
 
     #[test]
@@ -256,16 +447,21 @@ mod tests {
         // spot price is always 1.5 ATOM
         assert_eq!(
             StdDecimal::percent(150),
-            curve.spot_price(Uint128::new(123))
+            curve.spot_price(Uint128::new(123)).unwrap()
         );
 
         // if we have 30 STEP, we should have 45 ATOM
-        let reserve = curve.reserve(Uint128::new(30_000_000_000));
+        let reserve = curve.reserve(Uint128::new(30_000_000_000)).unwrap();
         assert_eq!(Uint128::new(45_000_000), reserve);
 
         // if we have 36 ATOM, we should have 24 STEP
-        let supply = curve.supply(Uint128::new(36_000_000));
+        let supply = curve.supply(Uint128::new(36_000_000)).unwrap();
         assert_eq!(Uint128::new(24_000_000_000), supply);
+
+        // without decimals, one token costs 2 (rounded up), while 1 buys nothing (rounded down)
+        let curve = Constant::new(decimal(15u128, 1), DecimalPlaces::new(0, 0));
+        assert_eq!(Uint128::new(2), curve.reserve(Uint128::new(1)).unwrap());
+        assert_eq!(Uint128::zero(), curve.supply(Uint128::new(1)).unwrap());
     }
 
     #[test]
@@ -279,33 +475,33 @@ mod tests {
         // spot price is 0.1 with 1 USDT supply
         assert_eq!(
             StdDecimal::permille(100),
-            curve.spot_price(Uint128::new(100))
+            curve.spot_price(Uint128::new(100)).unwrap()
         );
         // spot price is 1.7 with 17 USDT supply
         assert_eq!(
             StdDecimal::permille(1700),
-            curve.spot_price(Uint128::new(1700))
+            curve.spot_price(Uint128::new(1700)).unwrap()
         );
         // spot price is 0.212 with 2.12 USDT supply
         assert_eq!(
             StdDecimal::permille(212),
-            curve.spot_price(Uint128::new(212))
+            curve.spot_price(Uint128::new(212)).unwrap()
         );
 
         // if we have 10 USDT, we should have 5 BTC
-        let reserve = curve.reserve(Uint128::new(1000));
+        let reserve = curve.reserve(Uint128::new(1000)).unwrap();
         assert_eq!(Uint128::new(500_000_000), reserve);
         // if we have 20 USDT, we should have 20 BTC
-        let reserve = curve.reserve(Uint128::new(2000));
+        let reserve = curve.reserve(Uint128::new(2000)).unwrap();
         assert_eq!(Uint128::new(2_000_000_000), reserve);
 
         // if we have 1.25 BTC, we should have 5 USDT
-        let supply = curve.supply(Uint128::new(125_000_000));
+        let supply = curve.supply(Uint128::new(125_000_000)).unwrap();
         assert_eq!(Uint128::new(500), supply);
         // test square root rounding
         // TODO: test when supply has many more decimal places than reserve
         // if we have 1.11 BTC, we should have 4.7116875957... USDT
-        let supply = curve.supply(Uint128::new(111_000_000));
+        let supply = curve.supply(Uint128::new(111_000_000)).unwrap();
         assert_eq!(Uint128::new(471), supply);
     }
 
@@ -320,38 +516,366 @@ mod tests {
         // spot price is 0.35 with 1 TREE supply
         assert_eq!(
             StdDecimal::percent(35),
-            curve.spot_price(Uint128::new(1_000_000))
+            curve.spot_price(Uint128::new(1_000_000)).unwrap()
         );
         // spot price is 3.5 with 100 TREE supply
         assert_eq!(
             StdDecimal::percent(350),
-            curve.spot_price(Uint128::new(100_000_000))
+            curve.spot_price(Uint128::new(100_000_000)).unwrap()
         );
-        // spot price should be 23.478713763747788 with 4500 TREE supply (test rounding and reporting here)
-        // rounds off around 8-9 sig figs (note diff for last points)
+        // spot price should be 23.478713763747791... with 4500 TREE supply,
+        // we keep all 18 digits (rounded down)
         assert_eq!(
-            StdDecimal::from_ratio(2347871365u128, 100_000_000u128),
-            curve.spot_price(Uint128::new(4_500_000_000))
+            StdDecimal::from_ratio(23_478_713_763_747_791_812u128, 10u128.pow(18)),
+            curve.spot_price(Uint128::new(4_500_000_000)).unwrap()
         );
 
         // if we have 1 TREE, we should have 0.2333333333333 CHF
-        let reserve = curve.reserve(Uint128::new(1_000_000));
-        assert_eq!(Uint128::new(23), reserve);
-        // if we have 100 TREE, we should have 233.333333333 CHF
-        let reserve = curve.reserve(Uint128::new(100_000_000));
-        assert_eq!(Uint128::new(23_333), reserve);
+        let reserve = curve.reserve(Uint128::new(1_000_000)).unwrap();
+        assert_eq!(Uint128::new(24), reserve); // round up
+                                               // if we have 100 TREE, we should have 233.333333333 CHF
+        let reserve = curve.reserve(Uint128::new(100_000_000)).unwrap();
+        assert_eq!(Uint128::new(23_334), reserve);
         // test rounding
         // if we have 235 TREE, we should have 840.5790828021146 CHF
-        let reserve = curve.reserve(Uint128::new(235_000_000));
-        assert_eq!(Uint128::new(84_057), reserve); // round down
+        let reserve = curve.reserve(Uint128::new(235_000_000)).unwrap();
+        assert_eq!(Uint128::new(84_058), reserve); // round up
 
-        // // if we have 0.23 CHF, we should have 0.990453 TREE (round down)
-        let supply = curve.supply(Uint128::new(23));
-        assert_eq!(Uint128::new(990_000), supply);
+        // if we have 0.23 CHF, we should have 0.990453 TREE (round down)
+        let supply = curve.supply(Uint128::new(23)).unwrap();
+        assert_eq!(Uint128::new(990_453), supply);
         // if we have 840.58 CHF, we should have 235.000170 TREE (round down)
-        let supply = curve.supply(Uint128::new(84058));
-        assert_eq!(Uint128::new(235_000_000), supply);
+        let supply = curve.supply(Uint128::new(84058)).unwrap();
+        assert_eq!(Uint128::new(235_000_170), supply);
+    }
+
+    #[test]
+    fn huge_powers_of_ten_are_errors() {
+        // beyond what the contract accepts, but the math must not panic there either
+        for places in [
+            DecimalPlaces::new(60, 0),
+            DecimalPlaces::new(200, 0),
+            DecimalPlaces::new(0, 200),
+        ] {
+            let curve = SquareRoot::new(decimal(1u128, 0), places);
+            let supply = Uint128::new(1_000_000);
+            assert!(curve.reserve(supply).is_err() || curve.spot_price(supply).is_err());
+        }
     }
 
-    // Idea: generic test that curve.supply(curve.reserve(supply)) == supply (or within some small rounding margin)
+    #[test]
+    fn linear_curve_many_decimals() {
+        // supply and reserve are both wei-like (18)
+        let normalize = DecimalPlaces::new(18, 18);
+        let curve = Linear::new(decimal(1u128, 0), normalize);
+
+        // spot price keeps all 18 digits of 1.5 TOKEN plus one wei
+        let supply = Uint128::new(1_500_000_000_000_000_001);
+        assert_eq!(
+            StdDecimal::from_ratio(supply, 1_000_000_000_000_000_000u128),
+            curve.spot_price(supply).unwrap()
+        );
+
+        // large reserves no longer overflow when taking the square root
+        let normalize = DecimalPlaces::new(0, 0);
+        let curve = Linear::new(decimal(1u128, 0), normalize);
+        // 1 billion tokens have a reserve of 500 million billion
+        let supply = Uint128::new(1_000_000_000);
+        let reserve = curve.reserve(supply).unwrap();
+        assert_eq!(Uint128::new(500_000_000_000_000_000), reserve);
+        assert_eq!(supply, curve.supply(reserve).unwrap());
+
+        // same for 1 billion tokens with 18 decimals each
+        let normalize = DecimalPlaces::new(18, 18);
+        let curve = Linear::new(decimal(1u128, 0), normalize);
+        let supply = Uint128::new(10u128.pow(27));
+        let reserve = curve.reserve(supply).unwrap();
+        assert_eq!(Uint128::new(5 * 10u128.pow(35)), reserve);
+        assert_eq!(supply, curve.supply(reserve).unwrap());
+    }
+
+    /// Curves and decimal places the comparison runs over
+    fn samples() -> Vec<(CurveType, DecimalPlaces)> {
+        let params = [(1u128, 3), (15, 1), (35, 2), (1, 0), (1_000, 0)];
+        let places = [(0, 0), (2, 8), (6, 2), (6, 6), (9, 6), (18, 6), (18, 18)];
+        let mut samples = vec![];
+        for &(param, scale) in params.iter() {
+            for &(supply, reserve) in places.iter() {
+                let param = Uint128::new(param);
+                let places = DecimalPlaces::new(supply, reserve);
+                samples.push((
+                    CurveType::Constant {
+                        value: param,
+                        scale,
+                    },
+                    places,
+                ));
+                samples.push((
+                    CurveType::Linear {
+                        slope: param,
+                        scale,
+                    },
+                    places,
+                ));
+                samples.push((
+                    CurveType::SquareRoot {
+                        slope: param,
+                        scale,
+                    },
+                    places,
+                ));
+            }
+        }
+        samples
+    }
+
+    /// Supplies spread logarithmically over the range the legacy math supports
+    fn supplies(curve_type: &CurveType, places: DecimalPlaces) -> Vec<Uint128> {
+        let max = legacy::max_supply(curve_type, places) as f64;
+        (0..=100)
+            .map(|i| Uint128::new(max.powf(i as f64 / 100.0) as u128))
+            .collect()
+    }
+
+    /// Absolute difference, and the relative one once the exact value is large enough that
+    /// it is not just about rounding the last digit
+    fn deviation(new: u128, old: u128) -> (u128, f64) {
+        let diff = new.abs_diff(old);
+        let relative = if new >= 1_000_000 {
+            diff as f64 / new as f64
+        } else {
+            0.0
+        };
+        (diff, relative)
+    }
+
+    #[test]
+    fn max_deviation_from_legacy() {
+        // largest difference (in base units, and relative to the exact result) per curve
+        let mut reserve = [(0u128, 0f64); 3];
+        let mut supply = [(0u128, 0f64); 3];
+        let mut price = [(0u128, 0f64); 3];
+        for (curve_type, places) in samples() {
+            let index = match curve_type {
+                CurveType::Constant { .. } => 0,
+                CurveType::Linear { .. } => 1,
+                CurveType::SquareRoot { .. } => 2,
+            };
+            let curve = curve_type.to_curve_fn()(places);
+            let old = legacy::to_curve(&curve_type, places);
+            for x in supplies(&curve_type, places) {
+                let r = curve.reserve(x).unwrap();
+                // only compare where the old math worked, it panicked on the rest
+                let max = |acc: &mut (u128, f64), new: u128, old: StdResult<u128>| {
+                    if let Ok(old) = old {
+                        let (diff, rel) = deviation(new, old);
+                        *acc = (acc.0.max(diff), acc.1.max(rel))
+                    }
+                };
+                max(
+                    &mut reserve[index],
+                    r.u128(),
+                    old.reserve(x).map(|x| x.u128()),
+                );
+                max(
+                    &mut supply[index],
+                    curve.supply(r).unwrap().u128(),
+                    old.supply(r).map(|x| x.u128()),
+                );
+                max(
+                    &mut price[index],
+                    curve.spot_price(x).unwrap().atomics().u128(),
+                    old.spot_price(x).map(|price| price.atomics().u128()),
+                );
+            }
+        }
+
+        // the largest differences, in base units and in permille of the exact result
+        let permille =
+            |max: [(u128, f64); 3]| max.map(|(diff, rel)| (diff, (rel * 1000.0).round() as u32));
+        // constant and linear curves only differ in rounding up the reserve, while the square
+        // root curve reserve was off by up to 5.7%
+        assert_eq!(
+            permille(reserve),
+            [(1, 0), (1, 0), (24_058_880_874_505_780_752, 57)]
+        );
+        // anything involving roots was only precise to 6 (square) or 3 (cube) decimal places
+        // of whole tokens before, which is all of it for small amounts with many decimals
+        assert_eq!(
+            permille(supply),
+            [(0, 0), (999_999_128_205, 1000), (996_505_175_059_698, 1000)]
+        );
+        assert_eq!(
+            permille(price),
+            [(0, 0), (0, 0), (999_317_075_797_898, 1000)]
+        );
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture bench_against_legacy`
+    #[test]
+    #[ignore]
+    fn bench_against_legacy() {
+        use std::time::Instant;
+
+        let samples: Vec<_> = samples()
+            .into_iter()
+            .flat_map(|(curve_type, places)| {
+                let supplies = supplies(&curve_type, places);
+                supplies
+                    .into_iter()
+                    .map(move |x| (curve_type.clone(), places, x))
+            })
+            .collect();
+        let run = |name: &str, build: &dyn Fn(&CurveType, DecimalPlaces) -> Box<dyn Curve>| {
+            let start = Instant::now();
+            for (curve_type, places, x) in samples.iter() {
+                let curve = build(curve_type, *places);
+                // the legacy math fails on some of them, like it panicked before
+                if let Ok(reserve) = curve.reserve(*x) {
+                    let _ = curve.supply(reserve);
+                }
+                let _ = curve.spot_price(*x);
+            }
+            let per_call = start.elapsed().as_nanos() / (3 * samples.len() as u128);
+            println!("{:>8}: {} ns per call", name, per_call);
+        };
+        run("integer", &|curve_type, places| {
+            curve_type.to_curve_fn()(places)
+        });
+        run("legacy", &|curve_type, places| {
+            legacy::to_curve(curve_type, places)
+        });
+    }
+
+    mod invariants {
+        use super::*;
+        use proptest::prelude::*;
+
+        /// Amounts as well as the atomics of spot prices are `Uint128`
+        const MAX_UINT: f64 = u128::MAX as f64;
+
+        fn any_places() -> impl Strategy<Value = DecimalPlaces> {
+            (0u8..=18, 0u8..=18).prop_map(|(supply, reserve)| DecimalPlaces::new(supply, reserve))
+        }
+
+        /// Largest supply (in base units) for which the reserve and spot price of a curve can
+        /// still be represented. This is the full supply range the curves support.
+        fn max_supply(curve_type: &CurveType, places: DecimalPlaces) -> u128 {
+            let (param, scale) = match curve_type {
+                CurveType::Constant { value, scale } => (value, scale),
+                CurveType::Linear { slope, scale } => (slope, scale),
+                CurveType::SquareRoot { slope, scale } => (slope, scale),
+            };
+            let k = param.u128() as f64 / 10f64.powi(*scale as i32);
+            let supply_unit = 10f64.powi(places.supply as i32);
+            let reserve_unit = 10f64.powi(places.reserve as i32);
+
+            // whole tokens, limited by the supply itself, the reserve and the spot price
+            let max_reserve = MAX_UINT / reserve_unit;
+            let max_price = MAX_UINT / 1e18;
+            let mut max = MAX_UINT / supply_unit;
+            match curve_type {
+                CurveType::Constant { .. } => max = max.min(max_reserve / k),
+                CurveType::Linear { .. } => {
+                    max = max.min((2.0 * max_reserve / k).sqrt()).min(max_price / k)
+                }
+                CurveType::SquareRoot { .. } => {
+                    max = max
+                        .min((1.5 * max_reserve / k).powf(2.0 / 3.0))
+                        .min((max_price / k).powi(2))
+                }
+            }
+            // leave a margin for the float approximation
+            (max * supply_unit * 0.99) as u128
+        }
+
+        /// A curve along with two supplies in its supported range (the first one is smaller)
+        fn curve_with_supplies() -> impl Strategy<Value = (CurveType, DecimalPlaces, u128, u128)> {
+            (any_curve(), any_places()).prop_flat_map(|(curve_type, places)| {
+                let max = max_supply(&curve_type, places);
+                (0..=max, 0..=max)
+                    .prop_map(move |(a, b)| (curve_type.clone(), places, a.min(b), a.max(b)))
+            })
+        }
+
+        proptest! {
+            #[test]
+            fn reserve_is_monotonic((curve_type, places, low, high) in curve_with_supplies()) {
+                let curve = curve_type.to_curve_fn()(places);
+                let low_reserve = curve.reserve(Uint128::new(low)).unwrap();
+                let high_reserve = curve.reserve(Uint128::new(high)).unwrap();
+                prop_assert!(low_reserve <= high_reserve);
+                prop_assert!(curve.spot_price(Uint128::new(low)).unwrap() <= curve.spot_price(Uint128::new(high)).unwrap());
+            }
+
+            #[test]
+            fn never_over_mints((curve_type, places, _, supply) in curve_with_supplies()) {
+                let curve = curve_type.to_curve_fn()(places);
+                let supply = Uint128::new(supply);
+                let reserve = curve.reserve(supply).unwrap();
+                // what we charge for a supply always buys at least that supply
+                prop_assert!(curve.supply(reserve).unwrap() >= supply);
+                // the tokens minted for a reserve are never worth more than that reserve
+                prop_assert!(curve.reserve(curve.supply(reserve).unwrap()).unwrap() <= reserve);
+            }
+
+            #[test]
+            fn buy_then_sell_never_profits((curve_type, places, supply, target) in curve_with_supplies()) {
+                let curve = curve_type.to_curve_fn()(places);
+                let supply = Uint128::new(supply);
+                let reserve = curve.reserve(supply).unwrap();
+                // pay whatever is needed to reach the target supply
+                let paid = curve.reserve(Uint128::new(target)).unwrap() - reserve;
+
+                let minted = curve.supply(reserve + paid).unwrap().saturating_sub(supply);
+                let released = curve.reserve(supply + minted).unwrap() - reserve;
+                prop_assert!(released <= paid);
+            }
+
+            #[test]
+            fn roots_round_as_requested(a: u128, b: u128, c: u128) {
+                let x = Uint512::from(a) * Uint512::from(b) * Uint512::from(c);
+                let one = Uint512::one();
+                let root = square_root(x, Rounding::Down);
+                prop_assert!(root * root <= x && (root + one) * (root + one) > x);
+                let root = square_root(x, Rounding::Up);
+                prop_assert!(root * root >= x && (root.is_zero() || (root - one) * (root - one) < x));
+                let root = cube_root(x, Rounding::Down);
+                prop_assert!(root * root * root <= x && (root + one) * (root + one) * (root + one) > x);
+                let root = cube_root(x, Rounding::Up);
+                let below = root.saturating_sub(one);
+                prop_assert!(root * root * root >= x && (root.is_zero() || below * below * below < x));
+            }
+
+            #[test]
+            fn never_panics(
+                curve_type in any_curve(),
+                // half of them beyond `MAX_DECIMALS`, which the contract refuses
+                places in prop_oneof![
+                    any_places(),
+                    any::<(u8, u8)>().prop_map(|(supply, reserve)| DecimalPlaces::new(supply, reserve)),
+                ],
+                supply: u128,
+                reserve: u128,
+                in_range: bool,
+            ) {
+                // any input either works or returns an error, and within the supported
+                // range it always works (half of the supplies are drawn from there)
+                let curve = curve_type.to_curve_fn()(places);
+                let max = max_supply(&curve_type, places);
+                let supply = if in_range { supply % (max + 1) } else { supply };
+                let max_decimals = MAX_DECIMALS as u32;
+                let supported =
+                    supply <= max && places.supply <= max_decimals && places.reserve <= max_decimals;
+                let supply = Uint128::new(supply);
+                let reserve = Uint128::new(reserve);
+                prop_assert!(curve.reserve(supply).is_ok() || !supported);
+                prop_assert!(curve.spot_price(supply).is_ok() || !supported);
+                let _ = curve.supply(reserve);
+                let _ = curve.supply(Uint128::MAX);
+                let _ = curve.reserve(Uint128::MAX);
+                let _ = curve.spot_price(Uint128::MAX);
+            }
+        }
+    }
 }
@@ -0,0 +1,151 @@
+//! The original curve math on top of `rust_decimal`, only kept around to compare against
+//! (and benchmark) the integer implementation. Where the original panicked, this returns
+//! an error.
+
+use integer_cbrt::IntegerCubeRoot;
+use integer_sqrt::IntegerSquareRoot;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+use cosmwasm_std::{Decimal as StdDecimal, StdError, StdResult, Uint128};
+
+use super::{Curve, DecimalPlaces};
+use crate::msg::CurveType;
+
+fn decimal<T: Into<u128>>(num: T, scale: u32) -> Decimal {
+    Decimal::from_i128_with_scale(num.into() as i128, scale)
+}
+
+fn decimal_to_std(x: Decimal) -> StdResult<StdDecimal> {
+    StdDecimal::from_str(&x.to_string())
+}
+
+fn to_uint(x: Decimal, places: u32) -> StdResult<Uint128> {
+    let out = x * decimal(10u128.pow(places), 0);
+    Ok(out.floor().to_u128().unwrap().into())
+}
+
+/// Builds the legacy version of a curve
+pub fn to_curve(curve_type: &CurveType, places: DecimalPlaces) -> Box<dyn Curve> {
+    match *curve_type {
+        CurveType::Constant { value, scale } => Box::new(Constant {
+            value: decimal(value, scale),
+            normalize: places,
+        }),
+        CurveType::Linear { slope, scale } => Box::new(Linear {
+            slope: decimal(slope, scale),
+            normalize: places,
+        }),
+        CurveType::SquareRoot { slope, scale } => Box::new(SquareRoot {
+            slope: decimal(slope, scale),
+            normalize: places,
+        }),
+    }
+}
+
+struct Constant {
+    value: Decimal,
+    normalize: DecimalPlaces,
+}
+
+impl Curve for Constant {
+    fn spot_price(&self, _supply: Uint128) -> StdResult<StdDecimal> {
+        decimal_to_std(self.value)
+    }
+
+    fn reserve(&self, supply: Uint128) -> StdResult<Uint128> {
+        let reserve = decimal(supply, self.normalize.supply) * self.value;
+        to_uint(reserve, self.normalize.reserve)
+    }
+
+    fn supply(&self, reserve: Uint128) -> StdResult<Uint128> {
+        let supply = decimal(reserve, self.normalize.reserve) / self.value;
+        to_uint(supply, self.normalize.supply)
+    }
+}
+
+struct Linear {
+    slope: Decimal,
+    normalize: DecimalPlaces,
+}
+
+impl Curve for Linear {
+    fn spot_price(&self, supply: Uint128) -> StdResult<StdDecimal> {
+        decimal_to_std(decimal(supply, self.normalize.supply) * self.slope)
+    }
+
+    fn reserve(&self, supply: Uint128) -> StdResult<Uint128> {
+        let normalized = decimal(supply, self.normalize.supply);
+        let reserve = normalized * normalized * self.slope * Decimal::new(5, 1);
+        to_uint(reserve, self.normalize.reserve)
+    }
+
+    fn supply(&self, reserve: Uint128) -> StdResult<Uint128> {
+        let square = decimal(reserve + reserve, self.normalize.reserve) / self.slope;
+        to_uint(square_root(square)?, self.normalize.supply)
+    }
+}
+
+struct SquareRoot {
+    slope: Decimal,
+    normalize: DecimalPlaces,
+}
+
+impl Curve for SquareRoot {
+    fn spot_price(&self, supply: Uint128) -> StdResult<StdDecimal> {
+        let root = square_root(decimal(supply, self.normalize.supply))?;
+        decimal_to_std(root * self.slope)
+    }
+
+    fn reserve(&self, supply: Uint128) -> StdResult<Uint128> {
+        let normalized = decimal(supply, self.normalize.supply);
+        let root = square_root(normalized)?;
+        let reserve = self.slope * normalized * root / Decimal::new(15, 1);
+        to_uint(reserve, self.normalize.reserve)
+    }
+
+    fn supply(&self, reserve: Uint128) -> StdResult<Uint128> {
+        let base = decimal(reserve, self.normalize.reserve) * Decimal::new(15, 1) / self.slope;
+        to_uint(cube_root(base * base)?, self.normalize.supply)
+    }
+}
+
+fn square_root(square: Decimal) -> StdResult<Decimal> {
+    let extended = extend(square, 12)?;
+    Ok(decimal(extended.integer_sqrt(), 6))
+}
+
+fn cube_root(cube: Decimal) -> StdResult<Decimal> {
+    let extended = extend(cube, 9)?;
+    Ok(decimal(extended.integer_cbrt(), 3))
+}
+
+fn extend(x: Decimal, digits: u32) -> StdResult<u128> {
+    x.checked_mul(decimal(10u128.pow(digits), 0))
+        .map(|extended| extended.floor().to_u128().unwrap())
+        .ok_or_else(|| StdError::generic_err("legacy root overflow"))
+}
+
+/// The legacy `Decimal` stores a 96 bit mantissa, we stay one digit below that
+const MAX_DECIMAL: f64 = 7.9e27;
+
+/// Largest supply (in base units) the legacy math supported for a curve
+pub fn max_supply(curve_type: &CurveType, places: DecimalPlaces) -> u128 {
+    let (param, scale) = match curve_type {
+        CurveType::Constant { value, scale } => (value, scale),
+        CurveType::Linear { slope, scale } => (slope, scale),
+        CurveType::SquareRoot { slope, scale } => (slope, scale),
+    };
+    let k = param.u128() as f64 / 10f64.powi(*scale as i32);
+    let supply_unit = 10f64.powi(places.supply as i32);
+    let max_reserve = MAX_DECIMAL / 10f64.powi(places.reserve as i32);
+    let max = match curve_type {
+        CurveType::Constant { .. } => max_reserve / k,
+        CurveType::Linear { .. } => (2.0 * max_reserve / k).sqrt().min(MAX_DECIMAL.sqrt()),
+        CurveType::SquareRoot { .. } => (1.5 * max_reserve / k)
+            .powf(2.0 / 3.0)
+            .min(MAX_DECIMAL.cbrt()),
+    };
+    (max.min(MAX_DECIMAL / supply_unit) * supply_unit * 0.99) as u128
+}
@@ -1,5 +1,6 @@
-use cosmwasm_std::StdError;
-use cw_utils::PaymentError;
+use cosmwasm_std::{StdError, Uint128};
+use cw_controllers::AdminError;
+use cw_utils::{ParseReplyError, PaymentError};
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -13,6 +14,85 @@ pub enum ContractError {
     #[error("{0}")]
     Payment(#[from] PaymentError),
 
+    #[error("{0}")]
+    Admin(#[from] AdminError),
+
+    #[error("{0}")]
+    ParseReply(#[from] ParseReplyError),
+
     #[error("Unauthorized")]
     Unauthorized {},
+
+    #[error("This contract is not in batch auction mode")]
+    NotBatchMode {},
+
+    #[error("The current batch can only be settled once its epoch has ended")]
+    BatchNotEnded {},
+
+    #[error("Batches must take at least one order")]
+    InvalidBatch {},
+
+    #[error("Orders must be at least {min}")]
+    OrderTooSmall { min: Uint128 },
+
+    #[error("This batch is full, it takes at most {max_orders} orders")]
+    BatchFull { max_orders: u32 },
+
+    #[error("The orders of the last batch are still being filled, call SettleBatch to continue")]
+    SettlementInProgress {},
+
+    #[error("Vesting cliff must not be longer than its duration, which must not be zero")]
+    InvalidVesting {},
+
+    #[error("No vested tokens to claim")]
+    NothingToClaim {},
+
+    #[error("Reserve basket must list distinct denoms with non-zero weights, starting with reserve_denom")]
+    InvalidBasket {},
+
+    #[error("Payment does not cover a single unit of the reserve basket")]
+    IncompleteBasket {},
+
+    #[error("This contract does not stake its reserve")]
+    NotStakingMode {},
+
+    #[error("Staking needs a single reserve denom that the chain bonds, a delegated share of at most 100%, and a treasury if rewards go there")]
+    InvalidStaking {},
+
+    #[error("{validator} is not in the current validator set")]
+    NotInValidatorSet { validator: String },
+
+    #[error("Only {available} of the reserve is liquid, but {requested} is needed. The rest is staked or unbonding")]
+    InsufficientLiquidity {
+        available: Uint128,
+        requested: Uint128,
+    },
+
+    #[error("Curve update requires a deposit of {required} {denom}, but only {sent} was sent")]
+    InsufficientDeposit {
+        required: Uint128,
+        sent: Uint128,
+        denom: String,
+    },
+
+    #[error("This contract does not host other curves")]
+    NotFactoryMode {},
+
+    #[error("Cannot host curves in {denom}, as the reserve of this contract is staked in it")]
+    StakedDenom { denom: String },
+
+    #[error("There is no hosted curve with id {curve_id}")]
+    UnknownCurve { curve_id: u64 },
+
+    #[error("Tokens may have at most {max} decimal places")]
+    TooManyDecimals { max: u8 },
+
+    #[error("Cannot migrate from {previous_contract}")]
+    CannotMigrate { previous_contract: String },
+
+    #[error("Cannot migrate from the newer version {previous_version}")]
+    CannotDowngrade { previous_version: String },
+
+    #[error("Unknown state version {version}")]
+    UnknownStateVersion { version: u16 },
 }
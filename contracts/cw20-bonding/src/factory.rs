@@ -0,0 +1,283 @@
+use cosmwasm_std::{
+    attr, from_binary, to_binary, Addr, BankMsg, Deps, DepsMut, Env, MessageInfo, Order, Reply,
+    Response, StdError, StdResult, Storage, SubMsg, Uint128, WasmMsg,
+};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, MinterResponse};
+use cw20_base::msg::InstantiateMarketingInfo;
+use cw_storage_plus::Bound;
+use cw_utils::{nonpayable, parse_reply_instantiate_data};
+
+use crate::basket::{basket_coins, init_basket, must_pay_basket};
+use crate::contract::validate_decimals;
+use crate::error::ContractError;
+use crate::marketing::with_marketing_admin;
+use crate::msg::{CurveInfoResponse, CurveType, HostedCurveInfo, HostedCurvesResponse, ReceiveMsg};
+use crate::state::{
+    BasketComponent, CurveState, HostedCurve, PendingCurve, CURVE_STATE, FACTORY, HOSTED_COUNT,
+    HOSTED_CURVES, HOSTED_RESERVES, HOSTED_TOKENS, PENDING_CURVES, STAKING_CONFIG,
+};
+
+// settings for pagination
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+
+/// Stores a pending curve and instantiates its token. The curve is registered once the reply
+/// brings the token address, so it can be bought right after this message.
+#[allow(clippy::too_many_arguments)]
+pub fn execute_create_curve(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    name: String,
+    symbol: String,
+    decimals: u8,
+    reserve_denom: String,
+    reserve_decimals: u8,
+    curve_type: CurveType,
+    marketing: Option<InstantiateMarketingInfo>,
+) -> Result<Response, ContractError> {
+    nonpayable(&info)?;
+    let factory = FACTORY
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NotFactoryMode {})?;
+    // hosted curves share our balance, so they stay out of the denom that earns staking rewards
+    if STAKING_CONFIG.may_load(deps.storage)?.is_some()
+        && CURVE_STATE.load(deps.storage)?.basket[0].denom == reserve_denom
+    {
+        return Err(ContractError::StakedDenom {
+            denom: reserve_denom,
+        });
+    }
+
+    let places = validate_decimals(decimals, reserve_decimals)?;
+
+    let curve_id = HOSTED_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    HOSTED_COUNT.save(deps.storage, &curve_id)?;
+    let pending = PendingCurve {
+        curve_type,
+        state: CurveState::new(init_basket(reserve_denom, None)?, places),
+    };
+    PENDING_CURVES.save(deps.storage, curve_id, &pending)?;
+
+    let token_msg = cw20_base::msg::InstantiateMsg {
+        name,
+        symbol: symbol.clone(),
+        decimals,
+        initial_balances: vec![],
+        mint: Some(MinterResponse {
+            minter: env.contract.address.to_string(),
+            cap: None,
+        }),
+        marketing: Some(with_marketing_admin(marketing, &info.sender)),
+    };
+    let instantiate = WasmMsg::Instantiate {
+        admin: Some(env.contract.address.to_string()),
+        code_id: factory.cw20_code_id,
+        msg: to_binary(&token_msg)?,
+        funds: vec![],
+        label: format!("{} bonding curve", symbol),
+    };
+
+    let res = Response::new()
+        .add_submessage(SubMsg::reply_on_success(instantiate, curve_id))
+        .add_attribute("action", "create_curve")
+        .add_attribute("curve_id", curve_id.to_string());
+    Ok(res)
+}
+
+/// Registers the token of a pending curve. The reply id is the curve id.
+pub fn reply_token_instantiated(deps: DepsMut, msg: Reply) -> Result<Response, ContractError> {
+    let curve_id = msg.id;
+    let pending = PENDING_CURVES
+        .may_load(deps.storage, curve_id)?
+        .ok_or(ContractError::UnknownCurve { curve_id })?;
+    PENDING_CURVES.remove(deps.storage, curve_id);
+
+    let res = parse_reply_instantiate_data(msg)?;
+    let token = deps.api.addr_validate(&res.contract_address)?;
+    let curve = HostedCurve {
+        token: token.clone(),
+        curve_type: pending.curve_type,
+        state: pending.state,
+    };
+    HOSTED_CURVES.save(deps.storage, curve_id, &curve)?;
+    HOSTED_TOKENS.save(deps.storage, &token, &curve_id)?;
+
+    Ok(Response::new().add_attribute("token", token))
+}
+
+/// Adds `added` basket units to, and takes `removed` from, the totals in `HOSTED_RESERVES`
+pub fn update_hosted_reserves(
+    storage: &mut dyn Storage,
+    basket: &[BasketComponent],
+    added: Uint128,
+    removed: Uint128,
+) -> StdResult<()> {
+    for component in basket {
+        HOSTED_RESERVES.update(storage, &component.denom, |total| {
+            (total.unwrap_or_default() + added * component.weight)
+                .checked_sub(removed * component.weight)
+                .map_err(StdError::overflow)
+        })?;
+    }
+    Ok(())
+}
+
+fn load_curve(deps: Deps, curve_id: u64) -> Result<HostedCurve, ContractError> {
+    HOSTED_CURVES
+        .may_load(deps.storage, curve_id)?
+        .ok_or(ContractError::UnknownCurve { curve_id })
+}
+
+/// Same as a plain `Buy`, but mints the hosted token
+pub fn execute_buy_hosted(
+    deps: DepsMut,
+    info: MessageInfo,
+    curve_id: u64,
+) -> Result<Response, ContractError> {
+    let mut hosted = load_curve(deps.as_ref(), curve_id)?;
+    let state = &mut hosted.state;
+    let payment = must_pay_basket(&info, &state.basket)?;
+
+    let curve = hosted.curve_type.to_curve_fn()(state.decimals);
+    let curve_reserve = curve.reserve(state.supply)?;
+    let new_supply = curve.supply(curve_reserve + state.to_curve(payment, curve_reserve))?;
+    let minted = new_supply
+        .checked_sub(state.supply)
+        .map_err(StdError::overflow)?;
+    state.reserve += payment;
+    state.supply = new_supply;
+    HOSTED_CURVES.save(deps.storage, curve_id, &hosted)?;
+    update_hosted_reserves(deps.storage, &hosted.state.basket, payment, Uint128::zero())?;
+
+    let mint = WasmMsg::Execute {
+        contract_addr: hosted.token.to_string(),
+        msg: to_binary(&Cw20ExecuteMsg::Mint {
+            recipient: info.sender.to_string(),
+            amount: minted,
+        })?,
+        funds: vec![],
+    };
+    let res = Response::new()
+        .add_message(mint)
+        .add_attribute("action", "buy")
+        .add_attribute("curve_id", curve_id.to_string())
+        .add_attribute("from", info.sender)
+        .add_attribute("reserve", payment)
+        .add_attribute("supply", minted);
+    Ok(res)
+}
+
+/// Sells hosted tokens that were sent here. Anything but a hosted token is rejected.
+pub fn receive_hosted(
+    deps: DepsMut,
+    info: MessageInfo,
+    wrapper: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    let curve_id = HOSTED_TOKENS
+        .may_load(deps.storage, &info.sender)?
+        .ok_or(ContractError::Unauthorized {})?;
+    let sender = deps.api.addr_validate(&wrapper.sender)?;
+    let ReceiveMsg::Sell { recipient } = from_binary(&wrapper.msg)?;
+    let receiver = match recipient {
+        Some(recipient) => deps.api.addr_validate(&recipient)?,
+        None => sender.clone(),
+    };
+
+    let mut res = sell_hosted(deps, curve_id, &receiver, wrapper.amount)?;
+    res.attributes.push(attr("sender", sender));
+    res.attributes.push(attr("recipient", receiver));
+    Ok(res)
+}
+
+/// Burns `amount` of the hosted tokens we hold, and pays the released reserve to `receiver`
+fn sell_hosted(
+    deps: DepsMut,
+    curve_id: u64,
+    receiver: &Addr,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let mut hosted = load_curve(deps.as_ref(), curve_id)?;
+    let state = &mut hosted.state;
+
+    let curve = hosted.curve_type.to_curve_fn()(state.decimals);
+    let curve_reserve = curve.reserve(state.supply)?;
+    state.supply = state
+        .supply
+        .checked_sub(amount)
+        .map_err(StdError::overflow)?;
+    let curve_released = curve_reserve - curve.reserve(state.supply)?;
+    let released = state.from_curve(curve_released, curve_reserve);
+    state.reserve = state
+        .reserve
+        .checked_sub(released)
+        .map_err(StdError::overflow)?;
+    HOSTED_CURVES.save(deps.storage, curve_id, &hosted)?;
+    update_hosted_reserves(
+        deps.storage,
+        &hosted.state.basket,
+        Uint128::zero(),
+        released,
+    )?;
+
+    let burn = WasmMsg::Execute {
+        contract_addr: hosted.token.to_string(),
+        msg: to_binary(&Cw20ExecuteMsg::Burn { amount })?,
+        funds: vec![],
+    };
+    let payout = BankMsg::Send {
+        to_address: receiver.to_string(),
+        amount: basket_coins(&hosted.state.basket, released),
+    };
+    let res = Response::new()
+        .add_message(burn)
+        .add_message(payout)
+        .add_attribute("action", "receive_sell")
+        .add_attribute("curve_id", curve_id.to_string())
+        .add_attribute("supply", amount)
+        .add_attribute("reserve", released);
+    Ok(res)
+}
+
+fn hosted_info(curve_id: u64, hosted: HostedCurve) -> StdResult<HostedCurveInfo> {
+    let CurveState {
+        reserve,
+        supply,
+        basket,
+        decimals,
+        ..
+    } = hosted.state;
+    let curve = hosted.curve_type.to_curve_fn()(decimals);
+    Ok(HostedCurveInfo {
+        curve_id,
+        token: hosted.token.into(),
+        curve_type: hosted.curve_type,
+        info: CurveInfoResponse {
+            reserve,
+            supply,
+            spot_price: curve.spot_price(supply)?,
+            reserve_denom: basket[0].denom.clone(),
+        },
+    })
+}
+
+pub fn query_hosted_curve(deps: Deps, curve_id: u64) -> StdResult<HostedCurveInfo> {
+    let hosted = HOSTED_CURVES.load(deps.storage, curve_id)?;
+    hosted_info(curve_id, hosted)
+}
+
+pub fn query_hosted_curves(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<HostedCurvesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let curves = HOSTED_CURVES
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.and_then(|(curve_id, hosted)| hosted_info(curve_id, hosted)))
+        .collect::<StdResult<_>>()?;
+    Ok(HostedCurvesResponse { curves })
+}
@@ -0,0 +1,856 @@
+#![cfg(test)]
+
+use cosmwasm_std::testing::mock_env;
+use cosmwasm_std::{coins, to_binary, Addr, Coin, Decimal, Empty, Uint128, Validator};
+use cw20::{
+    BalanceResponse, Cw20ExecuteMsg, Cw20QueryMsg, MarketingInfoResponse, TokenInfoResponse,
+};
+use cw_multi_test::{
+    App, AppResponse, Contract, ContractWrapper, Executor, StakingInfo, StakingSudo, SudoMsg,
+};
+use proptest::prelude::*;
+
+use crate::curves::any_curve;
+use crate::msg::{
+    CurveInfoResponse, CurveType, ExecuteMsg, HostedCurveInfo, HostedCurvesResponse,
+    InstantiateMsg, QueryMsg, ReceiveMsg, StakingResponse,
+};
+use crate::state::{FactoryConfig, RewardsDestination, StakingConfig, Unbonding};
+use crate::ContractError;
+
+const DENOM: &str = "ustake";
+const OWNER: &str = "owner";
+const TRADERS: [&str; 3] = ["alice", "bob", "carl"];
+const INITIAL_FUNDS: u128 = 1_000_000_000_000;
+const VALIDATOR: &str = "validator";
+const TREASURY: &str = "treasury";
+const UNBONDING_PERIOD: u64 = 60;
+const YEAR: u64 = 365 * 24 * 60 * 60;
+
+pub fn contract_bonding() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        crate::contract::execute,
+        crate::contract::instantiate,
+        crate::contract::query,
+    )
+    .with_reply(crate::contract::reply);
+    Box::new(contract)
+}
+
+pub fn contract_cw20() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        cw20_base::contract::execute,
+        cw20_base::contract::instantiate,
+        cw20_base::contract::query,
+    );
+    Box::new(contract)
+}
+
+/// One step of a random trading session. Sell and transfer amounts are given in
+/// per mille of the trader's balance, so most of them are valid.
+#[derive(Clone, Debug)]
+enum Action {
+    Buy {
+        trader: usize,
+        amount: u128,
+    },
+    Sell {
+        trader: usize,
+        permille: u128,
+    },
+    SellTo {
+        trader: usize,
+        to: usize,
+        permille: u128,
+    },
+    SendToSell {
+        trader: usize,
+        permille: u128,
+    },
+    Transfer {
+        trader: usize,
+        to: usize,
+        permille: u128,
+    },
+}
+
+fn any_action() -> impl Strategy<Value = Action> {
+    let trader = 0..TRADERS.len();
+    let permille = 0u128..=1000;
+    prop_oneof![
+        (trader.clone(), 0u128..=INITIAL_FUNDS / 10)
+            .prop_map(|(trader, amount)| Action::Buy { trader, amount }),
+        (trader.clone(), permille.clone())
+            .prop_map(|(trader, permille)| Action::Sell { trader, permille }),
+        (trader.clone(), trader.clone(), permille.clone()).prop_map(|(trader, to, permille)| {
+            Action::SellTo {
+                trader,
+                to,
+                permille,
+            }
+        }),
+        (trader.clone(), permille.clone())
+            .prop_map(|(trader, permille)| Action::SendToSell { trader, permille }),
+        (trader.clone(), trader, permille).prop_map(|(trader, to, permille)| {
+            Action::Transfer {
+                trader,
+                to,
+                permille,
+            }
+        }),
+    ]
+}
+
+struct Suite {
+    app: App,
+    bonding: Addr,
+}
+
+impl Suite {
+    fn new(curve_type: CurveType, decimals: u8, reserve_decimals: u8) -> Self {
+        Self::instantiate(InstantiateMsg {
+            name: "Bonded".to_string(),
+            symbol: "EPOXY".to_string(),
+            decimals,
+            reserve_denom: DENOM.to_string(),
+            reserve_decimals,
+            basket: None,
+            curve_type,
+            admin: None,
+            treasury: None,
+            batch: None,
+            vesting: None,
+            staking: None,
+            factory: None,
+            marketing: None,
+        })
+    }
+
+    /// Sets up the chain with DENOM as the staking denom, and a validator without commission
+    fn instantiate(msg: InstantiateMsg) -> Self {
+        let mut app = App::new(|router, api, storage| {
+            for trader in TRADERS {
+                router
+                    .bank
+                    .init_balance(
+                        storage,
+                        &Addr::unchecked(trader),
+                        coins(INITIAL_FUNDS, DENOM),
+                    )
+                    .unwrap();
+            }
+            let staking_info = StakingInfo {
+                bonded_denom: DENOM.to_string(),
+                unbonding_time: UNBONDING_PERIOD,
+                apr: Decimal::percent(10),
+            };
+            router.staking.setup(storage, staking_info).unwrap();
+            let validator = Validator {
+                address: VALIDATOR.to_string(),
+                commission: Decimal::zero(),
+                max_commission: Decimal::one(),
+                max_change_rate: Decimal::one(),
+            };
+            router
+                .staking
+                .add_validator(api, storage, &mock_env().block, validator)
+                .unwrap();
+        });
+        let code_id = app.store_code(contract_bonding());
+        let bonding = app
+            .instantiate_contract(code_id, Addr::unchecked(OWNER), &msg, &[], "bonding", None)
+            .unwrap();
+        Suite { app, bonding }
+    }
+
+    fn execute(
+        &mut self,
+        sender: &str,
+        msg: &ExecuteMsg,
+        funds: &[Coin],
+    ) -> anyhow::Result<AppResponse> {
+        self.app
+            .execute_contract(Addr::unchecked(sender), self.bonding.clone(), msg, funds)
+    }
+
+    fn balance(&self, trader: &str) -> Uint128 {
+        let res: BalanceResponse = self
+            .app
+            .wrap()
+            .query_wasm_smart(
+                &self.bonding,
+                &QueryMsg::Balance {
+                    address: trader.to_string(),
+                },
+            )
+            .unwrap();
+        res.balance
+    }
+
+    fn bank_balance(&self, addr: &str) -> Uint128 {
+        self.app.wrap().query_balance(addr, DENOM).unwrap().amount
+    }
+
+    fn curve_info(&self) -> CurveInfoResponse {
+        self.app
+            .wrap()
+            .query_wasm_smart(&self.bonding, &QueryMsg::CurveInfo {})
+            .unwrap()
+    }
+
+    fn staking(&self) -> StakingResponse {
+        self.app
+            .wrap()
+            .query_wasm_smart(&self.bonding, &QueryMsg::Staking {})
+            .unwrap()
+    }
+
+    /// What the chain thinks we have delegated
+    fn delegation(&self) -> Uint128 {
+        self.app
+            .wrap()
+            .query_delegation(&self.bonding, VALIDATOR)
+            .unwrap()
+            .map(|d| d.amount.amount)
+            .unwrap_or_default()
+    }
+
+    fn advance(&mut self, seconds: u64) {
+        self.app.update_block(|block| {
+            block.height += seconds / 5;
+            block.time = block.time.plus_seconds(seconds);
+        });
+    }
+
+    fn share(&self, trader: &str, permille: u128) -> Uint128 {
+        self.balance(trader).multiply_ratio(permille, 1000u128)
+    }
+
+    /// Errors are fine (eg. zero amounts), as long as the invariants still hold
+    fn run(&mut self, action: &Action) {
+        let _ = match *action {
+            Action::Buy { trader, amount } => {
+                self.execute(TRADERS[trader], &ExecuteMsg::Buy {}, &coins(amount, DENOM))
+            }
+            Action::Sell { trader, permille } => {
+                let amount = self.share(TRADERS[trader], permille);
+                self.execute(TRADERS[trader], &ExecuteMsg::Burn { amount }, &[])
+            }
+            Action::SellTo {
+                trader,
+                to,
+                permille,
+            } => {
+                let msg = ExecuteMsg::SellTo {
+                    amount: self.share(TRADERS[trader], permille),
+                    recipient: TRADERS[to].to_string(),
+                };
+                self.execute(TRADERS[trader], &msg, &[])
+            }
+            Action::SendToSell { trader, permille } => {
+                let msg = ExecuteMsg::Send {
+                    contract: self.bonding.to_string(),
+                    amount: self.share(TRADERS[trader], permille),
+                    msg: to_binary(&ReceiveMsg::Sell { recipient: None }).unwrap(),
+                };
+                self.execute(TRADERS[trader], &msg, &[])
+            }
+            Action::Transfer {
+                trader,
+                to,
+                permille,
+            } => {
+                let msg = ExecuteMsg::Transfer {
+                    recipient: TRADERS[to].to_string(),
+                    amount: self.share(TRADERS[trader], permille),
+                };
+                self.execute(TRADERS[trader], &msg, &[])
+            }
+        };
+        self.app.update_block(|block| {
+            block.height += 1;
+            block.time = block.time.plus_seconds(5);
+        });
+    }
+
+    fn assert_invariants(&self) {
+        let curve: CurveInfoResponse = self
+            .app
+            .wrap()
+            .query_wasm_smart(&self.bonding, &QueryMsg::CurveInfo {})
+            .unwrap();
+        let held = self.app.wrap().query_balance(&self.bonding, DENOM).unwrap();
+        assert_eq!(curve.reserve, held.amount);
+
+        let info: TokenInfoResponse = self
+            .app
+            .wrap()
+            .query_wasm_smart(&self.bonding, &QueryMsg::TokenInfo {})
+            .unwrap();
+        assert_eq!(curve.supply, info.total_supply);
+
+        // no reserve is created or lost, it only moves between traders and the contract
+        let traders: u128 = TRADERS
+            .iter()
+            .map(|t| {
+                self.app
+                    .wrap()
+                    .query_balance(*t, DENOM)
+                    .unwrap()
+                    .amount
+                    .u128()
+            })
+            .sum();
+        assert_eq!(traders + held.amount.u128(), INITIAL_FUNDS * 3);
+    }
+}
+
+#[test]
+fn buy_transfer_and_sell_back() {
+    let curve_type = CurveType::Linear {
+        slope: Uint128::new(1),
+        scale: 1,
+    };
+    let mut suite = Suite::new(curve_type, 2, 8);
+
+    suite.run(&Action::Buy {
+        trader: 0,
+        amount: 500_000_000,
+    });
+    assert_eq!(suite.balance("alice"), Uint128::new(1000));
+    suite.run(&Action::Transfer {
+        trader: 0,
+        to: 1,
+        permille: 500,
+    });
+    suite.run(&Action::SendToSell {
+        trader: 1,
+        permille: 1000,
+    });
+    suite.run(&Action::Sell {
+        trader: 0,
+        permille: 1000,
+    });
+    suite.assert_invariants();
+
+    // everything was sold, so all reserve was paid out again
+    let held = suite
+        .app
+        .wrap()
+        .query_balance(&suite.bonding, DENOM)
+        .unwrap();
+    assert_eq!(held.amount, Uint128::zero());
+}
+
+fn staking_instantiate(rewards: RewardsDestination) -> InstantiateMsg {
+    // one token per ustake, so the curve reserve always equals the supply
+    InstantiateMsg {
+        name: "Bonded".to_string(),
+        symbol: "EPOXY".to_string(),
+        decimals: 6,
+        reserve_denom: DENOM.to_string(),
+        reserve_decimals: 6,
+        basket: None,
+        curve_type: CurveType::Constant {
+            value: Uint128::new(1),
+            scale: 0,
+        },
+        admin: None,
+        treasury: Some(TREASURY.to_string()),
+        batch: None,
+        vesting: None,
+        staking: Some(StakingConfig {
+            validator: VALIDATOR.to_string(),
+            delegated_share: Decimal::percent(80),
+            unbonding_period: UNBONDING_PERIOD,
+            rewards,
+        }),
+        factory: None,
+        marketing: None,
+    }
+}
+
+#[test]
+fn staking_needs_valid_config() {
+    let msg = staking_instantiate(RewardsDestination::Reserve);
+    let mut app = App::default();
+    let code_id = app.store_code(contract_bonding());
+    let err = app
+        .instantiate_contract(code_id, Addr::unchecked(OWNER), &msg, &[], "bonding", None)
+        .unwrap_err();
+    // the default app bonds a different denom than our reserve
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::InvalidStaking {}
+    );
+
+    // treasury mode needs a treasury
+    let mut msg = staking_instantiate(RewardsDestination::Treasury);
+    msg.treasury = None;
+    let err = app
+        .instantiate_contract(code_id, Addr::unchecked(OWNER), &msg, &[], "bonding", None)
+        .unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::InvalidStaking {}
+    );
+}
+
+#[test]
+fn staked_reserve_earns_rewards() {
+    let mut suite = Suite::instantiate(staking_instantiate(RewardsDestination::Reserve));
+
+    // 80% of a buy is delegated right away
+    suite.run(&Action::Buy {
+        trader: 0,
+        amount: 1_000_000,
+    });
+    assert_eq!(suite.delegation(), Uint128::new(800_000));
+    assert_eq!(
+        suite.bank_balance(suite.bonding.as_str()),
+        Uint128::new(200_000)
+    );
+    let staking = suite.staking();
+    assert_eq!(staking.delegated, Uint128::new(800_000));
+    assert_eq!(staking.liquid, Uint128::new(200_000));
+
+    // a year at 10% earns 80_000, which raises the backing of all 1_000_000 tokens by 8%
+    suite.advance(YEAR);
+    suite
+        .execute(OWNER, &ExecuteMsg::CollectRewards {}, &[])
+        .unwrap();
+    let curve = suite.curve_info();
+    assert_eq!(curve.reserve, Uint128::new(1_080_000));
+    assert_eq!(curve.supply, Uint128::new(1_000_000));
+    // and they get staked as well
+    assert_eq!(suite.delegation(), Uint128::new(864_000));
+
+    // sells can only be paid from the liquid buffer
+    let err = suite
+        .execute(
+            "alice",
+            &ExecuteMsg::Burn {
+                amount: Uint128::new(500_000),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::InsufficientLiquidity {
+            available: Uint128::new(216_000),
+            requested: Uint128::new(540_000),
+        }
+    );
+
+    // selling 150_000 tokens pays out 162_000, leaving the buffer below half its target
+    suite
+        .execute(
+            "alice",
+            &ExecuteMsg::Burn {
+                amount: Uint128::new(150_000),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(
+        suite.bank_balance("alice"),
+        Uint128::new(INITIAL_FUNDS - 1_000_000 + 162_000)
+    );
+    // so the stake is brought back down to 80% of the remaining 918_000
+    assert_eq!(suite.delegation(), Uint128::new(734_400));
+    let staking = suite.staking();
+    assert_eq!(staking.liquid, Uint128::new(54_000));
+    assert_eq!(
+        staking.unbonding,
+        vec![Unbonding {
+            amount: Uint128::new(129_600),
+            release_at: suite.app.block_info().time.plus_seconds(UNBONDING_PERIOD),
+        }]
+    );
+
+    // once unbonded, it is liquid again
+    suite.advance(UNBONDING_PERIOD);
+    suite
+        .app
+        .sudo(SudoMsg::Staking(StakingSudo::ProcessQueue {}))
+        .unwrap();
+    let staking = suite.staking();
+    assert_eq!(staking.liquid, Uint128::new(183_600));
+    assert_eq!(staking.unbonding, vec![]);
+    assert_eq!(
+        suite.bank_balance(suite.bonding.as_str()),
+        Uint128::new(183_600)
+    );
+
+    // another year on 734_400 staked raises the reserve by 73_440
+    suite.advance(YEAR);
+    suite
+        .execute(OWNER, &ExecuteMsg::CollectRewards {}, &[])
+        .unwrap();
+    assert_eq!(suite.curve_info().reserve, Uint128::new(991_440));
+
+    // which is 1.08^2 of backing for each of the 850_000 tokens left
+    suite
+        .execute(
+            "alice",
+            &ExecuteMsg::Burn {
+                amount: Uint128::new(150_000),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(
+        suite.bank_balance("alice"),
+        Uint128::new(INITIAL_FUNDS - 1_000_000 + 162_000 + 174_960)
+    );
+}
+
+#[test]
+fn rewards_can_go_to_treasury() {
+    let mut suite = Suite::instantiate(staking_instantiate(RewardsDestination::Treasury));
+    suite.run(&Action::Buy {
+        trader: 1,
+        amount: 1_000_000,
+    });
+    suite.advance(YEAR);
+    suite
+        .execute(OWNER, &ExecuteMsg::CollectRewards {}, &[])
+        .unwrap();
+
+    // the reserve stays the same
+    assert_eq!(suite.bank_balance(TREASURY), Uint128::new(80_000));
+    assert_eq!(suite.curve_info().reserve, Uint128::new(1_000_000));
+    assert_eq!(suite.delegation(), Uint128::new(800_000));
+
+    // only the contract may distribute the rewards
+    let err = suite
+        .execute(OWNER, &ExecuteMsg::_DistributeRewards {}, &[])
+        .unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::Unauthorized {}
+    );
+}
+
+fn create_curve(symbol: &str, decimals: u8, curve_type: CurveType) -> ExecuteMsg {
+    ExecuteMsg::CreateCurve {
+        name: format!("{} token", symbol),
+        symbol: symbol.to_string(),
+        decimals,
+        reserve_denom: DENOM.to_string(),
+        reserve_decimals: 6,
+        curve_type,
+        marketing: None,
+    }
+}
+
+#[test]
+fn factory_hosts_many_curves() {
+    let mut app = App::new(|router, _, storage| {
+        for trader in TRADERS {
+            router
+                .bank
+                .init_balance(
+                    storage,
+                    &Addr::unchecked(trader),
+                    coins(INITIAL_FUNDS, DENOM),
+                )
+                .unwrap();
+        }
+    });
+    let cw20_code_id = app.store_code(contract_cw20());
+    let code_id = app.store_code(contract_bonding());
+    let msg = InstantiateMsg {
+        name: "Launchpad".to_string(),
+        symbol: "LAUNCH".to_string(),
+        decimals: 6,
+        reserve_denom: DENOM.to_string(),
+        reserve_decimals: 6,
+        basket: None,
+        curve_type: CurveType::Constant {
+            value: Uint128::new(1),
+            scale: 0,
+        },
+        admin: None,
+        treasury: None,
+        batch: None,
+        vesting: None,
+        staking: None,
+        factory: Some(FactoryConfig { cw20_code_id }),
+        marketing: None,
+    };
+    let bonding = app
+        .instantiate_contract(code_id, Addr::unchecked(OWNER), &msg, &[], "bonding", None)
+        .unwrap();
+
+    // anyone can create curves, each gets its own token
+    let err = app
+        .execute_contract(
+            Addr::unchecked("alice"),
+            bonding.clone(),
+            &create_curve(
+                "WEI",
+                19,
+                CurveType::Constant {
+                    value: Uint128::new(1),
+                    scale: 0,
+                },
+            ),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::TooManyDecimals { max: 18 },
+        err.downcast().unwrap()
+    );
+    let linear = CurveType::Linear {
+        slope: Uint128::new(1),
+        scale: 1,
+    };
+    let constant = CurveType::Constant {
+        value: Uint128::new(15),
+        scale: 1,
+    };
+    for (symbol, decimals, curve_type) in [("LIN", 0, &linear), ("CON", 6, &constant)] {
+        let msg = create_curve(symbol, decimals, curve_type.clone());
+        app.execute_contract(Addr::unchecked(OWNER), bonding.clone(), &msg, &[])
+            .unwrap();
+    }
+    let hosted = |app: &App, curve_id| -> HostedCurveInfo {
+        app.wrap()
+            .query_wasm_smart(&bonding, &QueryMsg::HostedCurve { curve_id })
+            .unwrap()
+    };
+    let lin_token = Addr::unchecked(hosted(&app, 1).token);
+    let con_token = Addr::unchecked(hosted(&app, 2).token);
+    assert_ne!(lin_token, con_token);
+    let token_info: TokenInfoResponse = app
+        .wrap()
+        .query_wasm_smart(&lin_token, &Cw20QueryMsg::TokenInfo {})
+        .unwrap();
+    assert_eq!(token_info.symbol, "LIN");
+    // their creator is the marketing admin
+    let marketing: MarketingInfoResponse = app
+        .wrap()
+        .query_wasm_smart(&lin_token, &Cw20QueryMsg::MarketingInfo {})
+        .unwrap();
+    assert_eq!(marketing.marketing, Some(Addr::unchecked(OWNER)));
+
+    // 5 reserve buy 10 LIN (reserve is 0.05 * supply^2), and 1.5 buy 1 CON
+    let buy = |app: &mut App, trader: &str, curve_id, amount| {
+        app.execute_contract(
+            Addr::unchecked(trader),
+            bonding.clone(),
+            &ExecuteMsg::BuyHosted { curve_id },
+            &coins(amount, DENOM),
+        )
+    };
+    buy(&mut app, "alice", 1, 5_000_000).unwrap();
+    buy(&mut app, "bob", 2, 1_500_000).unwrap();
+    let balance = |app: &App, token: &Addr, trader: &str| {
+        let res: BalanceResponse = app
+            .wrap()
+            .query_wasm_smart(
+                token,
+                &Cw20QueryMsg::Balance {
+                    address: trader.to_string(),
+                },
+            )
+            .unwrap();
+        res.balance
+    };
+    assert_eq!(balance(&app, &lin_token, "alice"), Uint128::new(10));
+    assert_eq!(balance(&app, &con_token, "bob"), Uint128::new(1_000_000));
+
+    // the registry lists all curves with their current state
+    let res: HostedCurvesResponse = app
+        .wrap()
+        .query_wasm_smart(
+            &bonding,
+            &QueryMsg::HostedCurves {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        res.curves,
+        vec![
+            HostedCurveInfo {
+                curve_id: 1,
+                token: lin_token.to_string(),
+                curve_type: linear,
+                info: CurveInfoResponse {
+                    reserve: Uint128::new(5_000_000),
+                    supply: Uint128::new(10),
+                    spot_price: Decimal::one(),
+                    reserve_denom: DENOM.to_string(),
+                },
+            },
+            HostedCurveInfo {
+                curve_id: 2,
+                token: con_token.to_string(),
+                curve_type: constant,
+                info: CurveInfoResponse {
+                    reserve: Uint128::new(1_500_000),
+                    supply: Uint128::new(1_000_000),
+                    spot_price: Decimal::percent(150),
+                    reserve_denom: DENOM.to_string(),
+                },
+            },
+        ]
+    );
+    let res: HostedCurvesResponse = app
+        .wrap()
+        .query_wasm_smart(
+            &bonding,
+            &QueryMsg::HostedCurves {
+                start_after: Some(1),
+                limit: None,
+            },
+        )
+        .unwrap();
+    assert_eq!(res.curves.len(), 1);
+    assert_eq!(res.curves[0].curve_id, 2);
+
+    // selling 5 LIN back releases 3.75 reserve (to carl, as requested), the rest stays put
+    let sell = Cw20ExecuteMsg::Send {
+        contract: bonding.to_string(),
+        amount: Uint128::new(5),
+        msg: to_binary(&ReceiveMsg::Sell {
+            recipient: Some("carl".to_string()),
+        })
+        .unwrap(),
+    };
+    app.execute_contract(Addr::unchecked("alice"), lin_token.clone(), &sell, &[])
+        .unwrap();
+    let carl = app.wrap().query_balance("carl", DENOM).unwrap().amount;
+    assert_eq!(carl, Uint128::new(INITIAL_FUNDS + 3_750_000));
+    let info = hosted(&app, 1).info;
+    assert_eq!(info.reserve, Uint128::new(1_250_000));
+    assert_eq!(info.supply, Uint128::new(5));
+    let token_info: TokenInfoResponse = app
+        .wrap()
+        .query_wasm_smart(&lin_token, &Cw20QueryMsg::TokenInfo {})
+        .unwrap();
+    assert_eq!(token_info.total_supply, Uint128::new(5));
+    let held = app.wrap().query_balance(&bonding, DENOM).unwrap().amount;
+    assert_eq!(held, Uint128::new(1_250_000 + 1_500_000));
+
+    // only the factory can mint the hosted tokens
+    let mint = Cw20ExecuteMsg::Mint {
+        recipient: "alice".to_string(),
+        amount: Uint128::new(1),
+    };
+    app.execute_contract(Addr::unchecked("alice"), lin_token, &mint, &[])
+        .unwrap_err();
+    let err = buy(&mut app, "alice", 3, 1_000).unwrap_err();
+    assert_eq!(
+        ContractError::UnknownCurve { curve_id: 3 },
+        err.downcast().unwrap()
+    );
+}
+
+#[test]
+fn curves_are_only_hosted_in_factory_mode() {
+    let curve_type = CurveType::Constant {
+        value: Uint128::new(1),
+        scale: 0,
+    };
+    let mut suite = Suite::new(curve_type.clone(), 6, 6);
+    let err = suite
+        .execute(OWNER, &create_curve("NOPE", 6, curve_type), &[])
+        .unwrap_err();
+    assert_eq!(ContractError::NotFactoryMode {}, err.downcast().unwrap());
+}
+
+#[test]
+fn factory_and_staking_modes_together() {
+    const OTHER_DENOM: &str = "uatom";
+    // the bonding code is stored first, so the cw20 code stored right after gets id 2
+    let mut msg = staking_instantiate(RewardsDestination::Treasury);
+    msg.factory = Some(FactoryConfig { cw20_code_id: 2 });
+    let mut suite = Suite::instantiate(msg);
+    assert_eq!(suite.app.store_code(contract_cw20()), 2);
+    suite.app.init_modules(|router, _, storage| {
+        router
+            .bank
+            .init_balance(
+                storage,
+                &Addr::unchecked("bob"),
+                coins(1_000_000, OTHER_DENOM),
+            )
+            .unwrap();
+    });
+
+    // curves in the staked denom would share the balance that rewards are taken from
+    let curve_type = CurveType::Constant {
+        value: Uint128::new(1),
+        scale: 0,
+    };
+    let err = suite
+        .execute(OWNER, &create_curve("STAKED", 6, curve_type.clone()), &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::StakedDenom {
+            denom: DENOM.to_string()
+        },
+        err.downcast().unwrap()
+    );
+
+    // curves in other denoms are fine
+    let msg = ExecuteMsg::CreateCurve {
+        name: "Other token".to_string(),
+        symbol: "OTHER".to_string(),
+        decimals: 6,
+        reserve_denom: OTHER_DENOM.to_string(),
+        reserve_decimals: 6,
+        curve_type,
+        marketing: None,
+    };
+    suite.execute(OWNER, &msg, &[]).unwrap();
+    suite
+        .execute(
+            "bob",
+            &ExecuteMsg::BuyHosted { curve_id: 1 },
+            &coins(500_000, OTHER_DENOM),
+        )
+        .unwrap();
+
+    // staking the main reserve works as before, and the hosted reserve stays put
+    suite.run(&Action::Buy {
+        trader: 0,
+        amount: 1_000_000,
+    });
+    suite.advance(YEAR);
+    suite
+        .execute(OWNER, &ExecuteMsg::CollectRewards {}, &[])
+        .unwrap();
+    assert_eq!(suite.bank_balance(TREASURY), Uint128::new(80_000));
+    assert_eq!(suite.curve_info().reserve, Uint128::new(1_000_000));
+    let held = suite
+        .app
+        .wrap()
+        .query_balance(&suite.bonding, OTHER_DENOM)
+        .unwrap();
+    assert_eq!(held.amount, Uint128::new(500_000));
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn reserve_matches_bank_balance(
+        curve_type in any_curve(),
+        decimals in 0u8..=9,
+        reserve_decimals in 0u8..=9,
+        actions in prop::collection::vec(any_action(), 1..40),
+    ) {
+        let mut suite = Suite::new(curve_type, decimals, reserve_decimals);
+        for action in &actions {
+            suite.run(action);
+            suite.assert_invariants();
+        }
+    }
+}
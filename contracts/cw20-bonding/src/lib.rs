@@ -1,7 +1,16 @@
+pub mod basket;
+pub mod batch;
 pub mod contract;
 pub mod curves;
 mod error;
+pub mod factory;
+mod integration_test;
+pub mod marketing;
+pub mod migrations;
 pub mod msg;
+pub mod oracle;
+pub mod staking;
 pub mod state;
+pub mod vesting;
 
 pub use crate::error::ContractError;
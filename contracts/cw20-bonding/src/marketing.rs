@@ -0,0 +1,202 @@
+use cosmwasm_std::{Addr, DepsMut, Env, MessageInfo};
+use cw20::MarketingInfoResponse;
+use cw20_base::contract::execute_upload_logo;
+use cw20_base::msg::InstantiateMarketingInfo;
+use cw20_base::state::MARKETING_INFO;
+
+use crate::error::ContractError;
+
+/// Returns the marketing info with `owner` as marketing admin, unless it names another one
+pub fn with_marketing_admin(
+    marketing: Option<InstantiateMarketingInfo>,
+    owner: &Addr,
+) -> InstantiateMarketingInfo {
+    let mut marketing = marketing.unwrap_or(InstantiateMarketingInfo {
+        project: None,
+        description: None,
+        marketing: None,
+        logo: None,
+    });
+    marketing.marketing.get_or_insert_with(|| owner.to_string());
+    marketing
+}
+
+/// Stores the marketing info of the supply token in cw20-base format. This is always stored,
+/// so `owner` can add a project, description or logo later.
+pub fn init_marketing(
+    mut deps: DepsMut,
+    env: &Env,
+    marketing: Option<InstantiateMarketingInfo>,
+    owner: &Addr,
+) -> Result<(), ContractError> {
+    let marketing = with_marketing_admin(marketing, owner);
+    let admin = deps
+        .api
+        .addr_validate(marketing.marketing.as_deref().unwrap_or_default())?;
+    let data = MarketingInfoResponse {
+        project: marketing.project,
+        description: marketing.description,
+        marketing: Some(admin.clone()),
+        logo: None,
+    };
+    MARKETING_INFO.save(deps.storage, &data)?;
+
+    // cw20-base only exposes its logo checks through the upload, so do that as the admin
+    if let Some(logo) = marketing.logo {
+        let info = MessageInfo {
+            sender: admin,
+            funds: vec![],
+        };
+        execute_upload_logo(deps.branch(), env.clone(), info, logo)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate, query};
+    use crate::msg::{CurveType, ExecuteMsg, InstantiateMsg, QueryMsg};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{from_binary, Deps, Uint128};
+    use cw20::{DownloadLogoResponse, EmbeddedLogo, Logo, LogoInfo};
+
+    const DENOM: &str = "ustake";
+    const CREATOR: &str = "creator";
+    const OWNER: &str = "owner";
+
+    const PNG_HEADER: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+    fn setup_test(
+        deps: DepsMut,
+        admin: Option<&str>,
+        marketing: Option<InstantiateMarketingInfo>,
+    ) -> Result<(), ContractError> {
+        let msg = InstantiateMsg {
+            name: "Bonded".to_string(),
+            symbol: "EPOXY".to_string(),
+            decimals: 6,
+            reserve_denom: DENOM.to_string(),
+            reserve_decimals: 6,
+            basket: None,
+            curve_type: CurveType::Constant {
+                value: Uint128::new(1),
+                scale: 0,
+            },
+            admin: admin.map(String::from),
+            treasury: None,
+            batch: None,
+            vesting: None,
+            staking: None,
+            factory: None,
+            marketing,
+        };
+        instantiate(deps, mock_env(), mock_info(CREATOR, &[]), msg).map(|_| ())
+    }
+
+    fn marketing_info(deps: Deps) -> MarketingInfoResponse {
+        from_binary(&query(deps, mock_env(), QueryMsg::MarketingInfo {}).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn owner_is_default_marketing_admin() {
+        let mut deps = mock_dependencies();
+        let marketing = InstantiateMarketingInfo {
+            project: Some("https://epoxy.example".to_string()),
+            description: Some("Sticks to the curve".to_string()),
+            marketing: None,
+            logo: Some(Logo::Url("https://epoxy.example/logo.png".to_string())),
+        };
+        setup_test(deps.as_mut(), Some(OWNER), Some(marketing)).unwrap();
+        assert_eq!(
+            marketing_info(deps.as_ref()),
+            MarketingInfoResponse {
+                project: Some("https://epoxy.example".to_string()),
+                description: Some("Sticks to the curve".to_string()),
+                marketing: Some(Addr::unchecked(OWNER)),
+                logo: Some(LogoInfo::Url("https://epoxy.example/logo.png".to_string())),
+            }
+        );
+
+        // only the owner may update it
+        let update = ExecuteMsg::UpdateMarketing {
+            project: None,
+            description: Some("Even stickier".to_string()),
+            marketing: None,
+        };
+        let info = mock_info(CREATOR, &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, update.clone()).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::Base(cw20_base::ContractError::Unauthorized {})
+        );
+        let info = mock_info(OWNER, &[]);
+        execute(deps.as_mut(), mock_env(), info, update).unwrap();
+        let info = marketing_info(deps.as_ref());
+        assert_eq!(info.description, Some("Even stickier".to_string()));
+        assert_eq!(info.project, Some("https://epoxy.example".to_string()));
+
+        // and upload a logo to store on chain
+        let logo = Logo::Embedded(EmbeddedLogo::Png(PNG_HEADER.into()));
+        let info = mock_info(OWNER, &[]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::UploadLogo(logo),
+        )
+        .unwrap();
+        assert_eq!(marketing_info(deps.as_ref()).logo, Some(LogoInfo::Embedded));
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::DownloadLogo {}).unwrap();
+        assert_eq!(
+            from_binary::<DownloadLogoResponse>(&res).unwrap(),
+            DownloadLogoResponse {
+                mime_type: "image/png".to_string(),
+                data: PNG_HEADER.into(),
+            }
+        );
+    }
+
+    #[test]
+    fn fixed_curves_are_marketed_by_the_instantiator() {
+        let mut deps = mock_dependencies();
+        setup_test(deps.as_mut(), None, None).unwrap();
+        assert_eq!(
+            marketing_info(deps.as_ref()),
+            MarketingInfoResponse {
+                project: None,
+                description: None,
+                marketing: Some(Addr::unchecked(CREATOR)),
+                logo: None,
+            }
+        );
+        // there is no logo to download yet
+        query(deps.as_ref(), mock_env(), QueryMsg::DownloadLogo {}).unwrap_err();
+
+        // the admin can be handed over to someone else
+        let update = ExecuteMsg::UpdateMarketing {
+            project: Some("https://epoxy.example".to_string()),
+            description: None,
+            marketing: Some("agency".to_string()),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), update).unwrap();
+        let info = marketing_info(deps.as_ref());
+        assert_eq!(info.marketing, Some(Addr::unchecked("agency")));
+    }
+
+    #[test]
+    fn logos_are_checked_on_instantiate() {
+        let mut deps = mock_dependencies();
+        let marketing = InstantiateMarketingInfo {
+            project: None,
+            description: None,
+            marketing: Some("agency".to_string()),
+            logo: Some(Logo::Embedded(EmbeddedLogo::Png(b"not a png".into()))),
+        };
+        let err = setup_test(deps.as_mut(), Some(OWNER), Some(marketing)).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::Base(cw20_base::ContractError::InvalidPngHeader {})
+        );
+    }
+}
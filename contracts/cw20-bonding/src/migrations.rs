@@ -0,0 +1,307 @@
+//! Every change to the layout of `CurveState` (or other stored state) bumps `CURRENT_STATE_VERSION` and
+//! adds a step here, which converts the stored state from the previous version. The old layouts are kept
+//! as private types, so `migrate_state` can bring any older contract up to date, one version
+//! at a time.
+//!
+//! Items that were added later and are always loaded don't exist in older contracts either.
+//! `migrate_state` starts those out the way `instantiate` would have.
+
+use cosmwasm_std::{Addr, Env, StdResult, Storage, Uint128};
+use cw_storage_plus::Item;
+
+use crate::error::ContractError;
+use crate::oracle::init_price_accumulator;
+use crate::state::{BasketComponent, CurveState, CURVE_STATE, PRICE_ACCUMULATOR, STATE_VERSION};
+
+/// 1. the original layout, with a single `reserve_denom`
+/// 2. `reserve_denom` became a basket of denoms
+pub const CURRENT_STATE_VERSION: u16 = 2;
+
+mod v1 {
+    use super::*;
+    use crate::curves::DecimalPlaces;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    pub struct CurveState {
+        pub reserve: Uint128,
+        pub supply: Uint128,
+        pub reserve_denom: String,
+        pub decimals: DecimalPlaces,
+    }
+
+    pub const CURVE_STATE: Item<CurveState> = Item::new("curve_state");
+}
+
+/// The item behind `ADMIN`, which only offers `load`
+const ADMIN_ITEM: Item<Option<Addr>> = Item::new("admin");
+
+/// Brings the stored state up to `CURRENT_STATE_VERSION`, and returns the version it started at
+pub fn migrate_state(storage: &mut dyn Storage, env: &Env) -> Result<u16, ContractError> {
+    // only the original contract predates `STATE_VERSION`
+    let from = STATE_VERSION.may_load(storage)?.unwrap_or(1);
+    if from > CURRENT_STATE_VERSION {
+        return Err(ContractError::UnknownStateVersion { version: from });
+    }
+    if from == 1 {
+        v1_to_v2(storage)?;
+    }
+    STATE_VERSION.save(storage, &CURRENT_STATE_VERSION)?;
+    init_missing(storage, env)?;
+    Ok(from)
+}
+
+/// Starts out the items the contract loads unconditionally, if they predate this contract
+fn init_missing(storage: &mut dyn Storage, env: &Env) -> StdResult<()> {
+    // without an admin, the curve stays fixed like it always was
+    if ADMIN_ITEM.may_load(storage)?.is_none() {
+        ADMIN_ITEM.save(storage, &None)?;
+    }
+    // the price history starts now. The next update adds the current spot price since then
+    if PRICE_ACCUMULATOR.may_load(storage)?.is_none() {
+        init_price_accumulator(storage, env.block.time)?;
+    }
+    Ok(())
+}
+
+fn v1_to_v2(storage: &mut dyn Storage) -> StdResult<()> {
+    let old = v1::CURVE_STATE.load(storage)?;
+    let state = CurveState {
+        reserve: old.reserve,
+        supply: old.supply,
+        basket: vec![BasketComponent {
+            denom: old.reserve_denom,
+            weight: Uint128::new(1),
+        }],
+        decimals: old.decimals,
+    };
+    CURVE_STATE.save(storage, &state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate, migrate, query, CONTRACT_NAME};
+    use crate::curves::DecimalPlaces;
+    use crate::msg::{CurveType, ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, TwapResponse};
+    use crate::state::{ADMIN, CURVE_TYPE};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{attr, coins, from_binary, BankMsg, Decimal, DepsMut, SubMsg};
+    use cw20::MarketingInfoResponse;
+    use cw20_base::state::{MinterData, TokenInfo, BALANCES, MARKETING_INFO, TOKEN_INFO};
+
+    const DENOM: &str = "ustake";
+    const CREATOR: &str = "creator";
+    const INVESTOR: &str = "investor";
+
+    fn curve_type() -> CurveType {
+        CurveType::Constant {
+            value: Uint128::new(1),
+            scale: 0,
+        }
+    }
+
+    fn instantiate_msg() -> InstantiateMsg {
+        InstantiateMsg {
+            name: "Bonded".to_string(),
+            symbol: "EPOXY".to_string(),
+            decimals: 6,
+            reserve_denom: DENOM.to_string(),
+            reserve_decimals: 6,
+            basket: None,
+            curve_type: curve_type(),
+            admin: None,
+            treasury: None,
+            batch: None,
+            vesting: None,
+            staking: None,
+            factory: None,
+            marketing: None,
+        }
+    }
+
+    /// Instantiates and buys 1000 tokens, so there is some state to migrate
+    fn setup_test(mut deps: DepsMut) {
+        let info = mock_info(CREATOR, &[]);
+        instantiate(deps.branch(), mock_env(), info, instantiate_msg()).unwrap();
+        let info = mock_info(INVESTOR, &coins(1_000, DENOM));
+        execute(deps, mock_env(), info, ExecuteMsg::Buy {}).unwrap();
+    }
+
+    /// Writes the keys the original contract stored after `INVESTOR` bought 1000 tokens,
+    /// and nothing else
+    fn setup_original_layout(deps: DepsMut, env: &Env) {
+        let storage = deps.storage;
+        cw2::set_contract_version(storage, CONTRACT_NAME, "0.12.0").unwrap();
+        let token = TokenInfo {
+            name: "Bonded".to_string(),
+            symbol: "EPOXY".to_string(),
+            decimals: 6,
+            total_supply: Uint128::new(1_000),
+            mint: Some(MinterData {
+                minter: env.contract.address.clone(),
+                cap: None,
+            }),
+        };
+        TOKEN_INFO.save(storage, &token).unwrap();
+        let investor = Addr::unchecked(INVESTOR);
+        BALANCES
+            .save(storage, &investor, &Uint128::new(1_000))
+            .unwrap();
+        let old = v1::CurveState {
+            reserve: Uint128::new(1_000),
+            supply: Uint128::new(1_000),
+            reserve_denom: DENOM.to_string(),
+            decimals: DecimalPlaces::new(6, 6),
+        };
+        v1::CURVE_STATE.save(storage, &old).unwrap();
+        CURVE_TYPE.save(storage, &curve_type()).unwrap();
+    }
+
+    fn all_queries() -> Vec<QueryMsg> {
+        vec![
+            QueryMsg::CurveInfo {},
+            QueryMsg::Twap { window_seconds: 10 },
+            QueryMsg::Observations {},
+            QueryMsg::Vesting {
+                address: INVESTOR.to_string(),
+            },
+            QueryMsg::VestingSchedules {
+                address: INVESTOR.to_string(),
+                start_after: None,
+                limit: None,
+            },
+            QueryMsg::BasketInfo {},
+            QueryMsg::Staking {},
+            QueryMsg::Batch {},
+            QueryMsg::Orders {
+                start_after: None,
+                limit: None,
+            },
+            QueryMsg::HostedCurve { curve_id: 1 },
+            QueryMsg::HostedCurves {
+                start_after: None,
+                limit: None,
+            },
+            QueryMsg::TokenInfo {},
+            QueryMsg::Balance {
+                address: INVESTOR.to_string(),
+            },
+            QueryMsg::Allowance {
+                owner: INVESTOR.to_string(),
+                spender: CREATOR.to_string(),
+            },
+            QueryMsg::MarketingInfo {},
+            QueryMsg::DownloadLogo {},
+        ]
+    }
+
+    #[test]
+    fn migrates_original_layout() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        setup_original_layout(deps.as_mut(), &env);
+
+        let res = migrate(deps.as_mut(), env.clone(), MigrateMsg {}).unwrap();
+        assert!(res.attributes.contains(&attr("from_version", "0.12.0")));
+        assert!(res.attributes.contains(&attr("from_state_version", "1")));
+        assert_eq!(
+            STATE_VERSION.load(&deps.storage).unwrap(),
+            CURRENT_STATE_VERSION
+        );
+        let state = CURVE_STATE.load(&deps.storage).unwrap();
+        assert_eq!(
+            state,
+            CurveState {
+                reserve: Uint128::new(1_000),
+                supply: Uint128::new(1_000),
+                basket: vec![BasketComponent {
+                    denom: DENOM.to_string(),
+                    weight: Uint128::new(1),
+                }],
+                decimals: DecimalPlaces::new(6, 6),
+            }
+        );
+        // there was no admin, and still isn't. So nobody manages the marketing info either
+        assert_eq!(ADMIN.get(deps.as_ref()).unwrap(), None);
+        let marketing = MARKETING_INFO.load(&deps.storage).unwrap();
+        assert_eq!(marketing, MarketingInfoResponse::default());
+
+        // buying works and starts the price history
+        env.block.time = env.block.time.plus_seconds(10);
+        let info = mock_info(INVESTOR, &coins(1_000, DENOM));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Buy {}).unwrap();
+        let state = CURVE_STATE.load(&deps.storage).unwrap();
+        assert_eq!(state.reserve, Uint128::new(2_000));
+        assert_eq!(state.supply, Uint128::new(2_000));
+
+        // selling works as before
+        env.block.time = env.block.time.plus_seconds(10);
+        let info = mock_info(INVESTOR, &[]);
+        let burn = ExecuteMsg::Burn {
+            amount: Uint128::new(500),
+        };
+        let res = execute(deps.as_mut(), env.clone(), info, burn).unwrap();
+        assert_eq!(
+            res.messages[0],
+            SubMsg::new(BankMsg::Send {
+                to_address: INVESTOR.to_string(),
+                amount: coins(500, DENOM),
+            })
+        );
+        let state = CURVE_STATE.load(&deps.storage).unwrap();
+        assert_eq!(state.reserve, Uint128::new(1_500));
+        assert_eq!(state.supply, Uint128::new(1_500));
+
+        let twap: TwapResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::Twap { window_seconds: 10 },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(twap.price, Decimal::one());
+
+        // every query answers just like on a contract instantiated with the current code
+        let mut fresh = mock_dependencies();
+        setup_test(fresh.as_mut());
+        for msg in all_queries() {
+            let migrated = query(deps.as_ref(), env.clone(), msg.clone());
+            let current = query(fresh.as_ref(), env.clone(), msg.clone());
+            assert_eq!(migrated.is_ok(), current.is_ok(), "{:?}", msg);
+        }
+
+        // migrating again changes nothing
+        let res = migrate(deps.as_mut(), env, MigrateMsg {}).unwrap();
+        assert!(res.attributes.contains(&attr("from_state_version", "2")));
+        assert_eq!(CURVE_STATE.load(&deps.storage).unwrap(), state);
+    }
+
+    #[test]
+    fn only_migrates_older_versions_of_this_contract() {
+        let mut deps = mock_dependencies();
+        setup_test(deps.as_mut());
+
+        let storage = deps.as_mut().storage;
+        cw2::set_contract_version(storage, "crates.io:cw20-base", "0.12.1").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CannotMigrate {
+                previous_contract: "crates.io:cw20-base".to_string()
+            }
+        );
+
+        let storage = deps.as_mut().storage;
+        cw2::set_contract_version(storage, CONTRACT_NAME, "99.0.0").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CannotDowngrade {
+                previous_version: "99.0.0".to_string()
+            }
+        );
+    }
+}
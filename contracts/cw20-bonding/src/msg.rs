@@ -2,8 +2,13 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::curves::{decimal, Constant, Curve, DecimalPlaces, Linear, SquareRoot};
+use crate::state::{
+    BasketComponent, BatchConfig, FactoryConfig, Observation, OrderSide, Settlement, StakingConfig,
+    Unbonding, VestingConfig, VestingSchedule,
+};
 use cosmwasm_std::{Binary, Decimal, Uint128};
-use cw20::Expiration;
+use cw20::{Cw20ReceiveMsg, Expiration, Logo};
+use cw20_base::msg::InstantiateMarketingInfo;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
@@ -20,14 +25,44 @@ pub struct InstantiateMsg {
     /// number of decimal places for the reserve token, needed for proper curve math.
     /// Same format as decimals above, eg. if it is uatom, where 1 unit is 10^-6 ATOM, use 6 here
     pub reserve_decimals: u8,
+    /// if set, this is an index token backed by a fixed-ratio basket of several native denoms,
+    /// starting with `reserve_denom`. Buys must pay in these proportions, and sells pay out every
+    /// component. The curve prices basket units, and `reserve_decimals` applies to them
+    pub basket: Option<Vec<BasketComponent>>,
 
     /// enum to store the curve parameters used for this contract
     /// if you want to add a custom Curve, you should make a new contract that imports this one.
     /// write a custom `instantiate`, and then dispatch `your::execute` -> `cw20_bonding::do_execute`
     /// with your custom curve as a parameter (and same with `query` -> `do_query`)
     pub curve_type: CurveType,
+
+    /// admin may retune the curve later with `UpdateCurve`. If unset, the curve can never change
+    pub admin: Option<String>,
+    /// treasury receives any reserve freed up by a curve update. Defaults to the admin
+    pub treasury: Option<String>,
+
+    /// if set, this enables batch auction mode: buys and sells are queued during each epoch,
+    /// and then cleared all at once at a single price with `SettleBatch`
+    pub batch: Option<BatchConfig>,
+
+    /// if set, purchased tokens are held by the contract and vest linearly for the buyer,
+    /// who has to claim them with `ClaimVested`
+    pub vesting: Option<VestingConfig>,
+
+    /// if set, a share of the reserve is delegated to a validator, while the rest stays liquid
+    /// for sells. Needs a single reserve denom, which must be the chain's bonded denom
+    pub staking: Option<StakingConfig>,
+    /// if set, this contract can host any number of further curves with `CreateCurve`,
+    /// each with its own cw20 token (factory mode)
+    pub factory: Option<FactoryConfig>,
+    /// project, description and logo of the supply token, as in cw20-base. The marketing
+    /// admin defaults to the admin, or the instantiator if there is none
+    pub marketing: Option<InstantiateMarketingInfo>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {}
+
 pub type CurveFn = Box<dyn Fn(DecimalPlaces) -> Box<dyn Curve>>;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -70,8 +105,60 @@ impl CurveType {
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
     /// Buy will attempt to purchase as many supply tokens as possible.
-    /// You must send only reserve tokens in that message
+    /// You must send only reserve tokens in that message.
+    /// In batch mode, this queues a buy order for the current batch instead.
     Buy {},
+    /// ClaimVested transfers the tokens that have vested for the sender so far, from up to
+    /// `limit` of their oldest schedules
+    ClaimVested { limit: Option<u32> },
+    /// CancelOrder returns the escrowed tokens of a queued order to its owner (batch mode only)
+    CancelOrder { order_id: u64 },
+    /// SettleBatch clears all queued orders at a single price once the epoch has ended,
+    /// and starts the next batch (batch mode only). Anyone can call this. Each call fills up
+    /// to `limit` orders, until all are filled no new orders are taken.
+    SettleBatch { limit: Option<u32> },
+
+    /// SellTo burns `amount` of the sender's supply tokens, like Burn, but pays the
+    /// released reserve to `recipient` instead of the sender
+    SellTo { amount: Uint128, recipient: String },
+    /// Receive sells supply tokens that were sent here with `Send`, which lets other contracts
+    /// sell without setting up an allowance first. Only accepts this contract's own token,
+    /// and the tokens of hosted curves.
+    Receive(Cw20ReceiveMsg),
+
+    /// CollectRewards withdraws the staking rewards of the delegated reserve, and adds them to
+    /// the reserve or sends them to the treasury, as configured (staking mode only).
+    /// Anyone can call this
+    CollectRewards {},
+    /// _DistributeRewards can only be called by the contract itself, after the rewards have
+    /// been withdrawn as part of CollectRewards
+    _DistributeRewards {},
+
+    /// CreateCurve hosts a new plain bonding curve in this contract (factory mode only).
+    /// Its token is instantiated as a separate cw20 contract, which only this contract may
+    /// mint. Anyone can call this, the new curve id is returned in the `curve_id` attribute.
+    /// The caller is the token's marketing admin, unless `marketing` names another one.
+    CreateCurve {
+        name: String,
+        symbol: String,
+        decimals: u8,
+        reserve_denom: String,
+        reserve_decimals: u8,
+        curve_type: CurveType,
+        marketing: Option<InstantiateMarketingInfo>,
+    },
+    /// BuyHosted buys tokens of a hosted curve, paid in its reserve denom (factory mode only).
+    /// They are sold back by sending them here with `ReceiveMsg::Sell`.
+    BuyHosted { curve_id: u64 },
+
+    /// UpdateCurve replaces the stored curve (admin only). Any shortfall of the reserve to what
+    /// the new curve requires at the current supply must be sent along with this message in the
+    /// reserve denom (excess is refunded). Any surplus stays in the reserve as extra backing,
+    /// unless `sweep_surplus` is set, which sends it to the treasury
+    UpdateCurve {
+        curve_type: CurveType,
+        sweep_surplus: bool,
+    },
 
     /// Implements CW20. Transfer is a base message to move tokens to another account without triggering actions
     Transfer { recipient: String, amount: Uint128 },
@@ -117,6 +204,27 @@ pub enum ExecuteMsg {
     },
     /// Implements CW20 "approval" extension. Destroys tokens forever
     BurnFrom { owner: String, amount: Uint128 },
+    /// Implements CW20 "marketing" extension. If a field is not specified, it is not modified.
+    /// Only the marketing admin may update it, and an empty string unsets a field.
+    UpdateMarketing {
+        /// A URL pointing to the project behind this token.
+        project: Option<String>,
+        /// A longer description of the token and it's utility. Designed for tooltips or such
+        description: Option<String>,
+        /// The address (if any) who can update this data structure
+        marketing: Option<String>,
+    },
+    /// Implements CW20 "marketing" extension. If set as the "marketing" role on the contract,
+    /// upload a new URL, SVG, or PNG for the token
+    UploadLogo(Logo),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveMsg {
+    /// Sell all tokens sent along, and pay the reserve to `recipient`
+    /// (defaults to the account that sent the tokens)
+    Sell { recipient: Option<String> },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -124,6 +232,37 @@ pub enum ExecuteMsg {
 pub enum QueryMsg {
     /// Returns the reserve and supply quantities, as well as the spot price to buy 1 token
     CurveInfo {},
+    /// Returns the time-weighted average spot price over the last `window_seconds`.
+    /// Fails if the stored observations don't reach back that far.
+    Twap { window_seconds: u64 },
+    /// Returns all stored price observations, oldest first
+    Observations {},
+    /// Returns how many tokens bought by this address are claimable, and how many still vest
+    Vesting { address: String },
+    /// Lists the vesting schedules of this address, by start
+    VestingSchedules {
+        address: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Returns the components of the reserve basket, and how much of each backs the supply
+    BasketInfo {},
+    /// Returns how much of the reserve is delegated, unbonding, and liquid (staking mode only)
+    Staking {},
+    /// Returns the current batch (batch mode only)
+    Batch {},
+    /// Returns the open orders of the current batch, by order id
+    Orders {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Returns a curve hosted in factory mode
+    HostedCurve { curve_id: u64 },
+    /// Lists the curves hosted in factory mode, by id
+    HostedCurves {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
 
     /// Implements CW20. Returns the current balance of the given address, 0 if unset.
     Balance { address: String },
@@ -132,6 +271,12 @@ pub enum QueryMsg {
     /// Implements CW20 "allowance" extension.
     /// Returns how much spender can use from owner account, 0 if unset.
     Allowance { owner: String, spender: String },
+    /// Implements CW20 "marketing" extension.
+    /// Returns project, description, marketing admin and logo info of the token
+    MarketingInfo {},
+    /// Implements CW20 "marketing" extension.
+    /// Downloads the embedded logo data (if stored on chain). Errors if no logo data is stored.
+    DownloadLogo {},
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -141,5 +286,97 @@ pub struct CurveInfoResponse {
     // how many supply tokens have been issued
     pub supply: Uint128,
     pub spot_price: Decimal,
+    // the first component of the reserve basket
     pub reserve_denom: String,
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BasketInfoResponse {
+    /// how many basket units back the supply (the curve's reserve)
+    pub reserve: Uint128,
+    pub components: Vec<BasketHolding>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BasketHolding {
+    pub denom: String,
+    /// how many of this denom make up one basket unit
+    pub weight: Uint128,
+    /// how many of this denom the contract holds as reserve
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TwapResponse {
+    pub price: Decimal,
+    pub window_seconds: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ObservationsResponse {
+    pub observations: Vec<Observation>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BatchResponse {
+    pub id: u64,
+    pub config: BatchConfig,
+    pub ends: Expiration,
+    pub total_buy: Uint128,
+    pub total_sell: Uint128,
+    pub orders: u32,
+    /// the previous batch, while its orders are still being filled
+    pub settlement: Option<Settlement>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OrderInfo {
+    pub order_id: u64,
+    pub owner: String,
+    pub recipient: String,
+    pub side: OrderSide,
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OrdersResponse {
+    pub orders: Vec<OrderInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VestingResponse {
+    /// tokens that have vested but were not claimed yet, these can be claimed now
+    pub vested: Uint128,
+    /// tokens that are still vesting
+    pub unvested: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VestingSchedulesResponse {
+    pub schedules: Vec<VestingSchedule>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StakingResponse {
+    pub config: StakingConfig,
+    /// how much of the reserve is currently delegated
+    pub delegated: Uint128,
+    /// undelegated reserve that is not yet back in the contract's balance
+    pub unbonding: Vec<Unbonding>,
+    /// how much of the reserve can be paid out to sellers right now
+    pub liquid: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HostedCurveInfo {
+    pub curve_id: u64,
+    /// the cw20 contract of this curve's supply token
+    pub token: String,
+    pub curve_type: CurveType,
+    pub info: CurveInfoResponse,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HostedCurvesResponse {
+    pub curves: Vec<HostedCurveInfo>,
+}
@@ -0,0 +1,294 @@
+use std::convert::TryFrom;
+
+use cosmwasm_std::{
+    Decimal, Decimal256, Deps, Env, StdError, StdResult, Storage, Timestamp, Uint128, Uint256,
+};
+
+use crate::msg::{CurveFn, ObservationsResponse, TwapResponse};
+use crate::state::{
+    Observation, PriceAccumulator, CURVE_STATE, OBSERVATIONS, OBSERVATION_CAPACITY,
+    PRICE_ACCUMULATOR,
+};
+
+/// Starts the accumulator at zero, with a first observation at instantiation time
+pub fn init_price_accumulator(storage: &mut dyn Storage, time: Timestamp) -> StdResult<()> {
+    let acc = PriceAccumulator {
+        price_cumulative: Decimal256::zero(),
+        last_update: time,
+        observations: 0,
+    };
+    record_observation(storage, acc)
+}
+
+/// Adds the spot price that was valid since the last update, weighted by the seconds elapsed.
+/// This must be called *before* the supply (or curve) changes, with the old spot price.
+/// Like Uniswap v2, we only record one observation per second (the first trade wins).
+pub fn update_price_accumulator(
+    storage: &mut dyn Storage,
+    time: Timestamp,
+    spot_price: Decimal,
+) -> StdResult<()> {
+    let mut acc = PRICE_ACCUMULATOR.load(storage)?;
+    if time.seconds() <= acc.last_update.seconds() {
+        return Ok(());
+    }
+    acc.price_cumulative = accumulate(acc.price_cumulative, spot_price, acc.last_update, time);
+    acc.last_update = time;
+    record_observation(storage, acc)
+}
+
+fn record_observation(storage: &mut dyn Storage, mut acc: PriceAccumulator) -> StdResult<()> {
+    let observation = Observation {
+        timestamp: acc.last_update,
+        price_cumulative: acc.price_cumulative,
+    };
+    OBSERVATIONS.save(
+        storage,
+        acc.observations % OBSERVATION_CAPACITY,
+        &observation,
+    )?;
+    acc.observations += 1;
+    PRICE_ACCUMULATOR.save(storage, &acc)
+}
+
+/// cumulative + price * (to - from), in seconds
+fn accumulate(
+    cumulative: Decimal256,
+    price: Decimal,
+    from: Timestamp,
+    to: Timestamp,
+) -> Decimal256 {
+    let elapsed = to.seconds().saturating_sub(from.seconds());
+    let added = Uint256::from(price.atomics()) * Uint256::from(elapsed);
+    cumulative + Decimal256::new(added)
+}
+
+/// Returns the stored observations in chronological order
+fn load_observations(deps: Deps, acc: &PriceAccumulator) -> StdResult<Vec<Observation>> {
+    let stored = acc.observations.min(OBSERVATION_CAPACITY);
+    (acc.observations - stored..acc.observations)
+        .map(|i| OBSERVATIONS.load(deps.storage, i % OBSERVATION_CAPACITY))
+        .collect()
+}
+
+pub fn query_twap(
+    deps: Deps,
+    env: Env,
+    curve_fn: CurveFn,
+    window_seconds: u64,
+) -> StdResult<TwapResponse> {
+    if window_seconds == 0 {
+        return Err(StdError::generic_err(
+            "TWAP window must be at least one second",
+        ));
+    }
+    let acc = PRICE_ACCUMULATOR.load(deps.storage)?;
+    let observations = load_observations(deps, &acc)?;
+
+    // extend the accumulator to now with the current spot price
+    let state = CURVE_STATE.load(deps.storage)?;
+    let spot_price = curve_fn(state.decimals).spot_price(state.supply)?;
+    let now = env.block.time;
+    let cumulative_now = accumulate(acc.price_cumulative, spot_price, acc.last_update, now);
+
+    let start = now.seconds().checked_sub(window_seconds);
+    let oldest = observations[0].timestamp.seconds();
+    let start = match start {
+        Some(start) if start >= oldest => start,
+        _ => {
+            return Err(StdError::generic_err(format!(
+                "Not enough price history for a {} second window",
+                window_seconds
+            )))
+        }
+    };
+
+    // find the last observation at or before the start, and interpolate from there
+    let idx = observations.partition_point(|o| o.timestamp.seconds() <= start) - 1;
+    let before = &observations[idx];
+    let cumulative_start = match observations.get(idx + 1) {
+        Some(after) => {
+            let span = after.timestamp.seconds() - before.timestamp.seconds();
+            let delta = (after.price_cumulative - before.price_cumulative).atomics();
+            let partial = delta.multiply_ratio(start - before.timestamp.seconds(), span);
+            before.price_cumulative + Decimal256::new(partial)
+        }
+        // after the last observation, the current spot price has been valid
+        None => accumulate(
+            before.price_cumulative,
+            spot_price,
+            before.timestamp,
+            Timestamp::from_seconds(start),
+        ),
+    };
+
+    let average = (cumulative_now - cumulative_start).atomics() / Uint256::from(window_seconds);
+    let price = Decimal::from_atomics(Uint128::try_from(average)?, Decimal::DECIMAL_PLACES)
+        .map_err(|e| StdError::generic_err(e.to_string()))?;
+    Ok(TwapResponse {
+        price,
+        window_seconds,
+    })
+}
+
+pub fn query_observations(deps: Deps) -> StdResult<ObservationsResponse> {
+    let acc = PRICE_ACCUMULATOR.load(deps.storage)?;
+    let observations = load_observations(deps, &acc)?;
+    Ok(ObservationsResponse { observations })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate, query_curve_info};
+    use crate::msg::{CurveType, ExecuteMsg, InstantiateMsg};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{coins, DepsMut};
+
+    const DENOM: &str = "satoshi";
+    const CREATOR: &str = "creator";
+    const INVESTOR: &str = "investor";
+
+    fn linear() -> CurveType {
+        CurveType::Linear {
+            slope: Uint128::new(1),
+            scale: 1,
+        }
+    }
+
+    fn env_at(seconds: u64) -> Env {
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(seconds);
+        env
+    }
+
+    fn setup_test(deps: DepsMut) {
+        // same setup as `buy_issues_tokens` in contract.rs
+        let msg = InstantiateMsg {
+            name: "Bonded".to_string(),
+            symbol: "EPOXY".to_string(),
+            decimals: 2,
+            reserve_denom: DENOM.to_string(),
+            reserve_decimals: 8,
+            basket: None,
+            curve_type: linear(),
+            admin: None,
+            treasury: None,
+            batch: None,
+            vesting: None,
+            staking: None,
+            factory: None,
+            marketing: None,
+        };
+        instantiate(deps, env_at(0), mock_info(CREATOR, &[]), msg).unwrap();
+    }
+
+    fn buy(deps: DepsMut, env: Env, amount: u128) {
+        let info = mock_info(INVESTOR, &coins(amount, DENOM));
+        execute(deps, env, info, ExecuteMsg::Buy {}).unwrap();
+    }
+
+    fn twap(deps: Deps, env: Env, window_seconds: u64) -> StdResult<Decimal> {
+        query_twap(deps, env, linear().to_curve_fn(), window_seconds).map(|r| r.price)
+    }
+
+    #[test]
+    fn twap_weights_prices_by_time() {
+        let mut deps = mock_dependencies();
+        setup_test(deps.as_mut());
+
+        // buy 10.00 EPOXY right away (spot price 1.0), and 10.00 more after 100 seconds (spot 2.0)
+        buy(deps.as_mut(), env_at(0), 500_000_000);
+        buy(deps.as_mut(), env_at(100), 1_500_000_000);
+        let curve = query_curve_info(deps.as_ref(), linear().to_curve_fn()).unwrap();
+        assert_eq!(curve.spot_price, Decimal::percent(200));
+
+        // the price was 1.0 for 100 seconds, then 2.0 for 100 seconds
+        assert_eq!(
+            twap(deps.as_ref(), env_at(200), 200).unwrap(),
+            Decimal::percent(150)
+        );
+        // only the last 50 seconds were at 2.0
+        assert_eq!(
+            twap(deps.as_ref(), env_at(200), 50).unwrap(),
+            Decimal::percent(200)
+        );
+        // 50 seconds at 1.0, 100 seconds at 2.0
+        assert_eq!(
+            twap(deps.as_ref(), env_at(200), 150).unwrap(),
+            Decimal::from_ratio(250u128, 150u128)
+        );
+        // a window between two observations is interpolated: 20s at 1.0 from 80s to 100s
+        assert_eq!(
+            twap(deps.as_ref(), env_at(100), 20).unwrap(),
+            Decimal::one()
+        );
+
+        // we cannot look back before instantiation
+        let err = twap(deps.as_ref(), env_at(200), 201).unwrap_err();
+        assert_eq!(
+            err,
+            StdError::generic_err("Not enough price history for a 201 second window")
+        );
+        let err = twap(deps.as_ref(), env_at(200), 0).unwrap_err();
+        assert_eq!(
+            err,
+            StdError::generic_err("TWAP window must be at least one second")
+        );
+
+        // selling accrues the 2.0 price up to now, before dropping the price back to 1.0
+        let info = mock_info(INVESTOR, &[]);
+        let burn = ExecuteMsg::Burn {
+            amount: Uint128::new(1000),
+        };
+        execute(deps.as_mut(), env_at(200), info, burn).unwrap();
+        let observations = query_observations(deps.as_ref()).unwrap().observations;
+        let expected: Vec<_> = [(0, 0u128), (100, 100), (200, 300)]
+            .iter()
+            .map(|&(secs, cumulative)| Observation {
+                timestamp: env_at(secs).block.time,
+                price_cumulative: Decimal256::from_ratio(cumulative, 1u128),
+            })
+            .collect();
+        assert_eq!(observations, expected);
+
+        // 100 seconds at 2.0, then 100 seconds at 1.0
+        assert_eq!(
+            twap(deps.as_ref(), env_at(300), 200).unwrap(),
+            Decimal::percent(150)
+        );
+    }
+
+    #[test]
+    fn observations_wrap_around() {
+        let mut deps = mock_dependencies();
+        setup_test(deps.as_mut());
+
+        // one buy every 10 seconds, more than fit into the ring buffer
+        let buys = OBSERVATION_CAPACITY + 6;
+        for i in 1..=buys {
+            buy(deps.as_mut(), env_at(i * 10), 1_000_000);
+        }
+
+        let observations = query_observations(deps.as_ref()).unwrap().observations;
+        assert_eq!(observations.len() as u64, OBSERVATION_CAPACITY);
+        // the oldest ones are overwritten, but order is preserved
+        assert_eq!(
+            observations[0].timestamp,
+            env_at((buys - OBSERVATION_CAPACITY + 1) * 10).block.time
+        );
+        assert_eq!(
+            observations.last().unwrap().timestamp,
+            env_at(buys * 10).block.time
+        );
+        assert!(observations
+            .windows(2)
+            .all(|w| w[0].price_cumulative <= w[1].price_cumulative));
+
+        // we can query up to the oldest observation, but no further
+        let now = env_at(buys * 10);
+        let max_window = (OBSERVATION_CAPACITY - 1) * 10;
+        twap(deps.as_ref(), now.clone(), max_window).unwrap();
+        twap(deps.as_ref(), now, max_window + 1).unwrap_err();
+    }
+}
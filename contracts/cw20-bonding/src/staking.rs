@@ -0,0 +1,270 @@
+use cosmwasm_std::{
+    coin, to_binary, BankMsg, CosmosMsg, Decimal, Deps, DepsMut, DistributionMsg, Env, MessageInfo,
+    Response, StakingMsg, StdError, StdResult, Storage, Uint128, WasmMsg,
+};
+use cw_utils::nonpayable;
+
+use crate::error::ContractError;
+use crate::msg::{ExecuteMsg, StakingResponse};
+use crate::state::{
+    BasketComponent, CurveState, RewardsDestination, StakedReserve, StakingConfig, Unbonding,
+    BATCH, CURVE_STATE, HOSTED_RESERVES, SETTLEMENT, STAKED_RESERVE, STAKING_CONFIG, TREASURY,
+};
+
+/// Validates the staking config. The reserve must be a single denom, which the chain bonds.
+pub fn init_staking(
+    deps: DepsMut,
+    basket: &[BasketComponent],
+    config: StakingConfig,
+) -> Result<(), ContractError> {
+    let bonded_denom = deps.querier.query_bonded_denom()?;
+    let valid = matches!(basket, [single] if single.denom == bonded_denom)
+        && config.delegated_share <= Decimal::one()
+        && (config.rewards == RewardsDestination::Reserve
+            || TREASURY.may_load(deps.storage)?.is_some());
+    if !valid {
+        return Err(ContractError::InvalidStaking {});
+    }
+    if deps.querier.query_validator(&config.validator)?.is_none() {
+        return Err(ContractError::NotInValidatorSet {
+            validator: config.validator,
+        });
+    }
+
+    STAKING_CONFIG.save(deps.storage, &config)?;
+    STAKED_RESERVE.save(deps.storage, &StakedReserve::default())?;
+    Ok(())
+}
+
+/// Loads the staked reserve, forgetting about unbondings that are back in our balance by now
+fn load_staked(storage: &dyn Storage, env: &Env) -> StdResult<StakedReserve> {
+    let mut staked = STAKED_RESERVE.load(storage)?;
+    staked.unbonding.retain(|u| u.release_at > env.block.time);
+    Ok(staked)
+}
+
+fn unbonding_total(staked: &StakedReserve) -> Uint128 {
+    staked.unbonding.iter().map(|u| u.amount).sum()
+}
+
+/// Checks that the liquid reserve covers `payout`, then moves the reserve towards the delegated
+/// share. `state` is the curve state after the payout. Does nothing unless staking is configured.
+pub fn rebalance_stake(
+    storage: &mut dyn Storage,
+    env: &Env,
+    state: &CurveState,
+    payout: Uint128,
+) -> Result<Option<CosmosMsg>, ContractError> {
+    let config = match STAKING_CONFIG.may_load(storage)? {
+        Some(config) => config,
+        None => return Ok(None),
+    };
+    let mut staked = load_staked(storage, env)?;
+    let denom = &state.basket[0].denom;
+    let weight = state.basket[0].weight;
+    let reserve = state.reserve * weight;
+    let payout = payout * weight;
+
+    let unbonding = unbonding_total(&staked);
+    let available = (reserve + payout).saturating_sub(staked.delegated + unbonding);
+    if payout > available {
+        return Err(ContractError::InsufficientLiquidity {
+            available,
+            requested: payout,
+        });
+    }
+    let liquid = available - payout;
+
+    let target = reserve * config.delegated_share;
+    let buffer = reserve - target;
+    let msg = if staked.delegated > target {
+        // the chain limits pending unbondings, so only undelegate below half of the buffer
+        if liquid + unbonding >= buffer.multiply_ratio(1u128, 2u128) {
+            return Ok(None);
+        }
+        let amount = staked.delegated - target;
+        staked.delegated = target;
+        staked.unbonding.push(Unbonding {
+            amount,
+            release_at: env.block.time.plus_seconds(config.unbonding_period),
+        });
+        StakingMsg::Undelegate {
+            validator: config.validator,
+            amount: coin(amount.u128(), denom),
+        }
+    } else {
+        let amount = (target - staked.delegated).min(liquid);
+        if amount.is_zero() {
+            return Ok(None);
+        }
+        staked.delegated += amount;
+        StakingMsg::Delegate {
+            validator: config.validator,
+            amount: coin(amount.u128(), denom),
+        }
+    };
+    STAKED_RESERVE.save(storage, &staked)?;
+    Ok(Some(msg.into()))
+}
+
+/// Withdraws the staking rewards, and then distributes them in a callback to ourselves
+pub fn execute_collect_rewards(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    nonpayable(&info)?;
+    let config = STAKING_CONFIG
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NotStakingMode {})?;
+
+    let res = Response::new()
+        .add_message(DistributionMsg::WithdrawDelegatorReward {
+            validator: config.validator,
+        })
+        .add_message(WasmMsg::Execute {
+            contract_addr: env.contract.address.to_string(),
+            msg: to_binary(&ExecuteMsg::_DistributeRewards {})?,
+            funds: vec![],
+        })
+        .add_attribute("action", "collect_rewards");
+    Ok(res)
+}
+
+/// Anything in our balance that is not reserve, queued or settled batch funds, or held by
+/// hosted curves must be rewards, which are added to the reserve or sent to the treasury.
+pub fn execute_distribute_rewards(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    // this is just meant as a call-back to ourself
+    if info.sender != env.contract.address {
+        return Err(ContractError::Unauthorized {});
+    }
+    let config = STAKING_CONFIG
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NotStakingMode {})?;
+    let mut state = CURVE_STATE.load(deps.storage)?;
+    let staked = load_staked(deps.storage, &env)?;
+    let component = state.basket[0].clone();
+
+    // queued buys are not part of the reserve yet, and payouts of a settled batch no longer
+    let queued = BATCH
+        .may_load(deps.storage)?
+        .map(|batch| batch.total_buy)
+        .unwrap_or_default();
+    let unpaid = SETTLEMENT
+        .may_load(deps.storage)?
+        .map(|settlement| settlement.reserve_to_sellers - settlement.paid)
+        .unwrap_or_default();
+    let liquid = (state.reserve * component.weight)
+        .saturating_sub(staked.delegated + unbonding_total(&staked));
+    let balance = deps
+        .querier
+        .query_balance(&env.contract.address, &component.denom)?;
+    let hosted = HOSTED_RESERVES
+        .may_load(deps.storage, &component.denom)?
+        .unwrap_or_default();
+    let rewards = balance
+        .amount
+        .saturating_sub(liquid + (queued + unpaid) * component.weight + hosted);
+
+    let mut res = Response::new()
+        .add_attribute("action", "distribute_rewards")
+        .add_attribute("rewards", rewards);
+    match config.rewards {
+        RewardsDestination::Reserve => {
+            // any remainder stays around until the next distribution
+            state.reserve += rewards / component.weight;
+            CURVE_STATE.save(deps.storage, &state)?;
+            if let Some(msg) = rebalance_stake(deps.storage, &env, &state, Uint128::zero())? {
+                res = res.add_message(msg);
+            }
+            res = res.add_attribute("destination", "reserve");
+        }
+        RewardsDestination::Treasury => {
+            let treasury = TREASURY.load(deps.storage)?;
+            if !rewards.is_zero() {
+                res = res.add_message(BankMsg::Send {
+                    to_address: treasury.to_string(),
+                    amount: vec![coin(rewards.u128(), component.denom)],
+                });
+            }
+            res = res.add_attribute("destination", treasury);
+        }
+    }
+    Ok(res)
+}
+
+pub fn query_staking(deps: Deps, env: Env) -> StdResult<StakingResponse> {
+    let config = STAKING_CONFIG
+        .may_load(deps.storage)?
+        .ok_or_else(|| StdError::generic_err(ContractError::NotStakingMode {}.to_string()))?;
+    let state = CURVE_STATE.load(deps.storage)?;
+    let staked = load_staked(deps.storage, &env)?;
+    let liquid = (state.reserve * state.basket[0].weight)
+        .saturating_sub(staked.delegated + unbonding_total(&staked));
+    Ok(StakingResponse {
+        config,
+        delegated: staked.delegated,
+        unbonding: staked.unbonding,
+        liquid,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curves::DecimalPlaces;
+    use crate::state::HOSTED_RESERVES;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MOCK_CONTRACT_ADDR};
+    use cosmwasm_std::{coins, Addr, SubMsg};
+
+    const DENOM: &str = "ustake";
+    const TREASURY_ADDR: &str = "treasury";
+
+    #[test]
+    fn rewards_leave_hosted_reserves_alone() {
+        let mut deps = mock_dependencies();
+        let storage = deps.as_mut().storage;
+        let basket = vec![BasketComponent {
+            denom: DENOM.to_string(),
+            weight: Uint128::new(1),
+        }];
+        let mut state = CurveState::new(basket, DecimalPlaces::new(6, 6));
+        state.reserve = Uint128::new(1_000);
+        CURVE_STATE.save(storage, &state).unwrap();
+        let config = StakingConfig {
+            validator: "validator".to_string(),
+            delegated_share: Decimal::percent(80),
+            unbonding_period: 60,
+            rewards: RewardsDestination::Treasury,
+        };
+        STAKING_CONFIG.save(storage, &config).unwrap();
+        let staked = StakedReserve {
+            delegated: Uint128::new(800),
+            unbonding: vec![],
+        };
+        STAKED_RESERVE.save(storage, &staked).unwrap();
+        TREASURY
+            .save(storage, &Addr::unchecked(TREASURY_ADDR))
+            .unwrap();
+        // hosted curves hold 500 in our balance, next to 200 liquid reserve and 30 rewards
+        HOSTED_RESERVES
+            .save(storage, DENOM, &Uint128::new(500))
+            .unwrap();
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(730, DENOM));
+
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        let res = execute_distribute_rewards(deps.as_mut(), mock_env(), info).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: TREASURY_ADDR.to_string(),
+                amount: coins(30, DENOM),
+            })]
+        );
+    }
+}
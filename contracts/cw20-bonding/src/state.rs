@@ -1,38 +1,299 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::Uint128;
-use cw_storage_plus::Item;
+use cosmwasm_std::{Addr, Decimal, Decimal256, Timestamp, Uint128};
+use cw_controllers::Admin;
+use cw_storage_plus::{Item, Map};
+use cw_utils::{Duration, Expiration};
 
 use crate::curves::DecimalPlaces;
 use crate::msg::CurveType;
 
+/// BasketComponent is one native denom backing the supply
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BasketComponent {
+    pub denom: String,
+    /// how many of this denom make up one unit of the reserve basket
+    pub weight: Uint128,
+}
+
 /// Supply is dynamic and tracks the current supply of staked and ERC20 tokens.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
 pub struct CurveState {
-    /// reserve is how many native tokens exist bonded to the validator
+    /// reserve is how many basket units back the supply. The contract holds
+    /// `reserve * weight` of every basket component
     pub reserve: Uint128,
     /// supply is how many tokens this contract has issued
     pub supply: Uint128,
 
-    // the denom of the reserve token
-    pub reserve_denom: String,
+    // the denoms of the reserve basket, a single one with weight 1 unless this is an index token
+    pub basket: Vec<BasketComponent>,
 
     // how to normalize reserve and supply
     pub decimals: DecimalPlaces,
 }
 
 impl CurveState {
-    pub fn new(reserve_denom: String, decimals: DecimalPlaces) -> Self {
+    pub fn new(basket: Vec<BasketComponent>, decimals: DecimalPlaces) -> Self {
         CurveState {
             reserve: Uint128::zero(),
             supply: Uint128::zero(),
-            reserve_denom,
+            basket,
             decimals,
         }
     }
+
+    /// Reserve held beyond what the curve requires at the current supply (eg. staking rewards)
+    /// raises the backing of every token alike. So amounts paid in are scaled down to what the
+    /// curve sees by `curve_reserve / reserve`, with `curve_reserve = curve.reserve(supply)`.
+    pub fn to_curve(&self, amount: Uint128, curve_reserve: Uint128) -> Uint128 {
+        if curve_reserve.is_zero() || self.reserve <= curve_reserve {
+            amount
+        } else {
+            amount.multiply_ratio(curve_reserve, self.reserve)
+        }
+    }
+
+    /// Inverse of `to_curve`, scales amounts the curve pays out up by `reserve / curve_reserve`
+    pub fn from_curve(&self, amount: Uint128, curve_reserve: Uint128) -> Uint128 {
+        if curve_reserve.is_zero() || self.reserve <= curve_reserve {
+            amount
+        } else {
+            amount.multiply_ratio(self.reserve, curve_reserve)
+        }
+    }
 }
 
 pub const CURVE_STATE: Item<CurveState> = Item::new("curve_state");
 
+/// Version of the `CurveState` layout, see `migrations`. Unset for contracts instantiated
+/// before it was introduced.
+pub const STATE_VERSION: Item<u16> = Item::new("state_version");
+
 pub const CURVE_TYPE: Item<CurveType> = Item::new("curve_type");
+
+/// The admin may retune the curve with `UpdateCurve`. If unset, the curve is fixed forever.
+pub const ADMIN: Admin = Admin::new("admin");
+
+/// Receives any reserve freed up by a curve update. Falls back to the admin if unset.
+pub const TREASURY: Item<Addr> = Item::new("treasury");
+
+/// PriceAccumulator sums up the spot price over time (in seconds), Uniswap v2 style.
+/// The difference of two readings divided by the time between them is the TWAP.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceAccumulator {
+    /// sum of spot_price * seconds since instantiation, up to last_update
+    pub price_cumulative: Decimal256,
+    /// when the accumulator was last updated (always matches the newest observation)
+    pub last_update: Timestamp,
+    /// total number of observations ever recorded, used to index the ring buffer
+    pub observations: u64,
+}
+
+/// Observation is a snapshot of the accumulator, stored in a ring buffer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Observation {
+    pub timestamp: Timestamp,
+    pub price_cumulative: Decimal256,
+}
+
+/// How many observations we keep, older ones are overwritten
+pub const OBSERVATION_CAPACITY: u64 = 64;
+
+pub const PRICE_ACCUMULATOR: Item<PriceAccumulator> = Item::new("price_accumulator");
+
+/// Ring buffer of observations, keyed by `observation count % OBSERVATION_CAPACITY`
+pub const OBSERVATIONS: Map<u64, Observation> = Map::new("observations");
+
+/// BatchConfig enables batch auction mode, and bounds the orders of each batch so that
+/// settling it stays affordable
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BatchConfig {
+    /// length of each epoch
+    pub epoch: Duration,
+    /// smallest buy order, in reserve basket units
+    pub min_buy: Uint128,
+    /// smallest sell order, in supply tokens
+    pub min_sell: Uint128,
+    /// most orders a single batch takes
+    pub max_orders: u32,
+}
+
+/// Batch is only set in batch auction mode. Buy and sell orders are then queued until the
+/// epoch ends, and all of them are cleared at a single price by `SettleBatch`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Batch {
+    pub config: BatchConfig,
+    /// id of the current batch, increases with every settlement
+    pub id: u64,
+    /// the batch can be settled once this has expired
+    pub ends: Expiration,
+    /// reserve tokens sent with all queued buy orders
+    pub total_buy: Uint128,
+    /// supply tokens escrowed by all queued sell orders
+    pub total_sell: Uint128,
+    /// how many orders are queued
+    pub orders: u32,
+}
+
+/// Settlement is a settled batch whose orders are still being filled, a page at a time.
+/// Its clearing is already booked in the curve state: `tokens_to_buyers` count towards the
+/// supply, and `reserve_to_sellers` no longer towards the reserve.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Settlement {
+    pub batch_id: u64,
+    pub total_buy: Uint128,
+    pub total_sell: Uint128,
+    pub tokens_to_buyers: Uint128,
+    pub reserve_to_sellers: Uint128,
+    /// what the orders filled so far got
+    pub minted: Uint128,
+    pub paid: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderSide {
+    /// amount is in reserve tokens, paid in when queueing
+    Buy,
+    /// amount is in supply tokens, escrowed by the contract when queueing
+    Sell,
+}
+
+/// Order is a queued buy or sell in the current batch
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Order {
+    /// owner may cancel the order, and gets the escrowed tokens back if so
+    pub owner: Addr,
+    /// recipient gets the supply (buy) or reserve (sell) tokens on settlement
+    pub recipient: Addr,
+    pub side: OrderSide,
+    pub amount: Uint128,
+}
+
+pub const BATCH: Item<Batch> = Item::new("batch");
+
+/// Only set while the orders of a settled batch are being filled. No orders can be queued or
+/// cancelled until then.
+pub const SETTLEMENT: Item<Settlement> = Item::new("settlement");
+
+/// Open orders of the current batch, by order id. Removed once filled.
+pub const ORDERS: Map<u64, Order> = Map::new("orders");
+
+pub const ORDER_COUNT: Item<u64> = Item::new("order_count");
+
+/// VestingConfig is only set if purchased tokens vest linearly, rather than going straight
+/// to the buyer. Times are in seconds from the purchase.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+pub struct VestingConfig {
+    /// nothing can be claimed before the cliff
+    pub cliff: u64,
+    /// everything can be claimed after the duration
+    pub duration: u64,
+}
+
+impl VestingConfig {
+    /// How much of `total` has vested at `now`, for a schedule that started at `start`
+    pub fn vested(&self, total: Uint128, start: Timestamp, now: Timestamp) -> Uint128 {
+        let elapsed = now.seconds().saturating_sub(start.seconds());
+        if elapsed < self.cliff {
+            Uint128::zero()
+        } else if elapsed >= self.duration {
+            total
+        } else {
+            total.multiply_ratio(elapsed, self.duration)
+        }
+    }
+}
+
+/// VestingSchedule tracks the tokens bought in one block, held by the contract until claimed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VestingSchedule {
+    pub start: Timestamp,
+    pub total: Uint128,
+    pub claimed: Uint128,
+}
+
+pub const VESTING_CONFIG: Item<VestingConfig> = Item::new("vesting_config");
+
+/// Vesting schedules by buyer and start (in seconds). Purchases of the same block vest alike,
+/// so they share a schedule
+pub const VESTING: Map<(&Addr, u64), VestingSchedule> = Map::new("vesting_schedules");
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RewardsDestination {
+    /// rewards are added to the reserve, which raises the backing of every token
+    Reserve,
+    /// rewards are sent to the treasury
+    Treasury,
+}
+
+/// StakingConfig is only set if idle reserve is delegated to a validator
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StakingConfig {
+    pub validator: String,
+    /// share of the reserve that is delegated, the rest stays liquid to pay out sells
+    pub delegated_share: Decimal,
+    /// the chain's unbonding time in seconds, after which undelegated tokens are liquid again
+    pub unbonding_period: u64,
+    pub rewards: RewardsDestination,
+}
+
+/// Unbonding tokens were undelegated, but are not yet back in the contract's balance
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Unbonding {
+    pub amount: Uint128,
+    pub release_at: Timestamp,
+}
+
+/// StakedReserve tracks which part of the reserve is not liquid
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct StakedReserve {
+    pub delegated: Uint128,
+    pub unbonding: Vec<Unbonding>,
+}
+
+pub const STAKING_CONFIG: Item<StakingConfig> = Item::new("staking_config");
+
+pub const STAKED_RESERVE: Item<StakedReserve> = Item::new("staked_reserve");
+
+/// FactoryConfig is only set if this contract may host further curves. Each of them gets its
+/// own cw20 token, instantiated from `cw20_code_id` with this contract as minter.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FactoryConfig {
+    pub cw20_code_id: u64,
+}
+
+/// HostedCurve is a plain bonding curve hosted in factory mode. The supply is tracked here,
+/// while the balances live in its token.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HostedCurve {
+    pub token: Addr,
+    pub curve_type: CurveType,
+    pub state: CurveState,
+}
+
+/// PendingCurve is a hosted curve whose token is still being instantiated
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingCurve {
+    pub curve_type: CurveType,
+    pub state: CurveState,
+}
+
+pub const FACTORY: Item<FactoryConfig> = Item::new("factory");
+
+/// Hosted curves by id
+pub const HOSTED_CURVES: Map<u64, HostedCurve> = Map::new("hosted_curves");
+
+/// The curve id of every hosted token
+pub const HOSTED_TOKENS: Map<&Addr, u64> = Map::new("hosted_tokens");
+
+pub const HOSTED_COUNT: Item<u64> = Item::new("hosted_count");
+
+/// Reserve of all hosted curves together, by denom. It sits in our balance next to the reserve
+/// of the main curve, so staking rewards are only what is left beyond both
+pub const HOSTED_RESERVES: Map<&str, Uint128> = Map::new("hosted_reserves");
+
+/// Curves waiting for the reply to their token instantiation, by id (which is the reply id)
+pub const PENDING_CURVES: Map<u64, PendingCurve> = Map::new("pending_curves");
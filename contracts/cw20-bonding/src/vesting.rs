@@ -0,0 +1,389 @@
+use cosmwasm_std::{Addr, Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult, Uint128};
+use cw20_base::contract::{execute_mint, execute_transfer};
+use cw_storage_plus::Bound;
+use cw_utils::nonpayable;
+
+use crate::error::ContractError;
+use crate::msg::{VestingResponse, VestingSchedulesResponse};
+use crate::state::{VestingConfig, VestingSchedule, VESTING, VESTING_CONFIG};
+
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+
+pub fn init_vesting(deps: DepsMut, config: VestingConfig) -> Result<(), ContractError> {
+    if config.duration == 0 || config.cliff > config.duration {
+        return Err(ContractError::InvalidVesting {});
+    }
+    VESTING_CONFIG.save(deps.storage, &config)?;
+    Ok(())
+}
+
+/// Mints purchased tokens to the buyer. If vesting is enabled, the contract holds them
+/// instead, in a vesting schedule of the buyer that starts with this block.
+pub fn mint_purchase(
+    deps: DepsMut,
+    env: Env,
+    buyer: &Addr,
+    amount: Uint128,
+) -> Result<(), ContractError> {
+    // call into cw20-base to mint the token, call as self as no one else is allowed
+    let sub_info = MessageInfo {
+        sender: env.contract.address.clone(),
+        funds: vec![],
+    };
+    if VESTING_CONFIG.may_load(deps.storage)?.is_none() {
+        execute_mint(deps, env, sub_info, buyer.to_string(), amount)?;
+        return Ok(());
+    }
+
+    let start = env.block.time;
+    VESTING.update(
+        deps.storage,
+        (buyer, start.seconds()),
+        |schedule| -> StdResult<_> {
+            let mut schedule = schedule.unwrap_or(VestingSchedule {
+                start,
+                total: Uint128::zero(),
+                claimed: Uint128::zero(),
+            });
+            schedule.total += amount;
+            Ok(schedule)
+        },
+    )?;
+    let contract = env.contract.address.to_string();
+    execute_mint(deps, env, sub_info, contract, amount)?;
+    Ok(())
+}
+
+/// Claims from the oldest schedules of the sender, which vest first
+pub fn execute_claim_vested(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    nonpayable(&info)?;
+    let config = VESTING_CONFIG.may_load(deps.storage)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let schedules = VESTING
+        .prefix(&info.sender)
+        .range(deps.storage, None, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let mut claimable = Uint128::zero();
+    for (start, mut schedule) in schedules {
+        if let Some(config) = config {
+            let vested = config.vested(schedule.total, schedule.start, env.block.time);
+            claimable += vested - schedule.claimed;
+            schedule.claimed = vested;
+        }
+        // drop schedules once they are fully claimed
+        if schedule.claimed < schedule.total {
+            VESTING.save(deps.storage, (&info.sender, start), &schedule)?;
+        } else {
+            VESTING.remove(deps.storage, (&info.sender, start));
+        }
+    }
+    if claimable.is_zero() {
+        return Err(ContractError::NothingToClaim {});
+    }
+
+    // the contract holds the tokens, so it transfers them out
+    let contract_info = MessageInfo {
+        sender: env.contract.address.clone(),
+        funds: vec![],
+    };
+    execute_transfer(deps, env, contract_info, info.sender.to_string(), claimable)?;
+
+    let res = Response::new()
+        .add_attribute("action", "claim_vested")
+        .add_attribute("to", info.sender)
+        .add_attribute("amount", claimable);
+    Ok(res)
+}
+
+/// Sums up all schedules of `address`
+pub fn query_vesting(deps: Deps, env: Env, address: String) -> StdResult<VestingResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let config = VESTING_CONFIG.may_load(deps.storage)?;
+
+    let mut res = VestingResponse {
+        vested: Uint128::zero(),
+        unvested: Uint128::zero(),
+    };
+    if let Some(config) = config {
+        for item in VESTING
+            .prefix(&address)
+            .range(deps.storage, None, None, Order::Ascending)
+        {
+            let (_, schedule) = item?;
+            let vested = config.vested(schedule.total, schedule.start, env.block.time);
+            res.vested += vested - schedule.claimed;
+            res.unvested += schedule.total - vested;
+        }
+    }
+    Ok(res)
+}
+
+pub fn query_vesting_schedules(
+    deps: Deps,
+    address: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<VestingSchedulesResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let schedules = VESTING
+        .prefix(&address)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, schedule)| schedule))
+        .collect::<StdResult<_>>()?;
+    Ok(VestingSchedulesResponse { schedules })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::msg::{CurveType, ExecuteMsg, InstantiateMsg};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MOCK_CONTRACT_ADDR};
+    use cosmwasm_std::{coins, OverflowError, OverflowOperation, StdError};
+    use cw20_base::contract::query_balance;
+
+    const DENOM: &str = "satoshi";
+    const CREATOR: &str = "creator";
+    const INVESTOR: &str = "investor";
+
+    fn vesting_instantiate(cliff: u64, duration: u64) -> InstantiateMsg {
+        InstantiateMsg {
+            name: "Bonded".to_string(),
+            symbol: "EPOXY".to_string(),
+            decimals: 2,
+            reserve_denom: DENOM.to_string(),
+            reserve_decimals: 8,
+            basket: None,
+            curve_type: CurveType::Linear {
+                slope: Uint128::new(1),
+                scale: 1,
+            },
+            admin: None,
+            treasury: None,
+            batch: None,
+            vesting: Some(VestingConfig { cliff, duration }),
+            staking: None,
+            factory: None,
+            marketing: None,
+        }
+    }
+
+    fn env_at(seconds: u64) -> Env {
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(seconds);
+        env
+    }
+
+    fn balance(deps: Deps, addr: &str) -> Uint128 {
+        query_balance(deps, addr.to_string()).unwrap().balance
+    }
+
+    fn vesting(deps: Deps, env: Env) -> (u128, u128) {
+        let res = query_vesting(deps, env, INVESTOR.to_string()).unwrap();
+        (res.vested.u128(), res.unvested.u128())
+    }
+
+    #[test]
+    fn invalid_vesting_config() {
+        let mut deps = mock_dependencies();
+        let info = mock_info(CREATOR, &[]);
+        let err = instantiate(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            vesting_instantiate(0, 0),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::InvalidVesting {});
+        let err = instantiate(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            vesting_instantiate(101, 100),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::InvalidVesting {});
+    }
+
+    #[test]
+    fn purchases_vest_linearly() {
+        let mut deps = mock_dependencies();
+        let info = mock_info(CREATOR, &[]);
+        instantiate(
+            deps.as_mut(),
+            env_at(0),
+            info,
+            vesting_instantiate(100, 1000),
+        )
+        .unwrap();
+
+        // 1000 EPOXY are bought, but held by the contract
+        let info = mock_info(INVESTOR, &coins(500_000_000, DENOM));
+        execute(deps.as_mut(), env_at(0), info, ExecuteMsg::Buy {}).unwrap();
+        assert_eq!(balance(deps.as_ref(), INVESTOR), Uint128::zero());
+        assert_eq!(
+            balance(deps.as_ref(), MOCK_CONTRACT_ADDR),
+            Uint128::new(1000)
+        );
+        assert_eq!(vesting(deps.as_ref(), env_at(0)), (0, 1000));
+
+        // nothing can be claimed (or sold) before the cliff
+        assert_eq!(vesting(deps.as_ref(), env_at(99)), (0, 1000));
+        let info = mock_info(INVESTOR, &[]);
+        let err = execute(
+            deps.as_mut(),
+            env_at(99),
+            info.clone(),
+            ExecuteMsg::ClaimVested { limit: None },
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::NothingToClaim {});
+        let burn = ExecuteMsg::Burn {
+            amount: Uint128::new(100),
+        };
+        execute(deps.as_mut(), env_at(99), info.clone(), burn).unwrap_err();
+
+        // after the cliff, it vests linearly from the purchase
+        assert_eq!(vesting(deps.as_ref(), env_at(100)), (100, 900));
+        assert_eq!(vesting(deps.as_ref(), env_at(500)), (500, 500));
+        execute(
+            deps.as_mut(),
+            env_at(500),
+            info.clone(),
+            ExecuteMsg::ClaimVested { limit: None },
+        )
+        .unwrap();
+        assert_eq!(balance(deps.as_ref(), INVESTOR), Uint128::new(500));
+        assert_eq!(vesting(deps.as_ref(), env_at(500)), (0, 500));
+
+        // another purchase starts its own schedule
+        let buy_info = mock_info(INVESTOR, &coins(1_500_000_000, DENOM));
+        execute(deps.as_mut(), env_at(500), buy_info, ExecuteMsg::Buy {}).unwrap();
+        assert_eq!(
+            balance(deps.as_ref(), MOCK_CONTRACT_ADDR),
+            Uint128::new(1500)
+        );
+        assert_eq!(vesting(deps.as_ref(), env_at(1000)), (500 + 500, 500));
+
+        // only vested and claimed tokens can be sold
+        let burn = ExecuteMsg::Burn {
+            amount: Uint128::new(600),
+        };
+        let err = execute(deps.as_mut(), env_at(1000), info.clone(), burn).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::Base(cw20_base::ContractError::Std(StdError::overflow(
+                OverflowError::new(OverflowOperation::Sub, 500, 600)
+            )))
+        );
+        let burn = ExecuteMsg::Burn {
+            amount: Uint128::new(500),
+        };
+        execute(deps.as_mut(), env_at(1000), info.clone(), burn).unwrap();
+
+        // eventually everything is claimed, and the schedules are cleaned up
+        execute(
+            deps.as_mut(),
+            env_at(1500),
+            info,
+            ExecuteMsg::ClaimVested { limit: None },
+        )
+        .unwrap();
+        assert_eq!(balance(deps.as_ref(), INVESTOR), Uint128::new(1500));
+        assert_eq!(balance(deps.as_ref(), MOCK_CONTRACT_ADDR), Uint128::zero());
+        assert_eq!(vesting(deps.as_ref(), env_at(1500)), (0, 0));
+        assert!(VESTING
+            .prefix(&Addr::unchecked(INVESTOR))
+            .keys(&deps.storage, None, None, Order::Ascending)
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn schedules_merge_by_block_and_are_claimed_in_pages() {
+        let mut deps = mock_dependencies();
+        let info = mock_info(CREATOR, &[]);
+        instantiate(deps.as_mut(), env_at(0), info, vesting_instantiate(0, 100)).unwrap();
+
+        // purchases of the same block share a schedule
+        for seconds in [0, 0, 10, 20] {
+            let info = mock_info(INVESTOR, &coins(500_000_000, DENOM));
+            execute(deps.as_mut(), env_at(seconds), info, ExecuteMsg::Buy {}).unwrap();
+        }
+        let query = |deps: Deps, start_after, limit| {
+            query_vesting_schedules(deps, INVESTOR.to_string(), start_after, limit)
+                .unwrap()
+                .schedules
+        };
+        let schedules = query(deps.as_ref(), None, None);
+        let starts: Vec<_> = schedules.iter().map(|s| s.start).collect();
+        assert_eq!(
+            starts,
+            vec![
+                env_at(0).block.time,
+                env_at(10).block.time,
+                env_at(20).block.time
+            ]
+        );
+        let totals: Vec<_> = schedules.iter().map(|s| s.total).collect();
+
+        // the query pages by start
+        let first = env_at(0).block.time.seconds();
+        assert_eq!(query(deps.as_ref(), None, Some(1)), schedules[..1]);
+        assert_eq!(query(deps.as_ref(), Some(first), None), schedules[1..]);
+
+        // claims take the oldest schedules first
+        let info = mock_info(INVESTOR, &[]);
+        let claim = ExecuteMsg::ClaimVested { limit: Some(2) };
+        execute(deps.as_mut(), env_at(200), info.clone(), claim).unwrap();
+        assert_eq!(balance(deps.as_ref(), INVESTOR), totals[0] + totals[1]);
+        assert_eq!(query(deps.as_ref(), None, None), schedules[2..]);
+
+        let claim = ExecuteMsg::ClaimVested { limit: None };
+        execute(deps.as_mut(), env_at(200), info, claim).unwrap();
+        assert_eq!(
+            balance(deps.as_ref(), INVESTOR),
+            totals.iter().copied().sum::<Uint128>()
+        );
+        assert_eq!(query(deps.as_ref(), None, None), vec![]);
+    }
+
+    #[test]
+    fn totals_cover_schedules_beyond_a_page() {
+        let mut deps = mock_dependencies();
+        let info = mock_info(CREATOR, &[]);
+        instantiate(deps.as_mut(), env_at(0), info, vesting_instantiate(0, 100)).unwrap();
+
+        for seconds in 0..12 {
+            let info = mock_info(INVESTOR, &coins(500_000_000, DENOM));
+            execute(deps.as_mut(), env_at(seconds), info, ExecuteMsg::Buy {}).unwrap();
+        }
+        let bought = balance(deps.as_ref(), MOCK_CONTRACT_ADDR).u128();
+        assert_eq!(vesting(deps.as_ref(), env_at(0)).1, bought);
+        assert_eq!(vesting(deps.as_ref(), env_at(200)), (bought, 0));
+
+        // the list is paged on its own
+        let page = |start_after| {
+            query_vesting_schedules(deps.as_ref(), INVESTOR.to_string(), start_after, None)
+                .unwrap()
+                .schedules
+        };
+        let first = page(None);
+        assert_eq!(first.len(), 10);
+        let rest = page(Some(first[9].start.seconds()));
+        assert_eq!(rest.len(), 2);
+        let listed: Uint128 = first.iter().chain(&rest).map(|s| s.total).sum();
+        assert_eq!(listed.u128(), bought);
+    }
+}
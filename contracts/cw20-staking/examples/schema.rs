@@ -4,7 +4,12 @@ use std::fs::create_dir_all;
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
 use cw20::{AllowanceResponse, BalanceResponse, TokenInfoResponse};
-use cw20_staking::msg::{ClaimsResponse, ExecuteMsg, InstantiateMsg, InvestmentResponse, QueryMsg};
+use cw20_staking::msg::{
+    AllowlistResponse, BufferResponse, ClaimsResponse, EstimatedAprResponse,
+    ExchangeRateHistoryResponse, ExecuteMsg, HeadroomResponse, InstantiateMsg, InvestmentResponse,
+    PendingEpochResponse, ProcessedEpochsResponse, ProposalResponse, QueryMsg, ReceiveMsg,
+    ValidatorsResponse, VoteResponse,
+};
 
 fn main() {
     let mut out_dir = current_dir().unwrap();
@@ -15,9 +20,20 @@ fn main() {
     export_schema(&schema_for!(InstantiateMsg), &out_dir);
     export_schema(&schema_for!(ExecuteMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(ReceiveMsg), &out_dir);
     export_schema(&schema_for!(AllowanceResponse), &out_dir);
+    export_schema(&schema_for!(AllowlistResponse), &out_dir);
     export_schema(&schema_for!(BalanceResponse), &out_dir);
+    export_schema(&schema_for!(BufferResponse), &out_dir);
     export_schema(&schema_for!(ClaimsResponse), &out_dir);
+    export_schema(&schema_for!(EstimatedAprResponse), &out_dir);
+    export_schema(&schema_for!(ExchangeRateHistoryResponse), &out_dir);
+    export_schema(&schema_for!(HeadroomResponse), &out_dir);
     export_schema(&schema_for!(InvestmentResponse), &out_dir);
+    export_schema(&schema_for!(PendingEpochResponse), &out_dir);
+    export_schema(&schema_for!(ProcessedEpochsResponse), &out_dir);
+    export_schema(&schema_for!(ProposalResponse), &out_dir);
     export_schema(&schema_for!(TokenInfoResponse), &out_dir);
+    export_schema(&schema_for!(ValidatorsResponse), &out_dir);
+    export_schema(&schema_for!(VoteResponse), &out_dir);
 }
@@ -0,0 +1,185 @@
+use cosmwasm_std::{
+    coin, BankMsg, BlockInfo, Decimal, Deps, DepsMut, Env, MessageInfo, Order as Ordering,
+    Response, StdResult, Storage, Uint128,
+};
+use cw20_base::contract::execute_burn;
+
+use crate::error::ContractError;
+use crate::fees::{bps_of, BPS_DENOMINATOR};
+use crate::gov::update_votes;
+use crate::history::record_rate;
+use crate::limits::record_unbond;
+use crate::msg::BufferResponse;
+use crate::slashing::sync_bonded;
+use crate::state::{
+    InvestmentInfo, Supply, BUFFER_REFILLS, INVESTMENT, PROCESSED_EPOCHS, TOTAL_SUPPLY,
+};
+use crate::validators::{get_delegations, total};
+
+/// Epochs are unbonded from the delegations, so most of the backing must stay bonded
+pub const MAX_BUFFER_TARGET_BPS: u16 = 5_000;
+
+pub fn validate_buffer(target_bps: u16, fee_bps: u16) -> Result<(), ContractError> {
+    if target_bps > MAX_BUFFER_TARGET_BPS {
+        return Err(ContractError::BufferTargetTooHigh {
+            max_bps: MAX_BUFFER_TARGET_BPS,
+        });
+    }
+    if fee_bps > BPS_DENOMINATOR {
+        return Err(ContractError::InstantUnbondFeeTooHigh {
+            max_bps: BPS_DENOMINATOR,
+        });
+    }
+    Ok(())
+}
+
+/// How much the buffer is missing if the backing was `value`, counting what is refilling already
+pub fn buffer_deficit(invest: &InvestmentInfo, supply: &Supply, value: Uint128) -> Uint128 {
+    bps_of(value, invest.buffer_target_bps).saturating_sub(supply.buffer + supply.refilling)
+}
+
+/// Refills that have finished unbonding, by epoch, in native tokens
+fn released_refills(storage: &dyn Storage, block: &BlockInfo) -> StdResult<Vec<(u64, Uint128)>> {
+    let mut released = vec![];
+    for item in BUFFER_REFILLS.range(storage, None, None, Ordering::Ascending) {
+        let (id, amount) = item?;
+        if PROCESSED_EPOCHS
+            .load(storage, id)?
+            .release_at
+            .is_expired(block)
+        {
+            released.push((id, amount));
+        }
+    }
+    Ok(released)
+}
+
+fn add_to_buffer(supply: &mut Supply, amount: Uint128) {
+    supply.refilling -= amount;
+    supply.buffer += amount;
+}
+
+/// Moves refills that have arrived into the buffer. This must be done before looking at the
+/// contract balance, which holds them by then
+pub fn settle_refills(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    supply: &mut Supply,
+) -> StdResult<()> {
+    for (id, amount) in released_refills(storage, block)? {
+        add_to_buffer(supply, amount);
+        BUFFER_REFILLS.remove(storage, id);
+    }
+    Ok(())
+}
+
+/// Pays `amount` out of the buffer, and what it cannot cover out of the refills still on their
+/// way (which are released before any new epoch)
+pub fn draw_liquid(
+    storage: &mut dyn Storage,
+    supply: &mut Supply,
+    amount: Uint128,
+) -> Result<(), ContractError> {
+    let from_buffer = amount.min(supply.buffer);
+    supply.buffer -= from_buffer;
+    let mut missing = amount - from_buffer;
+    let refills = BUFFER_REFILLS
+        .range(storage, None, None, Ordering::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (id, refill) in refills {
+        if missing.is_zero() {
+            break;
+        }
+        let taken = refill.min(missing);
+        if taken == refill {
+            BUFFER_REFILLS.remove(storage, id);
+        } else {
+            BUFFER_REFILLS.save(storage, id, &(refill - taken))?;
+        }
+        supply.refilling -= taken;
+        missing -= taken;
+    }
+    if !missing.is_zero() {
+        return Err(ContractError::InsufficientBuffer {
+            available: amount - missing,
+        });
+    }
+    Ok(())
+}
+
+pub fn execute_instant_unbond(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let invest = INVESTMENT.load(deps.storage)?;
+    // ensure it is big enough to care
+    if amount < invest.min_withdrawal {
+        return Err(ContractError::UnbondTooSmall {
+            min_bonded: invest.min_withdrawal,
+            denom: invest.bond_denom,
+        });
+    }
+
+    // burn from the original caller, which also ensures there is a supply to price them against
+    execute_burn(deps.branch(), env.clone(), info.clone(), amount)?;
+    update_votes(deps.storage, &env.block, &[&info.sender])?;
+
+    // price the tokens with the real delegations, and what has arrived in the buffer
+    let bonded = total(&get_delegations(&deps.querier, &env.contract.address)?);
+    let mut supply = TOTAL_SUPPLY.load(deps.storage)?;
+    let slashed = sync_bonded(deps.storage, &env.block, &mut supply, bonded)?;
+    settle_refills(deps.storage, &env.block, &mut supply)?;
+
+    // the fee stays in the buffer, for the remaining holders
+    let worth = amount.multiply_ratio(supply.value(), supply.issued);
+    let fee = bps_of(worth, invest.instant_unbond_fee_bps);
+    let payout = worth - fee;
+    if payout > supply.buffer {
+        return Err(ContractError::InsufficientBuffer {
+            available: supply.buffer,
+        });
+    }
+
+    record_unbond(deps.storage, &supply, &info.sender, amount)?;
+    supply.buffer -= payout;
+    supply.issued -= amount;
+    TOTAL_SUPPLY.save(deps.storage, &supply)?;
+    // the fee raises the rate for the remaining holders
+    record_rate(deps.storage, &env.block, &supply)?;
+
+    let res = Response::new()
+        .add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: vec![coin(payout.u128(), &invest.bond_denom)],
+        })
+        .add_events(slashed)
+        .add_attribute("action", "instant_unbond")
+        .add_attribute("from", info.sender)
+        .add_attribute("burnt", amount)
+        .add_attribute("payout", payout)
+        .add_attribute("fee", fee);
+    Ok(res)
+}
+
+pub fn query_buffer(deps: Deps, env: Env) -> StdResult<BufferResponse> {
+    let invest = INVESTMENT.load(deps.storage)?;
+    let mut supply = TOTAL_SUPPLY.load(deps.storage)?;
+    for (_, amount) in released_refills(deps.storage, &env.block)? {
+        add_to_buffer(&mut supply, amount);
+    }
+
+    let target = bps_of(supply.value(), invest.buffer_target_bps);
+    let utilisation = if target.is_zero() {
+        Decimal::zero()
+    } else {
+        Decimal::from_ratio(target.saturating_sub(supply.buffer), target)
+    };
+    Ok(BufferResponse {
+        available: supply.buffer,
+        refilling: supply.refilling,
+        target,
+        utilisation,
+    })
+}
@@ -0,0 +1,216 @@
+use cosmwasm_std::{Decimal, DepsMut, Event, MessageInfo, Response, Uint128};
+use cw_utils::Duration;
+
+use crate::buffer::validate_buffer;
+use crate::error::ContractError;
+use crate::fees::validate_fees;
+use crate::state::{ExitTaxMode, INVESTMENT, PENDING_OWNER};
+
+/// The owner can never take more than this on unbonding
+pub const MAX_EXIT_TAX_PERCENT: u64 = 10;
+
+pub fn validate_exit_tax(exit_tax: Decimal) -> Result<(), ContractError> {
+    let max = Decimal::percent(MAX_EXIT_TAX_PERCENT);
+    if exit_tax > max {
+        return Err(ContractError::ExitTaxTooHigh { max });
+    }
+    Ok(())
+}
+
+/// Unbonds and claims below the minimum withdrawal fail, so it can't be set high enough to
+/// lock holders in. This is one token of a denom with 6 decimals
+pub const MAX_MIN_WITHDRAWAL: Uint128 = Uint128::new(1_000_000);
+
+pub fn validate_min_withdrawal(min_withdrawal: Uint128) -> Result<(), ContractError> {
+    if min_withdrawal > MAX_MIN_WITHDRAWAL {
+        return Err(ContractError::MinWithdrawalTooHigh {
+            max: MAX_MIN_WITHDRAWAL,
+        });
+    }
+    Ok(())
+}
+
+/// Longest unbonding period, in seconds or blocks. Well beyond any chain's unbonding time, but
+/// claims can't be locked away (or their release overflow)
+pub const MAX_UNBONDING_PERIOD: u64 = 90 * 24 * 60 * 60;
+
+/// Longest unbonding epoch, in seconds or blocks, so unbond requests can't be held back forever
+pub const MAX_UNBONDING_EPOCH: u64 = 14 * 24 * 60 * 60;
+
+fn duration_value(duration: Duration) -> u64 {
+    match duration {
+        Duration::Time(seconds) => seconds,
+        Duration::Height(blocks) => blocks,
+    }
+}
+
+/// Claims must wait for the undelegated tokens to arrive
+pub fn validate_unbonding_period(period: Duration) -> Result<(), ContractError> {
+    if matches!(period, Duration::Time(0) | Duration::Height(0)) {
+        return Err(ContractError::ZeroUnbondingPeriod {});
+    }
+    if duration_value(period) > MAX_UNBONDING_PERIOD {
+        return Err(ContractError::UnbondingPeriodTooLong {
+            max: MAX_UNBONDING_PERIOD,
+        });
+    }
+    Ok(())
+}
+
+pub fn validate_unbonding_epoch(epoch: Duration) -> Result<(), ContractError> {
+    if duration_value(epoch) > MAX_UNBONDING_EPOCH {
+        return Err(ContractError::UnbondingEpochTooLong {
+            max: MAX_UNBONDING_EPOCH,
+        });
+    }
+    Ok(())
+}
+
+/// Updates must not pay claims out sooner than the chain releases the tokens. Periods in
+/// blocks and in seconds can't be compared, so they must be given like the first one
+fn check_min_unbonding_period(period: Duration, min: Duration) -> Result<(), ContractError> {
+    let long_enough = match (period, min) {
+        (Duration::Time(period), Duration::Time(min)) => period >= min,
+        (Duration::Height(period), Duration::Height(min)) => period >= min,
+        _ => false,
+    };
+    if !long_enough {
+        return Err(ContractError::UnbondingPeriodTooShort { min });
+    }
+    Ok(())
+}
+
+/// Changes the given settings (owner only). A new unbonding period only applies to epochs
+/// processed afterwards
+#[allow(clippy::too_many_arguments)]
+pub fn execute_update_config(
+    deps: DepsMut,
+    info: MessageInfo,
+    exit_tax: Option<Decimal>,
+    min_withdrawal: Option<Uint128>,
+    unbonding_period: Option<Duration>,
+    unbonding_epoch: Option<Duration>,
+    keeper_bounty_bps: Option<u16>,
+    commission_bps: Option<u16>,
+    treasury: Option<String>,
+    exit_tax_mode: Option<ExitTaxMode>,
+    buffer_target_bps: Option<u16>,
+    instant_unbond_fee_bps: Option<u16>,
+) -> Result<Response, ContractError> {
+    let mut invest = INVESTMENT.load(deps.storage)?;
+    if info.sender != invest.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut event = Event::new("update_config");
+    if let Some(exit_tax) = exit_tax {
+        validate_exit_tax(exit_tax)?;
+        invest.exit_tax = exit_tax;
+        event = event.add_attribute("exit_tax", exit_tax.to_string());
+    }
+    if let Some(min_withdrawal) = min_withdrawal {
+        validate_min_withdrawal(min_withdrawal)?;
+        invest.min_withdrawal = min_withdrawal;
+        event = event.add_attribute("min_withdrawal", min_withdrawal);
+    }
+    if let Some(period) = unbonding_period {
+        validate_unbonding_period(period)?;
+        check_min_unbonding_period(period, invest.min_unbonding_period)?;
+        invest.unbonding_period = period;
+        event = event.add_attribute("unbonding_period", period.to_string());
+    }
+    if let Some(epoch) = unbonding_epoch {
+        validate_unbonding_epoch(epoch)?;
+        invest.unbonding_epoch = epoch;
+        event = event.add_attribute("unbonding_epoch", epoch.to_string());
+    }
+    if let Some(bps) = keeper_bounty_bps {
+        invest.keeper_bounty_bps = bps;
+        event = event.add_attribute("keeper_bounty_bps", bps.to_string());
+    }
+    if let Some(bps) = commission_bps {
+        invest.commission_bps = bps;
+        event = event.add_attribute("commission_bps", bps.to_string());
+    }
+    if let Some(treasury) = treasury {
+        invest.treasury = deps.api.addr_validate(&treasury)?;
+        event = event.add_attribute("treasury", &invest.treasury);
+    }
+    if let Some(mode) = exit_tax_mode {
+        invest.exit_tax_mode = mode;
+        event = event.add_attribute("exit_tax_mode", mode.as_str());
+    }
+    if let Some(bps) = buffer_target_bps {
+        invest.buffer_target_bps = bps;
+        event = event.add_attribute("buffer_target_bps", bps.to_string());
+    }
+    if let Some(bps) = instant_unbond_fee_bps {
+        invest.instant_unbond_fee_bps = bps;
+        event = event.add_attribute("instant_unbond_fee_bps", bps.to_string());
+    }
+    validate_fees(invest.keeper_bounty_bps, invest.commission_bps)?;
+    validate_buffer(invest.buffer_target_bps, invest.instant_unbond_fee_bps)?;
+    INVESTMENT.save(deps.storage, &invest)?;
+
+    let res = Response::new()
+        .add_event(event)
+        .add_attribute("action", "update_config");
+    Ok(res)
+}
+
+/// Proposes a new owner (owner only), who must accept before anything changes.
+/// `None` withdraws a pending proposal
+pub fn execute_transfer_ownership(
+    deps: DepsMut,
+    info: MessageInfo,
+    new_owner: Option<String>,
+) -> Result<Response, ContractError> {
+    let invest = INVESTMENT.load(deps.storage)?;
+    if info.sender != invest.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut event = Event::new("transfer_ownership").add_attribute("owner", invest.owner);
+    match new_owner {
+        Some(new_owner) => {
+            let new_owner = deps.api.addr_validate(&new_owner)?;
+            PENDING_OWNER.save(deps.storage, &new_owner)?;
+            event = event.add_attribute("pending_owner", new_owner);
+        }
+        None => {
+            PENDING_OWNER.remove(deps.storage);
+            event = event.add_attribute("pending_owner", "none");
+        }
+    }
+
+    let res = Response::new()
+        .add_event(event)
+        .add_attribute("action", "transfer_ownership");
+    Ok(res)
+}
+
+/// Completes a transfer, called by the proposed owner
+pub fn execute_accept_ownership(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let pending = PENDING_OWNER
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NoPendingOwner {})?;
+    if info.sender != pending {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut invest = INVESTMENT.load(deps.storage)?;
+    let event = Event::new("accept_ownership")
+        .add_attribute("previous_owner", &invest.owner)
+        .add_attribute("owner", &pending);
+    invest.owner = pending;
+    INVESTMENT.save(deps.storage, &invest)?;
+    PENDING_OWNER.remove(deps.storage);
+
+    let res = Response::new()
+        .add_event(event)
+        .add_attribute("action", "accept_ownership");
+    Ok(res)
+}
@@ -1,11 +1,12 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    coin, to_binary, Addr, BankMsg, Binary, Decimal, Deps, DepsMut, DistributionMsg, Env,
-    MessageInfo, QuerierWrapper, Response, StakingMsg, StdError, StdResult, Uint128, WasmMsg,
+    coin, from_binary, to_binary, Addr, BankMsg, Binary, Decimal, Deps, DepsMut, DistributionMsg,
+    Empty, Env, MessageInfo, Response, StakingMsg, StdError, StdResult, Uint128, WasmMsg,
 };
 
 use cw2::set_contract_version;
+use cw20::Cw20ReceiveMsg;
 use cw20_base::allowances::{
     execute_burn_from, execute_decrease_allowance, execute_increase_allowance, execute_send_from,
     execute_transfer_from, query_allowance,
@@ -15,9 +16,38 @@ use cw20_base::contract::{
 };
 use cw20_base::state::{MinterData, TokenInfo, TOKEN_INFO};
 
+use crate::buffer::{
+    buffer_deficit, execute_instant_unbond, query_buffer, settle_refills, validate_buffer,
+};
+use crate::config::{
+    execute_accept_ownership, execute_transfer_ownership, execute_update_config, validate_exit_tax,
+    validate_min_withdrawal, validate_unbonding_epoch, validate_unbonding_period,
+};
+use crate::epochs::{
+    claim_epochs, execute_process_epoch, query_claims, query_pending_epoch, query_processed_epochs,
+    queue_unbond,
+};
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, InstantiateMsg, InvestmentResponse, QueryMsg};
-use crate::state::{InvestmentInfo, Supply, CLAIMS, INVESTMENT, TOTAL_SUPPLY};
+use crate::fees::{split_rewards, validate_fees};
+use crate::gov::{
+    execute_cast_vote, execute_register_proposal, execute_vote, query_proposal, query_vote,
+    update_votes,
+};
+use crate::history::{query_estimated_apr, query_rate_history, record_rate};
+use crate::limits::{
+    check_bond, execute_update_allowlist, execute_update_bond_limits, query_allowlist,
+    query_headroom, record_bond, record_unbond,
+};
+use crate::msg::{ExecuteMsg, InstantiateMsg, InvestmentResponse, QueryMsg, ReceiveMsg};
+use crate::slashing::sync_bonded;
+use crate::state::{
+    ExitTaxMode, InvestmentInfo, Supply, ALLOWLIST, FEES_PAID, INVESTMENT, PENDING_OWNER,
+    TOTAL_SUPPLY,
+};
+use crate::validators::{
+    bond_allocation, execute_redelegate, execute_set_validators, get_delegations, query_validators,
+    total, validate_validators,
+};
 
 const FALLBACK_RATIO: Decimal = Decimal::one();
 
@@ -34,13 +64,14 @@ pub fn instantiate(
 ) -> Result<Response, ContractError> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
-    // ensure the validator is registered
-    let vals = deps.querier.query_all_validators()?;
-    if !vals.iter().any(|v| v.address == msg.validator) {
-        return Err(ContractError::NotInValidatorSet {
-            validator: msg.validator,
-        });
-    }
+    // ensure the validators are registered
+    validate_validators(&deps.querier, &msg.validators)?;
+    validate_fees(msg.keeper_bounty_bps, msg.commission_bps)?;
+    validate_exit_tax(msg.exit_tax)?;
+    validate_unbonding_period(msg.unbonding_period)?;
+    validate_unbonding_epoch(msg.unbonding_epoch)?;
+    validate_min_withdrawal(msg.min_withdrawal)?;
+    validate_buffer(msg.buffer_target_bps, msg.instant_unbond_fee_bps)?;
 
     // store token info using cw20-base format
     let data = TokenInfo {
@@ -56,16 +87,36 @@ pub fn instantiate(
     };
     TOKEN_INFO.save(deps.storage, &data)?;
 
+    let treasury = match msg.treasury {
+        Some(treasury) => deps.api.addr_validate(&treasury)?,
+        None => info.sender.clone(),
+    };
     let denom = deps.querier.query_bonded_denom()?;
     let invest = InvestmentInfo {
         owner: info.sender,
         exit_tax: msg.exit_tax,
+        treasury,
+        exit_tax_mode: msg.exit_tax_mode,
         unbonding_period: msg.unbonding_period,
+        min_unbonding_period: msg.unbonding_period,
+        unbonding_epoch: msg.unbonding_epoch,
         bond_denom: denom,
-        validator: msg.validator,
+        validators: msg.validators,
         min_withdrawal: msg.min_withdrawal,
+        keeper_bounty_bps: msg.keeper_bounty_bps,
+        commission_bps: msg.commission_bps,
+        buffer_target_bps: msg.buffer_target_bps,
+        instant_unbond_fee_bps: msg.instant_unbond_fee_bps,
+        max_bonded: msg.max_bonded,
+        max_bonded_per_address: msg.max_bonded_per_address,
+        allowlist_enabled: msg.allowlist.is_some(),
     };
     INVESTMENT.save(deps.storage, &invest)?;
+    for address in msg.allowlist.unwrap_or_default() {
+        let address = deps.api.addr_validate(&address)?;
+        ALLOWLIST.save(deps.storage, &address, &Empty {})?;
+    }
+    FEES_PAID.save(deps.storage, &Default::default())?;
 
     // set supply to 0
     let supply = Supply::default();
@@ -76,7 +127,7 @@ pub fn instantiate(
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     msg: ExecuteMsg,
@@ -84,20 +135,103 @@ pub fn execute(
     match msg {
         ExecuteMsg::Bond {} => bond(deps, env, info),
         ExecuteMsg::Unbond { amount } => unbond(deps, env, info, amount),
-        ExecuteMsg::Claim {} => claim(deps, env, info),
+        ExecuteMsg::ProcessUnbondingEpoch {} => execute_process_epoch(deps, env, info),
+        ExecuteMsg::InstantUnbond { amount } => execute_instant_unbond(deps, env, info, amount),
+        ExecuteMsg::Claim { recipient } => claim(deps, env, info, recipient),
+        ExecuteMsg::ClaimFor { address } => claim_for(deps, env, address),
         ExecuteMsg::Reinvest {} => reinvest(deps, env, info),
-        ExecuteMsg::_BondAllTokens {} => _bond_all_tokens(deps, env, info),
-
-        // these all come from cw20-base to implement the cw20 standard
+        ExecuteMsg::_BondAllTokens { keeper } => _bond_all_tokens(deps, env, info, keeper),
+        ExecuteMsg::SetValidators { validators } => execute_set_validators(deps, info, validators),
+        ExecuteMsg::Redelegate { from, to, amount } => {
+            execute_redelegate(deps, env, info, from, to, amount)
+        }
+        ExecuteMsg::UpdateConfig {
+            exit_tax,
+            min_withdrawal,
+            unbonding_period,
+            unbonding_epoch,
+            keeper_bounty_bps,
+            commission_bps,
+            treasury,
+            exit_tax_mode,
+            buffer_target_bps,
+            instant_unbond_fee_bps,
+        } => execute_update_config(
+            deps,
+            info,
+            exit_tax,
+            min_withdrawal,
+            unbonding_period,
+            unbonding_epoch,
+            keeper_bounty_bps,
+            commission_bps,
+            treasury,
+            exit_tax_mode,
+            buffer_target_bps,
+            instant_unbond_fee_bps,
+        ),
+        ExecuteMsg::TransferOwnership { new_owner } => {
+            execute_transfer_ownership(deps, info, new_owner)
+        }
+        ExecuteMsg::AcceptOwnership {} => execute_accept_ownership(deps, info),
+        ExecuteMsg::UpdateBondLimits {
+            max_bonded,
+            max_bonded_per_address,
+            allowlist_enabled,
+        } => execute_update_bond_limits(
+            deps,
+            info,
+            max_bonded,
+            max_bonded_per_address,
+            allowlist_enabled,
+        ),
+        ExecuteMsg::UpdateAllowlist { add, remove } => {
+            execute_update_allowlist(deps, info, add, remove)
+        }
+        ExecuteMsg::Receive(msg) => receive(deps, env, info, msg),
+        ExecuteMsg::RegisterProposal {
+            proposal_id,
+            voting_ends,
+        } => execute_register_proposal(deps, env, info, proposal_id, voting_ends),
+        ExecuteMsg::Vote { proposal_id, vote } => execute_vote(deps, env, info, proposal_id, vote),
+        ExecuteMsg::CastVote { proposal_id } => execute_cast_vote(deps, env, info, proposal_id),
+
+        // these all come from cw20-base to implement the cw20 standard,
+        // and the votes of whoever's balance changed are recounted after
         ExecuteMsg::Transfer { recipient, amount } => {
-            Ok(execute_transfer(deps, env, info, recipient, amount)?)
+            let res = execute_transfer(
+                deps.branch(),
+                env.clone(),
+                info.clone(),
+                recipient.clone(),
+                amount,
+            )?;
+            let recipient = deps.api.addr_validate(&recipient)?;
+            update_votes(deps.storage, &env.block, &[&info.sender, &recipient])?;
+            Ok(res)
+        }
+        ExecuteMsg::Burn { amount } => {
+            let res = execute_burn(deps.branch(), env.clone(), info.clone(), amount)?;
+            update_votes(deps.storage, &env.block, &[&info.sender])?;
+            Ok(res)
         }
-        ExecuteMsg::Burn { amount } => Ok(execute_burn(deps, env, info, amount)?),
         ExecuteMsg::Send {
             contract,
             amount,
             msg,
-        } => Ok(execute_send(deps, env, info, contract, amount, msg)?),
+        } => {
+            let res = execute_send(
+                deps.branch(),
+                env.clone(),
+                info.clone(),
+                contract.clone(),
+                amount,
+                msg,
+            )?;
+            let contract = deps.api.addr_validate(&contract)?;
+            update_votes(deps.storage, &env.block, &[&info.sender, &contract])?;
+            Ok(res)
+        }
         ExecuteMsg::IncreaseAllowance {
             spender,
             amount,
@@ -116,56 +250,50 @@ pub fn execute(
             owner,
             recipient,
             amount,
-        } => Ok(execute_transfer_from(
-            deps, env, info, owner, recipient, amount,
-        )?),
+        } => {
+            let res = execute_transfer_from(
+                deps.branch(),
+                env.clone(),
+                info,
+                owner.clone(),
+                recipient.clone(),
+                amount,
+            )?;
+            let owner = deps.api.addr_validate(&owner)?;
+            let recipient = deps.api.addr_validate(&recipient)?;
+            update_votes(deps.storage, &env.block, &[&owner, &recipient])?;
+            Ok(res)
+        }
         ExecuteMsg::BurnFrom { owner, amount } => {
-            Ok(execute_burn_from(deps, env, info, owner, amount)?)
+            let res = execute_burn_from(deps.branch(), env.clone(), info, owner.clone(), amount)?;
+            let owner = deps.api.addr_validate(&owner)?;
+            update_votes(deps.storage, &env.block, &[&owner])?;
+            Ok(res)
         }
         ExecuteMsg::SendFrom {
             owner,
             contract,
             amount,
             msg,
-        } => Ok(execute_send_from(
-            deps, env, info, owner, contract, amount, msg,
-        )?),
-    }
-}
-
-// get_bonded returns the total amount of delegations from contract
-// it ensures they are all the same denom
-fn get_bonded(querier: &QuerierWrapper, contract: &Addr) -> Result<Uint128, ContractError> {
-    let bonds = querier.query_all_delegations(contract)?;
-    if bonds.is_empty() {
-        return Ok(Uint128::zero());
-    }
-    let denom = bonds[0].amount.denom.as_str();
-    bonds.iter().fold(Ok(Uint128::zero()), |racc, d| {
-        let acc = racc?;
-        if d.amount.denom.as_str() != denom {
-            Err(ContractError::DifferentBondDenom {
-                denom1: denom.into(),
-                denom2: d.amount.denom.to_string(),
-            })
-        } else {
-            Ok(acc + d.amount.amount)
+        } => {
+            let res = execute_send_from(
+                deps.branch(),
+                env.clone(),
+                info,
+                owner.clone(),
+                contract.clone(),
+                amount,
+                msg,
+            )?;
+            let owner = deps.api.addr_validate(&owner)?;
+            let contract = deps.api.addr_validate(&contract)?;
+            update_votes(deps.storage, &env.block, &[&owner, &contract])?;
+            Ok(res)
         }
-    })
-}
-
-fn assert_bonds(supply: &Supply, bonded: Uint128) -> Result<(), ContractError> {
-    if supply.bonded != bonded {
-        Err(ContractError::BondedMismatch {
-            stored: supply.bonded,
-            queried: bonded,
-        })
-    } else {
-        Ok(())
     }
 }
 
-pub fn bond(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+pub fn bond(mut deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
     // ensure we have the proper denom
     let invest = INVESTMENT.load(deps.storage)?;
     // payment finds the proper coin (or throws an error)
@@ -178,20 +306,26 @@ pub fn bond(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, Cont
         })?;
 
     // bonded is the total number of tokens we have delegated from this address
-    let bonded = get_bonded(&deps.querier, &env.contract.address)?;
+    let delegations = get_delegations(&deps.querier, &env.contract.address)?;
+    let bonded = total(&delegations);
 
     // calculate to_mint and update total supply
     let mut supply = TOTAL_SUPPLY.load(deps.storage)?;
-    // TODO: this is just a safety assertion - do we keep it, or remove caching?
-    // in the end supply is just there to cache the (expected) results of get_bonded() so we don't
-    // have expensive queries everywhere
-    assert_bonds(&supply, bonded)?;
-    let to_mint = if supply.issued.is_zero() || bonded.is_zero() {
+    // supply caches the (expected) results of get_delegations(), unless we were slashed
+    let slashed = sync_bonded(deps.storage, &env.block, &mut supply, bonded)?;
+    check_bond(deps.storage, &invest, &supply, &info.sender, payment.amount)?;
+    record_bond(deps.storage, &info.sender, payment.amount)?;
+    let value = supply.value();
+    let to_mint = if supply.issued.is_zero() || value.is_zero() {
         FALLBACK_RATIO * payment.amount
     } else {
-        payment.amount.multiply_ratio(supply.issued, bonded)
+        payment.amount.multiply_ratio(supply.issued, value)
     };
-    supply.bonded = bonded + payment.amount;
+    // keep back what the buffer is missing, and bond the rest
+    let buffered = buffer_deficit(&invest, &supply, value + payment.amount).min(payment.amount);
+    let to_bond = payment.amount - buffered;
+    supply.buffer += buffered;
+    supply.bonded = bonded + to_bond;
     supply.issued += to_mint;
     TOTAL_SUPPLY.save(deps.storage, &supply)?;
 
@@ -200,18 +334,30 @@ pub fn bond(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, Cont
         sender: env.contract.address.clone(),
         funds: vec![],
     };
-    execute_mint(deps, env, sub_info, info.sender.to_string(), to_mint)?;
-
-    // bond them to the validator
+    execute_mint(
+        deps.branch(),
+        env.clone(),
+        sub_info,
+        info.sender.to_string(),
+        to_mint,
+    )?;
+    update_votes(deps.storage, &env.block, &[&info.sender])?;
+
+    // bond them to the validators, moving the set towards its targets
+    let delegate = bond_allocation(&invest.validators, &delegations, to_bond)
+        .into_iter()
+        .map(|(validator, amount)| StakingMsg::Delegate {
+            validator,
+            amount: coin(amount.u128(), &invest.bond_denom),
+        });
     let res = Response::new()
-        .add_message(StakingMsg::Delegate {
-            validator: invest.validator,
-            amount: payment.clone(),
-        })
+        .add_messages(delegate)
+        .add_events(slashed)
         .add_attribute("action", "bond")
         .add_attribute("from", info.sender)
-        .add_attribute("bonded", payment.amount)
-        .add_attribute("minted", to_mint);
+        .add_attribute("bonded", to_bond)
+        .add_attribute("minted", to_mint)
+        .add_attribute("buffered", buffered);
     Ok(res)
 }
 
@@ -220,6 +366,52 @@ pub fn unbond(
     env: Env,
     info: MessageInfo,
     amount: Uint128,
+) -> Result<Response, ContractError> {
+    // move the tokens from the original caller to the contract, which holds them until the
+    // epoch is processed
+    execute_transfer(
+        deps.branch(),
+        env.clone(),
+        info.clone(),
+        env.contract.address.to_string(),
+        amount,
+    )?;
+    update_votes(deps.storage, &env.block, &[&info.sender])?;
+    unbond_held(deps, env, info.sender.clone(), info.sender, amount)
+}
+
+/// Handles derivative tokens sent to the contract with cw20 `Send`
+pub fn receive(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    wrapper: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    // only our own token is accepted, which calls us as the contract
+    if info.sender != env.contract.address {
+        return Err(ContractError::Unauthorized {});
+    }
+    let sender = deps.api.addr_validate(&wrapper.sender)?;
+    let msg: ReceiveMsg = from_binary(&wrapper.msg)?;
+    match msg {
+        ReceiveMsg::Unbond { recipient } => {
+            let recipient = match recipient {
+                Some(recipient) => deps.api.addr_validate(&recipient)?,
+                None => sender.clone(),
+            };
+            unbond_held(deps, env, sender, recipient, wrapper.amount)
+        }
+    }
+}
+
+/// Queues `amount` derivative tokens of `sender`, which the contract holds already, for
+/// unbonding. The claim goes to `recipient`
+fn unbond_held(
+    mut deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    recipient: Addr,
+    amount: Uint128,
 ) -> Result<Response, ContractError> {
     let invest = INVESTMENT.load(deps.storage)?;
     // ensure it is big enough to care
@@ -229,67 +421,83 @@ pub fn unbond(
             denom: invest.bond_denom,
         });
     }
+    let mut supply = TOTAL_SUPPLY.load(deps.storage)?;
+    record_unbond(deps.storage, &supply, &sender, amount)?;
+
     // calculate tax and remainer to unbond
     let tax = amount * invest.exit_tax;
 
-    // burn from the original caller
-    execute_burn(deps.branch(), env.clone(), info.clone(), amount)?;
+    // the rest is unbonded along with everything else queued in this epoch
+    let remainder = amount.checked_sub(tax).map_err(StdError::overflow)?;
+    let epoch = queue_unbond(deps.storage, &env.block, &invest, &recipient, remainder)?;
+
     if tax > Uint128::zero() {
+        // call into cw20-base as self, as the contract holds the tokens now
         let sub_info = MessageInfo {
             sender: env.contract.address.clone(),
             funds: vec![],
         };
-        // call into cw20-base to mint tokens to owner, call as self as no one else is allowed
-        execute_mint(
-            deps.branch(),
-            env.clone(),
-            sub_info,
-            invest.owner.to_string(),
-            tax,
-        )?;
+        match invest.exit_tax_mode {
+            ExitTaxMode::Treasury => {
+                execute_transfer(
+                    deps.branch(),
+                    env.clone(),
+                    sub_info,
+                    invest.treasury.to_string(),
+                    tax,
+                )?;
+                update_votes(deps.storage, &env.block, &[&invest.treasury])?;
+            }
+            ExitTaxMode::Native => {
+                queue_unbond(deps.storage, &env.block, &invest, &invest.treasury, tax)?;
+            }
+            ExitTaxMode::Burn => {
+                execute_burn(deps.branch(), env.clone(), sub_info, tax)?;
+                supply.issued = supply.issued.checked_sub(tax).map_err(StdError::overflow)?;
+                TOTAL_SUPPLY.save(deps.storage, &supply)?;
+                // every remaining token is worth more now
+                record_rate(deps.storage, &env.block, &supply)?;
+            }
+        }
     }
 
-    // re-calculate bonded to ensure we have real values
-    // bonded is the total number of tokens we have delegated from this address
-    let bonded = get_bonded(&deps.querier, &env.contract.address)?;
-
-    // calculate how many native tokens this is worth and update supply
-    let remainder = amount.checked_sub(tax).map_err(StdError::overflow)?;
-    let mut supply = TOTAL_SUPPLY.load(deps.storage)?;
-    // TODO: this is just a safety assertion - do we keep it, or remove caching?
-    // in the end supply is just there to cache the (expected) results of get_bonded() so we don't
-    // have expensive queries everywhere
-    assert_bonds(&supply, bonded)?;
-    let unbond = remainder.multiply_ratio(bonded, supply.issued);
-    supply.bonded = bonded.checked_sub(unbond).map_err(StdError::overflow)?;
-    supply.issued = supply
-        .issued
-        .checked_sub(remainder)
-        .map_err(StdError::overflow)?;
-    supply.claims += unbond;
-    TOTAL_SUPPLY.save(deps.storage, &supply)?;
-
-    CLAIMS.create_claim(
-        deps.storage,
-        &info.sender,
-        unbond,
-        invest.unbonding_period.after(&env.block),
-    )?;
-
-    // unbond them
     let res = Response::new()
-        .add_message(StakingMsg::Undelegate {
-            validator: invest.validator,
-            amount: coin(unbond.u128(), &invest.bond_denom),
-        })
         .add_attribute("action", "unbond")
-        .add_attribute("to", info.sender)
-        .add_attribute("unbonded", unbond)
-        .add_attribute("burnt", amount);
+        .add_attribute("from", sender)
+        .add_attribute("epoch", epoch.id.to_string())
+        .add_attribute("queued", remainder)
+        .add_attribute("tax", tax)
+        .add_attribute("exit_tax_mode", invest.exit_tax_mode.as_str())
+        .add_attribute("recipient", recipient);
     Ok(res)
 }
 
-pub fn claim(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+/// Pays out the matured claims of the sender, to `recipient` if given
+pub fn claim(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    recipient: Option<String>,
+) -> Result<Response, ContractError> {
+    let recipient = match recipient {
+        Some(recipient) => deps.api.addr_validate(&recipient)?,
+        None => info.sender.clone(),
+    };
+    pay_claims(deps, env, info.sender, recipient)
+}
+
+/// Pushes the matured claims of `address` out to it, so keepers can do it for users
+pub fn claim_for(deps: DepsMut, env: Env, address: String) -> Result<Response, ContractError> {
+    let address = deps.api.addr_validate(&address)?;
+    pay_claims(deps, env, address.clone(), address)
+}
+
+fn pay_claims(
+    deps: DepsMut,
+    env: Env,
+    owner: Addr,
+    recipient: Addr,
+) -> Result<Response, ContractError> {
     // find how many tokens the contract has
     let invest = INVESTMENT.load(deps.storage)?;
     let mut balance = deps
@@ -299,46 +507,53 @@ pub fn claim(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, Con
         return Err(ContractError::BalanceTooSmall {});
     }
 
+    // the buffer is not for claims
+    let mut supply = TOTAL_SUPPLY.load(deps.storage)?;
+    settle_refills(deps.storage, &env.block, &mut supply)?;
+    let available = balance.amount.saturating_sub(supply.buffer);
+
     // check how much to send - min(balance, claims[sender]), and reduce the claim
     // Ensure we have enough balance to cover this and only send some claims if that is all we can cover
-    let to_send =
-        CLAIMS.claim_tokens(deps.storage, &info.sender, &env.block, Some(balance.amount))?;
+    let to_send = claim_epochs(deps.storage, &owner, &env.block, available)?;
     if to_send == Uint128::zero() {
         return Err(ContractError::NothingToClaim {});
     }
 
-    // update total supply (lower claim)
-    TOTAL_SUPPLY.update(deps.storage, |mut supply| -> StdResult<_> {
-        supply.claims = supply.claims.checked_sub(to_send)?;
-        Ok(supply)
-    })?;
+    // update total supply (lower claim). Claims are rounded down, so a few tokens of the total
+    // may be left over
+    supply.claims = supply.claims.saturating_sub(to_send);
+    TOTAL_SUPPLY.save(deps.storage, &supply)?;
 
-    // transfer tokens to the sender
+    // transfer tokens to the recipient
     balance.amount = to_send;
     let res = Response::new()
         .add_message(BankMsg::Send {
-            to_address: info.sender.to_string(),
+            to_address: recipient.to_string(),
             amount: vec![balance],
         })
         .add_attribute("action", "claim")
-        .add_attribute("from", info.sender)
-        .add_attribute("amount", to_send);
+        .add_attribute("from", owner)
+        .add_attribute("amount", to_send)
+        .add_attribute("recipient", recipient);
     Ok(res)
 }
 
 /// reinvest will withdraw all pending rewards,
 /// then issue a callback to itself via _bond_all_tokens
-/// to reinvest the new earnings (and anything else that accumulated)
-pub fn reinvest(deps: DepsMut, env: Env, _info: MessageInfo) -> Result<Response, ContractError> {
+/// to reinvest the new earnings (and anything else that accumulated).
+/// The caller is passed on, to be paid the keeper bounty
+pub fn reinvest(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
     let contract_addr = env.contract.address;
-    let invest = INVESTMENT.load(deps.storage)?;
-    let msg = to_binary(&ExecuteMsg::_BondAllTokens {})?;
+    let msg = to_binary(&ExecuteMsg::_BondAllTokens {
+        keeper: info.sender.into(),
+    })?;
 
-    // and bond them to the validator
+    // withdraw from every validator we delegate to, and bond them to the validators
+    let withdraw = get_delegations(&deps.querier, &contract_addr)?
+        .into_iter()
+        .map(|(validator, _)| DistributionMsg::WithdrawDelegatorReward { validator });
     let res = Response::new()
-        .add_message(DistributionMsg::WithdrawDelegatorReward {
-            validator: invest.validator,
-        })
+        .add_messages(withdraw)
         .add_message(WasmMsg::Execute {
             contract_addr: contract_addr.to_string(),
             msg,
@@ -351,6 +566,7 @@ pub fn _bond_all_tokens(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
+    keeper: String,
 ) -> Result<Response, ContractError> {
     // this is just meant as a call-back to ourself
     if info.sender != env.contract.address {
@@ -359,44 +575,97 @@ pub fn _bond_all_tokens(
 
     // find how many tokens we have to bond
     let invest = INVESTMENT.load(deps.storage)?;
-    let mut balance = deps
+    let balance = deps
         .querier
         .query_balance(&env.contract.address, &invest.bond_denom)?;
 
-    // we deduct pending claims from our account balance before reinvesting.
-    // if there is not enough funds, we just return a no-op
-    match TOTAL_SUPPLY.update(deps.storage, |mut supply| -> StdResult<_> {
-        balance.amount = balance.amount.checked_sub(supply.claims)?;
-        // this just triggers the "no op" case if we don't have min_withdrawal left to reinvest
-        balance.amount.checked_sub(invest.min_withdrawal)?;
-        supply.bonded += balance.amount;
-        Ok(supply)
-    }) {
-        Ok(_) => {}
-        // if it is below the minimum, we do a no-op (do not revert other state from withdrawal)
-        Err(StdError::Overflow { .. }) => return Ok(Response::default()),
-        Err(e) => return Err(ContractError::Std(e)),
-    }
-
-    // and bond them to the validator
-    let res = Response::new()
-        .add_message(StakingMsg::Delegate {
-            validator: invest.validator,
-            amount: balance.clone(),
-        })
+    // as this is called regularly, this is where slashing usually shows up first
+    let delegations = get_delegations(&deps.querier, &env.contract.address)?;
+    let mut supply = TOTAL_SUPPLY.load(deps.storage)?;
+    let bonded = total(&delegations);
+    let slashed = sync_bonded(deps.storage, &env.block, &mut supply, bonded)?;
+    settle_refills(deps.storage, &env.block, &mut supply)?;
+    let res = Response::new().add_events(slashed);
+
+    // we deduct pending claims and the buffer from our account balance before reinvesting.
+    // if there is not enough funds left (at least min_withdrawal), we only record any slashing
+    // (do not revert other state from withdrawal)
+    let rewards = match balance.amount.checked_sub(supply.claims + supply.buffer) {
+        Ok(amount) if amount >= invest.min_withdrawal => amount,
+        _ => {
+            TOTAL_SUPPLY.save(deps.storage, &supply)?;
+            record_rate(deps.storage, &env.block, &supply)?;
+            return Ok(res);
+        }
+    };
+
+    // set the fees aside, and bond the rest for the holders
+    let split = split_rewards(&invest, rewards);
+    supply.bonded += split.bonded;
+    TOTAL_SUPPLY.save(deps.storage, &supply)?;
+    record_rate(deps.storage, &env.block, &supply)?;
+    FEES_PAID.update(deps.storage, |mut paid| -> StdResult<_> {
+        paid.keeper_bounties += split.keeper_bounty;
+        paid.commission += split.commission;
+        Ok(paid)
+    })?;
+
+    let fees = vec![
+        (keeper.clone(), split.keeper_bounty),
+        (invest.owner.to_string(), split.commission),
+    ];
+    let payouts = fees
+        .into_iter()
+        .filter(|(_, amount)| !amount.is_zero())
+        .map(|(to_address, amount)| BankMsg::Send {
+            to_address,
+            amount: vec![coin(amount.u128(), &invest.bond_denom)],
+        });
+
+    // and bond them to the validators
+    let delegate = bond_allocation(&invest.validators, &delegations, split.bonded)
+        .into_iter()
+        .map(|(validator, amount)| StakingMsg::Delegate {
+            validator,
+            amount: coin(amount.u128(), &invest.bond_denom),
+        });
+    let res = res
+        .add_messages(payouts)
+        .add_messages(delegate)
         .add_attribute("action", "reinvest")
-        .add_attribute("bonded", balance.amount);
+        .add_attribute("rewards", rewards)
+        .add_attribute("keeper", keeper)
+        .add_attribute("keeper_bounty", split.keeper_bounty)
+        .add_attribute("commission", split.commission)
+        .add_attribute("bonded", split.bonded);
     Ok(res)
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         // custom queries
-        QueryMsg::Claims { address } => {
-            to_binary(&CLAIMS.query_claims(deps, &deps.api.addr_validate(&address)?)?)
+        QueryMsg::Claims { address } => to_binary(&query_claims(deps, address)?),
+        QueryMsg::PendingEpoch {} => to_binary(&query_pending_epoch(deps)?),
+        QueryMsg::ProcessedEpochs { start_after, limit } => {
+            to_binary(&query_processed_epochs(deps, start_after, limit)?)
         }
         QueryMsg::Investment {} => to_binary(&query_investment(deps)?),
+        QueryMsg::Buffer {} => to_binary(&query_buffer(deps, env)?),
+        QueryMsg::Validators {} => to_binary(&query_validators(deps, env)?),
+        QueryMsg::ExchangeRateHistory { start_after, limit } => {
+            to_binary(&query_rate_history(deps, start_after, limit)?)
+        }
+        QueryMsg::EstimatedApr { window } => to_binary(&query_estimated_apr(deps, env, window)?),
+        QueryMsg::Proposal { proposal_id } => to_binary(&query_proposal(deps, proposal_id)?),
+        QueryMsg::Vote {
+            proposal_id,
+            address,
+        } => to_binary(&query_vote(deps, proposal_id, address)?),
+        QueryMsg::Headroom { address } => to_binary(&query_headroom(deps, address)?),
+        QueryMsg::Allowlist { start_after, limit } => {
+            to_binary(&query_allowlist(deps, start_after, limit)?)
+        }
         // inherited from cw20-base
         QueryMsg::TokenInfo {} => to_binary(&query_token_info(deps)?),
         QueryMsg::Balance { address } => to_binary(&query_balance(deps, address)?),
@@ -412,16 +681,31 @@ pub fn query_investment(deps: Deps) -> StdResult<InvestmentResponse> {
 
     let res = InvestmentResponse {
         owner: invest.owner.to_string(),
+        pending_owner: PENDING_OWNER
+            .may_load(deps.storage)?
+            .map(|owner| owner.into()),
         exit_tax: invest.exit_tax,
-        validator: invest.validator,
+        treasury: invest.treasury.into(),
+        exit_tax_mode: invest.exit_tax_mode,
+        validators: invest.validators,
+        unbonding_period: invest.unbonding_period,
+        unbonding_epoch: invest.unbonding_epoch,
         min_withdrawal: invest.min_withdrawal,
+        keeper_bounty_bps: invest.keeper_bounty_bps,
+        commission_bps: invest.commission_bps,
+        fees_paid: FEES_PAID.load(deps.storage)?,
         token_supply: supply.issued,
         staked_tokens: coin(supply.bonded.u128(), &invest.bond_denom),
         nominal_value: if supply.issued.is_zero() {
             FALLBACK_RATIO
         } else {
-            Decimal::from_ratio(supply.bonded, supply.issued)
+            Decimal::from_ratio(supply.value(), supply.issued)
         },
+        buffer_target_bps: invest.buffer_target_bps,
+        instant_unbond_fee_bps: invest.instant_unbond_fee_bps,
+        max_bonded: invest.max_bonded,
+        max_bonded_per_address: invest.max_bonded_per_address,
+        allowlist_enabled: invest.allowlist_enabled,
     };
     Ok(res)
 }
@@ -431,14 +715,21 @@ mod tests {
     use super::*;
     use std::str::FromStr;
 
+    use crate::config::{MAX_UNBONDING_EPOCH, MAX_UNBONDING_PERIOD};
+    use crate::msg::{
+        AllowlistResponse, BufferResponse, EpochClaim, EstimatedAprResponse,
+        ExchangeRateHistoryResponse, HeadroomResponse, PendingEpochResponse,
+        ProcessedEpochsResponse, ValidatorAllocation, ValidatorsResponse,
+    };
+    use crate::state::{FeesPaid, PendingEpoch, ProcessedEpoch, ValidatorWeight};
     use cosmwasm_std::testing::{
-        mock_dependencies, mock_env, mock_info, MockQuerier, MOCK_CONTRACT_ADDR,
+        mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+        MOCK_CONTRACT_ADDR,
     };
     use cosmwasm_std::{
-        coins, Coin, CosmosMsg, Decimal, FullDelegation, OverflowError, OverflowOperation,
-        Validator,
+        coins, Addr, Coin, CosmosMsg, Decimal, FullDelegation, OverflowError, OverflowOperation,
+        OwnedDeps, SubMsg, Validator,
     };
-    use cw_controllers::Claim;
     use cw_utils::{Duration, DAY, HOUR, WEEK};
 
     fn sample_validator(addr: &str) -> Validator {
@@ -487,15 +778,33 @@ mod tests {
 
     const DEFAULT_VALIDATOR: &str = "default-validator";
 
+    fn single(validator: &str) -> Vec<ValidatorWeight> {
+        vec![ValidatorWeight {
+            address: validator.to_string(),
+            weight: 1,
+        }]
+    }
+
     fn default_instantiate(tax_percent: u64, min_withdrawal: u128) -> InstantiateMsg {
         InstantiateMsg {
             name: "Cool Derivative".to_string(),
             symbol: "DRV".to_string(),
             decimals: 9,
-            validator: String::from(DEFAULT_VALIDATOR),
+            validators: single(DEFAULT_VALIDATOR),
             unbonding_period: DAY * 3,
+            // unbond requests can be processed right away
+            unbonding_epoch: Duration::Time(0),
             exit_tax: Decimal::percent(tax_percent),
             min_withdrawal: Uint128::new(min_withdrawal),
+            keeper_bounty_bps: 0,
+            commission_bps: 0,
+            treasury: None,
+            exit_tax_mode: ExitTaxMode::Treasury,
+            buffer_target_bps: 0,
+            instant_unbond_fee_bps: 0,
+            max_bonded: None,
+            max_bonded_per_address: None,
+            allowlist: None,
         }
     }
 
@@ -503,11 +812,13 @@ mod tests {
         query_balance(deps, addr.into()).unwrap().balance
     }
 
-    fn get_claims(deps: Deps, addr: &str) -> Vec<Claim> {
-        CLAIMS
-            .query_claims(deps, &Addr::unchecked(addr))
-            .unwrap()
-            .claims
+    fn get_claims(deps: Deps, addr: &str) -> Vec<EpochClaim> {
+        query_claims(deps, addr.to_string()).unwrap().claims
+    }
+
+    fn process_epoch(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+        let info = mock_info("anyone", &[]);
+        execute(deps, env, info, ExecuteMsg::ProcessUnbondingEpoch {})
     }
 
     #[test]
@@ -521,10 +832,20 @@ mod tests {
             name: "Cool Derivative".to_string(),
             symbol: "DRV".to_string(),
             decimals: 9,
-            validator: String::from("my-validator"),
+            validators: single("my-validator"),
             unbonding_period: WEEK,
+            unbonding_epoch: DAY,
             exit_tax: Decimal::percent(2),
             min_withdrawal: Uint128::new(50),
+            keeper_bounty_bps: 0,
+            commission_bps: 0,
+            treasury: None,
+            exit_tax_mode: ExitTaxMode::Treasury,
+            buffer_target_bps: 0,
+            instant_unbond_fee_bps: 0,
+            max_bonded: None,
+            max_bonded_per_address: None,
+            allowlist: None,
         };
         let info = mock_info(&creator, &[]);
 
@@ -556,10 +877,20 @@ mod tests {
             name: "Cool Derivative".to_string(),
             symbol: "DRV".to_string(),
             decimals: 0,
-            validator: String::from("my-validator"),
+            validators: single("my-validator"),
             unbonding_period: HOUR * 12,
+            unbonding_epoch: HOUR,
             exit_tax: Decimal::percent(2),
             min_withdrawal: Uint128::new(50),
+            keeper_bounty_bps: 0,
+            commission_bps: 0,
+            treasury: None,
+            exit_tax_mode: ExitTaxMode::Treasury,
+            buffer_target_bps: 0,
+            instant_unbond_fee_bps: 0,
+            max_bonded: None,
+            max_bonded_per_address: None,
+            allowlist: None,
         };
         let info = mock_info(&creator, &[]);
 
@@ -582,7 +913,7 @@ mod tests {
         // investment info correct
         let invest = query_investment(deps.as_ref()).unwrap();
         assert_eq!(&invest.owner, &creator);
-        assert_eq!(&invest.validator, &msg.validator);
+        assert_eq!(&invest.validators, &msg.validators);
         assert_eq!(invest.exit_tax, msg.exit_tax);
         assert_eq!(invest.min_withdrawal, msg.min_withdrawal);
 
@@ -659,7 +990,9 @@ mod tests {
         set_delegation(&mut deps.querier, 1000, "ustake");
 
         // fake a reinvestment (this must be sent by the contract itself)
-        let rebond_msg = ExecuteMsg::_BondAllTokens {};
+        let rebond_msg = ExecuteMsg::_BondAllTokens {
+            keeper: bob.clone(),
+        };
         let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
         deps.querier
             .update_balance(MOCK_CONTRACT_ADDR, coins(500, "ustake"));
@@ -747,7 +1080,9 @@ mod tests {
 
         // fake a reinvestment (this must be sent by the contract itself)
         // after this, we see 1000 issues and 1500 bonded (and a price of 1.5)
-        let rebond_msg = ExecuteMsg::_BondAllTokens {};
+        let rebond_msg = ExecuteMsg::_BondAllTokens {
+            keeper: bob.clone(),
+        };
         let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
         deps.querier
             .update_balance(MOCK_CONTRACT_ADDR, coins(500, "ustake"));
@@ -784,6 +1119,10 @@ mod tests {
         let env = mock_env();
         let info = mock_info(&bob, &[]);
         let res = execute(deps.as_mut(), env.clone(), info, unbond_msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // they are undelegated once the epoch is processed
+        let res = process_epoch(deps.as_mut(), env.clone()).unwrap();
         assert_eq!(1, res.messages.len());
         let delegate = &res.messages[0];
         match &delegate.msg {
@@ -801,9 +1140,11 @@ mod tests {
         assert_eq!(get_balance(deps.as_ref(), &bob), bobs_balance);
         assert_eq!(get_balance(deps.as_ref(), &creator), owner_cut);
         // proper claims
-        let expected_claims = vec![Claim {
-            amount: bobs_claim,
-            release_at: (DAY * 3).after(&env.block),
+        let expected_claims = vec![EpochClaim {
+            epoch: 1,
+            tokens: Uint128::new(540),
+            amount: Some(bobs_claim),
+            release_at: Some((DAY * 3).after(&env.block)),
         }];
         assert_eq!(expected_claims, get_claims(deps.as_ref(), &bob));
 
@@ -840,13 +1181,16 @@ mod tests {
         let env = mock_env();
         let info = mock_info(&bob, &[]);
         execute(deps.as_mut(), env.clone(), info.clone(), unbond_msg).unwrap();
+        process_epoch(deps.as_mut(), env.clone()).unwrap();
         set_delegation(&mut deps.querier, 460, "ustake");
 
         // ensure claims are proper
         let bobs_claim = Uint128::new(540);
-        let original_claims = vec![Claim {
-            amount: bobs_claim,
-            release_at: (DAY * 3).after(&env.block),
+        let original_claims = vec![EpochClaim {
+            epoch: 1,
+            tokens: bobs_claim,
+            amount: Some(bobs_claim),
+            release_at: Some((DAY * 3).after(&env.block)),
         }];
         assert_eq!(original_claims, get_claims(deps.as_ref(), &bob));
 
@@ -857,18 +1201,29 @@ mod tests {
             deps.as_mut(),
             claim_ready.clone(),
             info.clone(),
-            ExecuteMsg::Claim {},
+            ExecuteMsg::Claim { recipient: None },
         );
         assert!(fail.is_err(), "{:?}", fail);
 
         // provide the balance, but claim not yet mature - also prohibited
         deps.querier
             .update_balance(MOCK_CONTRACT_ADDR, coins(540, "ustake"));
-        let fail = execute(deps.as_mut(), too_soon, info.clone(), ExecuteMsg::Claim {});
+        let fail = execute(
+            deps.as_mut(),
+            too_soon,
+            info.clone(),
+            ExecuteMsg::Claim { recipient: None },
+        );
         assert!(fail.is_err(), "{:?}", fail);
 
         // this should work with cash and claims ready
-        let res = execute(deps.as_mut(), claim_ready, info, ExecuteMsg::Claim {}).unwrap();
+        let res = execute(
+            deps.as_mut(),
+            claim_ready,
+            info,
+            ExecuteMsg::Claim { recipient: None },
+        )
+        .unwrap();
         assert_eq!(1, res.messages.len());
         let payout = &res.messages[0];
         match &payout.msg {
@@ -962,4 +1317,1473 @@ mod tests {
         execute(deps.as_mut(), mock_env(), bob_info, burn).unwrap();
         assert_eq!(get_balance(deps.as_ref(), &bob), Uint128::new(420));
     }
+
+    #[test]
+    fn bonds_are_spread_across_validators() {
+        let mut deps = mock_dependencies();
+        let validators = [sample_validator("val-a"), sample_validator("val-b")];
+        deps.querier.update_staking("ustake", &validators, &[]);
+
+        let creator = String::from("creator");
+        let instantiate_msg = InstantiateMsg {
+            validators: vec![
+                ValidatorWeight {
+                    address: "val-a".to_string(),
+                    weight: 1,
+                },
+                ValidatorWeight {
+                    address: "val-b".to_string(),
+                    weight: 3,
+                },
+            ],
+            ..default_instantiate(0, 50)
+        };
+        let info = mock_info(&creator, &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // the first bond goes to both validators by weight
+        let bob = String::from("bob");
+        let info = mock_info(&bob, &coins(1000, "ustake"));
+        let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Bond {}).unwrap();
+        let delegate = |validator: &str, amount| -> CosmosMsg {
+            StakingMsg::Delegate {
+                validator: validator.to_string(),
+                amount: coin(amount, "ustake"),
+            }
+            .into()
+        };
+        let msgs: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(msgs, vec![delegate("val-b", 750), delegate("val-a", 250)]);
+
+        // say we ended up with too much on val-a, then unbonding drains it first
+        let delegations = [
+            sample_delegation("val-a", coin(600, "ustake")),
+            sample_delegation("val-b", coin(400, "ustake")),
+        ];
+        deps.querier
+            .update_staking("ustake", &validators, &delegations);
+        let unbond_msg = ExecuteMsg::Unbond {
+            amount: Uint128::new(400),
+        };
+        let info = mock_info(&bob, &[]);
+        execute(deps.as_mut(), mock_env(), info, unbond_msg).unwrap();
+        let res = process_epoch(deps.as_mut(), mock_env()).unwrap();
+        let msgs: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        let undelegate: CosmosMsg = StakingMsg::Undelegate {
+            validator: "val-a".to_string(),
+            amount: coin(400, "ustake"),
+        }
+        .into();
+        assert_eq!(msgs, vec![undelegate]);
+
+        // the query shows the delegations against their targets
+        let delegations = [
+            sample_delegation("val-a", coin(200, "ustake")),
+            sample_delegation("val-b", coin(400, "ustake")),
+        ];
+        deps.querier
+            .update_staking("ustake", &validators, &delegations);
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Validators {}).unwrap();
+        let res: ValidatorsResponse = cosmwasm_std::from_binary(&res).unwrap();
+        assert_eq!(
+            res.validators,
+            vec![
+                ValidatorAllocation {
+                    address: "val-a".to_string(),
+                    weight: 1,
+                    target: Uint128::new(150),
+                    bonded: Uint128::new(200),
+                },
+                ValidatorAllocation {
+                    address: "val-b".to_string(),
+                    weight: 3,
+                    target: Uint128::new(450),
+                    bonded: Uint128::new(400),
+                },
+            ]
+        );
+
+        // and rewards are withdrawn from both
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(&bob, &[]),
+            ExecuteMsg::Reinvest {},
+        )
+        .unwrap();
+        assert_eq!(res.messages.len(), 3);
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Distribution(DistributionMsg::WithdrawDelegatorReward {
+                validator: "val-b".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn instantiation_checks_validator_set() {
+        let mut deps = mock_dependencies();
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+
+        let msg = InstantiateMsg {
+            validators: vec![],
+            ..default_instantiate(2, 50)
+        };
+        let err = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::EmptyValidatorSet {});
+
+        let mut validators = single(DEFAULT_VALIDATOR);
+        validators.extend(single(DEFAULT_VALIDATOR));
+        let msg = InstantiateMsg {
+            validators,
+            ..default_instantiate(2, 50)
+        };
+        let err = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::DuplicateValidator {
+                validator: DEFAULT_VALIDATOR.to_string()
+            }
+        );
+
+        let msg = InstantiateMsg {
+            validators: vec![ValidatorWeight {
+                address: DEFAULT_VALIDATOR.to_string(),
+                weight: 0,
+            }],
+            ..default_instantiate(2, 50)
+        };
+        let err = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::ZeroWeight {
+                validator: DEFAULT_VALIDATOR.to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn redelegation_respects_chain_limits() {
+        let mut deps = mock_dependencies();
+        let validators = [sample_validator("val-a"), sample_validator("val-b")];
+        // only 200 of the 1000 on val-a may move again, the rest arrived by redelegation
+        let delegation = FullDelegation {
+            can_redelegate: coin(200, "ustake"),
+            ..sample_delegation("val-a", coin(1000, "ustake"))
+        };
+        deps.querier
+            .update_staking("ustake", &validators, &[delegation]);
+
+        let creator = String::from("creator");
+        let instantiate_msg = InstantiateMsg {
+            validators: single("val-b"),
+            ..default_instantiate(2, 50)
+        };
+        let info = mock_info(&creator, &[]);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), instantiate_msg).unwrap();
+
+        let redelegate = |from: &str, amount| ExecuteMsg::Redelegate {
+            from: from.to_string(),
+            to: "val-b".to_string(),
+            amount: Uint128::new(amount),
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            redelegate("val-a", 201),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::RedelegateTooMuch {
+                validator: "val-a".to_string(),
+                max: Uint128::new(200)
+            }
+        );
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            redelegate("val-b", 100),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::SameValidator {});
+
+        let res = execute(deps.as_mut(), mock_env(), info, redelegate("val-a", 200)).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Staking(StakingMsg::Redelegate {
+                src_validator: "val-a".to_string(),
+                dst_validator: "val-b".to_string(),
+                amount: coin(200, "ustake"),
+            })
+        );
+    }
+
+    #[test]
+    fn slashing_is_spread_over_holders_and_claims() {
+        let mut deps = mock_dependencies();
+        set_validator(&mut deps.querier);
+
+        let creator = String::from("creator");
+        let instantiate_msg = default_instantiate(0, 50);
+        let info = mock_info(&creator, &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // bob bonds 1000 and unbonds 200 of them
+        let bob = String::from("bob");
+        let info = mock_info(&bob, &coins(1000, "ustake"));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Bond {}).unwrap();
+        set_delegation(&mut deps.querier, 1000, "ustake");
+        let unbond_msg = ExecuteMsg::Unbond {
+            amount: Uint128::new(200),
+        };
+        let env = mock_env();
+        let info = mock_info(&bob, &[]);
+        execute(deps.as_mut(), env.clone(), info.clone(), unbond_msg).unwrap();
+        process_epoch(deps.as_mut(), env.clone()).unwrap();
+
+        // then the validator is slashed by half
+        set_delegation(&mut deps.querier, 400, "ustake");
+
+        // the next bond notices, and prices the token at half its value
+        let alice = String::from("alice");
+        let info_alice = mock_info(&alice, &coins(400, "ustake"));
+        let res = execute(deps.as_mut(), mock_env(), info_alice, ExecuteMsg::Bond {}).unwrap();
+        assert_eq!(res.events.len(), 1);
+        assert_eq!(res.events[0].ty, "slashing_detected");
+        assert_eq!(get_balance(deps.as_ref(), &alice), Uint128::new(800));
+        let invest = query_investment(deps.as_ref()).unwrap();
+        assert_eq!(invest.token_supply, Uint128::new(1600));
+        assert_eq!(invest.staked_tokens, coin(800, "ustake"));
+        assert_eq!(invest.nominal_value, Decimal::percent(50));
+
+        // bob's pending claim lost half as well
+        let claim = |epoch| EpochClaim {
+            epoch,
+            tokens: Uint128::new(200),
+            amount: Some(Uint128::new(100)),
+            release_at: Some((DAY * 3).after(&env.block)),
+        };
+        let expected = vec![claim(1)];
+        assert_eq!(get_claims(deps.as_ref(), &bob), expected);
+        let supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
+        assert_eq!(supply.claims, Uint128::new(100));
+
+        // unbonding keeps working, new claims keep their full value
+        set_delegation(&mut deps.querier, 800, "ustake");
+        let unbond_msg = ExecuteMsg::Unbond {
+            amount: Uint128::new(200),
+        };
+        execute(deps.as_mut(), env.clone(), info.clone(), unbond_msg).unwrap();
+        let res = process_epoch(deps.as_mut(), env.clone()).unwrap();
+        assert!(res.events.is_empty());
+        let expected = vec![claim(1), claim(2)];
+        assert_eq!(get_claims(deps.as_ref(), &bob), expected);
+
+        // which are paid out once mature
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(200, "ustake"));
+        let claim_ready = later(&env, (DAY * 3 + HOUR).unwrap());
+        let res = execute(
+            deps.as_mut(),
+            claim_ready,
+            info,
+            ExecuteMsg::Claim { recipient: None },
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: bob,
+                amount: coins(200, "ustake"),
+            })
+        );
+        let supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
+        assert_eq!(supply.claims, Uint128::zero());
+    }
+
+    #[test]
+    fn slashing_leaves_matured_claims_alone() {
+        let mut deps = mock_dependencies();
+        set_validator(&mut deps.querier);
+
+        let creator = String::from("creator");
+        let instantiate_msg = default_instantiate(0, 50);
+        let info = mock_info(&creator, &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // bob bonds 1000 and unbonds 200 of them, which have arrived but are not claimed yet
+        let bob = String::from("bob");
+        let info = mock_info(&bob, &coins(1000, "ustake"));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Bond {}).unwrap();
+        set_delegation(&mut deps.querier, 1000, "ustake");
+        let unbond_msg = ExecuteMsg::Unbond {
+            amount: Uint128::new(200),
+        };
+        let info = mock_info(&bob, &[]);
+        execute(deps.as_mut(), mock_env(), info.clone(), unbond_msg).unwrap();
+        process_epoch(deps.as_mut(), mock_env()).unwrap();
+        set_delegation(&mut deps.querier, 800, "ustake");
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(200, "ustake"));
+
+        // then the validator is slashed by half, and the next reinvest notices
+        set_delegation(&mut deps.querier, 400, "ustake");
+        let matured = later(&mock_env(), (DAY * 3 + HOUR).unwrap());
+        let rebond_msg = ExecuteMsg::_BondAllTokens {
+            keeper: bob.clone(),
+        };
+        let contract = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        let res = execute(deps.as_mut(), matured.clone(), contract, rebond_msg).unwrap();
+        assert_eq!(res.events[0].ty, "slashing_detected");
+        assert_eq!(res.events[0].attributes[3].value, "0");
+
+        // the claim keeps its value, so its tokens are not mistaken for rewards
+        assert_eq!(res.messages.len(), 0);
+        let invest = query_investment(deps.as_ref()).unwrap();
+        assert_eq!(invest.staked_tokens, coin(400, "ustake"));
+        assert_eq!(invest.nominal_value, Decimal::percent(50));
+        let supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
+        assert_eq!(supply.claims, Uint128::new(200));
+
+        // and is paid out in full
+        let res = execute(
+            deps.as_mut(),
+            matured,
+            info,
+            ExecuteMsg::Claim { recipient: None },
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: bob,
+                amount: coins(200, "ustake"),
+            })
+        );
+        let supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
+        assert_eq!(supply.claims, Uint128::zero());
+    }
+
+    #[test]
+    fn unbonds_are_batched_into_epochs() {
+        let mut deps = mock_dependencies();
+        set_validator(&mut deps.querier);
+
+        let creator = String::from("creator");
+        let instantiate_msg = InstantiateMsg {
+            unbonding_epoch: DAY,
+            ..default_instantiate(0, 50)
+        };
+        let info = mock_info(&creator, &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // bob and alice bond 1000 each
+        let bob = String::from("bob");
+        let alice = String::from("alice");
+        for (staker, bonded) in [(&bob, 1000), (&alice, 2000)] {
+            let info = mock_info(staker, &coins(1000, "ustake"));
+            execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Bond {}).unwrap();
+            set_delegation(&mut deps.querier, bonded, "ustake");
+        }
+
+        // both unbond in the same epoch, nothing is undelegated yet
+        let env = mock_env();
+        for (staker, amount) in [(&bob, 300), (&alice, 500)] {
+            let unbond_msg = ExecuteMsg::Unbond {
+                amount: Uint128::new(amount),
+            };
+            let info = mock_info(staker, &[]);
+            let res = execute(deps.as_mut(), env.clone(), info, unbond_msg).unwrap();
+            assert_eq!(0, res.messages.len());
+        }
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::PendingEpoch {}).unwrap();
+        let pending: PendingEpochResponse = cosmwasm_std::from_binary(&res).unwrap();
+        let closes_at = DAY.after(&env.block);
+        assert_eq!(
+            pending.epoch,
+            Some(PendingEpoch {
+                id: 1,
+                tokens: Uint128::new(800),
+                closes_at,
+            })
+        );
+        // the contract holds the tokens in the meantime
+        assert_eq!(get_balance(deps.as_ref(), &bob), Uint128::new(700));
+        assert_eq!(
+            get_balance(deps.as_ref(), MOCK_CONTRACT_ADDR),
+            Uint128::new(800)
+        );
+        let expected = vec![EpochClaim {
+            epoch: 1,
+            tokens: Uint128::new(300),
+            amount: None,
+            release_at: None,
+        }];
+        assert_eq!(get_claims(deps.as_ref(), &bob), expected);
+
+        // the epoch can't be processed before it closes
+        let err = process_epoch(deps.as_mut(), later(&env, HOUR)).unwrap_err();
+        assert_eq!(err, ContractError::EpochNotClosed { closes_at });
+
+        // then anyone can, and it is undelegated in one message
+        let closed = later(&env, DAY);
+        let res = process_epoch(deps.as_mut(), closed.clone()).unwrap();
+        assert_eq!(
+            res.messages[..],
+            [SubMsg::new(StakingMsg::Undelegate {
+                validator: DEFAULT_VALIDATOR.to_string(),
+                amount: coin(800, "ustake"),
+            })]
+        );
+        set_delegation(&mut deps.querier, 1200, "ustake");
+        let err = process_epoch(deps.as_mut(), closed.clone()).unwrap_err();
+        assert_eq!(err, ContractError::NoPendingEpoch {});
+
+        // the tokens are burnt and each claim is priced
+        let invest = query_investment(deps.as_ref()).unwrap();
+        assert_eq!(invest.token_supply, Uint128::new(1200));
+        assert_eq!(
+            get_balance(deps.as_ref(), MOCK_CONTRACT_ADDR),
+            Uint128::zero()
+        );
+        let release_at = (DAY * 3).after(&closed.block);
+        let expected = vec![EpochClaim {
+            epoch: 1,
+            tokens: Uint128::new(500),
+            amount: Some(Uint128::new(500)),
+            release_at: Some(release_at),
+        }];
+        assert_eq!(get_claims(deps.as_ref(), &alice), expected);
+        let msg = QueryMsg::ProcessedEpochs {
+            start_after: None,
+            limit: None,
+        };
+        let res = query(deps.as_ref(), closed.clone(), msg).unwrap();
+        let processed: ProcessedEpochsResponse = cosmwasm_std::from_binary(&res).unwrap();
+        assert_eq!(
+            processed.epochs,
+            vec![ProcessedEpoch {
+                id: 1,
+                tokens: Uint128::new(800),
+                unbonded: Uint128::new(800),
+                claimable: Uint128::new(800),
+                buffer_refill: Uint128::zero(),
+                release_at,
+            }]
+        );
+
+        // the next unbond opens a new epoch
+        let unbond_msg = ExecuteMsg::Unbond {
+            amount: Uint128::new(100),
+        };
+        let info = mock_info(&bob, &[]);
+        let res = execute(deps.as_mut(), closed.clone(), info, unbond_msg).unwrap();
+        assert_eq!(res.attributes[2].value, "2");
+
+        // bob is paid for the first one once it is released
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(800, "ustake"));
+        let claim_ready = later(&closed, DAY * 3);
+        let info = mock_info(&bob, &[]);
+        let res = execute(
+            deps.as_mut(),
+            claim_ready,
+            info,
+            ExecuteMsg::Claim { recipient: None },
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: bob.clone(),
+                amount: coins(300, "ustake"),
+            })
+        );
+        assert_eq!(get_claims(deps.as_ref(), &bob)[0].epoch, 2);
+    }
+
+    #[test]
+    fn reinvest_pays_keeper_and_commission() {
+        let mut deps = mock_dependencies();
+        set_validator(&mut deps.querier);
+
+        // fees must leave something of the rewards
+        let creator = String::from("creator");
+        let info = mock_info(&creator, &[]);
+        let instantiate_msg = InstantiateMsg {
+            keeper_bounty_bps: 2_000,
+            commission_bps: 9_000,
+            ..default_instantiate(0, 50)
+        };
+        let err =
+            instantiate(deps.as_mut(), mock_env(), info.clone(), instantiate_msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::FeesTooHigh {
+                keeper_bounty_bps: 2_000,
+                commission_bps: 9_000
+            }
+        );
+
+        // 1% for the keeper, 5% for the owner
+        let instantiate_msg = InstantiateMsg {
+            keeper_bounty_bps: 100,
+            commission_bps: 500,
+            ..default_instantiate(0, 50)
+        };
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+        let bob = String::from("bob");
+        let info = mock_info(&bob, &coins(1000, "ustake"));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Bond {}).unwrap();
+        set_delegation(&mut deps.querier, 1000, "ustake");
+
+        // the keeper triggers the callback, which finds 1000 of rewards
+        let keeper = String::from("keeper");
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(&keeper, &[]),
+            ExecuteMsg::Reinvest {},
+        )
+        .unwrap();
+        let callback = to_binary(&ExecuteMsg::_BondAllTokens {
+            keeper: keeper.clone(),
+        })
+        .unwrap();
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                msg: callback,
+                funds: vec![],
+            })
+        );
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(1000, "ustake"));
+        let rebond_msg = ExecuteMsg::_BondAllTokens {
+            keeper: keeper.clone(),
+        };
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, rebond_msg).unwrap();
+        let msgs: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        let send = |to: &str, amount| -> CosmosMsg {
+            BankMsg::Send {
+                to_address: to.to_string(),
+                amount: coins(amount, "ustake"),
+            }
+            .into()
+        };
+        let delegate: CosmosMsg = StakingMsg::Delegate {
+            validator: DEFAULT_VALIDATOR.to_string(),
+            amount: coin(940, "ustake"),
+        }
+        .into();
+        assert_eq!(msgs, vec![send(&keeper, 10), send(&creator, 50), delegate]);
+        let attrs: Vec<_> = res.attributes[1..]
+            .iter()
+            .map(|a| (a.key.as_str(), a.value.as_str()))
+            .collect();
+        assert_eq!(
+            attrs,
+            vec![
+                ("rewards", "1000"),
+                ("keeper", "keeper"),
+                ("keeper_bounty", "10"),
+                ("commission", "50"),
+                ("bonded", "940")
+            ]
+        );
+
+        // only the remainder adds to the value of the token
+        set_delegation(&mut deps.querier, 1940, "ustake");
+        let invest = query_investment(deps.as_ref()).unwrap();
+        assert_eq!(invest.staked_tokens, coin(1940, "ustake"));
+        assert_eq!(invest.keeper_bounty_bps, 100);
+        assert_eq!(invest.commission_bps, 500);
+        assert_eq!(
+            invest.fees_paid,
+            FeesPaid {
+                keeper_bounties: Uint128::new(10),
+                commission: Uint128::new(50),
+            }
+        );
+    }
+
+    #[test]
+    fn owner_updates_config() {
+        let mut deps = mock_dependencies();
+        set_validator(&mut deps.querier);
+
+        // the exit tax is capped from the start
+        let creator = String::from("creator");
+        let info = mock_info(&creator, &[]);
+        let err = instantiate(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            default_instantiate(11, 50),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::ExitTaxTooHigh {
+                max: Decimal::percent(10)
+            }
+        );
+        // and so are the unbonding period and epoch
+        let msg = InstantiateMsg {
+            unbonding_period: Duration::Time(u64::MAX),
+            ..default_instantiate(2, 50)
+        };
+        let err = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::UnbondingPeriodTooLong {
+                max: MAX_UNBONDING_PERIOD
+            }
+        );
+        let msg = InstantiateMsg {
+            unbonding_epoch: Duration::Height(MAX_UNBONDING_EPOCH + 1),
+            ..default_instantiate(2, 50)
+        };
+        let err = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::UnbondingEpochTooLong {
+                max: MAX_UNBONDING_EPOCH
+            }
+        );
+        instantiate(deps.as_mut(), mock_env(), info, default_instantiate(2, 50)).unwrap();
+
+        let update = ExecuteMsg::UpdateConfig {
+            exit_tax: Some(Decimal::percent(5)),
+            min_withdrawal: Some(Uint128::new(100)),
+            unbonding_period: Some(DAY * 14),
+            unbonding_epoch: None,
+            keeper_bounty_bps: None,
+            commission_bps: Some(1_000),
+            treasury: None,
+            exit_tax_mode: None,
+            buffer_target_bps: None,
+            instant_unbond_fee_bps: None,
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            update.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info(&creator, &[]), update).unwrap();
+        assert_eq!(res.events[0].ty, "update_config");
+        let changed: Vec<_> = res.events[0]
+            .attributes
+            .iter()
+            .map(|a| (a.key.as_str(), a.value.as_str()))
+            .collect();
+        assert_eq!(
+            changed,
+            vec![
+                ("exit_tax", "0.05"),
+                ("min_withdrawal", "100"),
+                ("unbonding_period", "time: 1209600"),
+                ("commission_bps", "1000"),
+            ]
+        );
+        let invest = query_investment(deps.as_ref()).unwrap();
+        assert_eq!(invest.exit_tax, Decimal::percent(5));
+        assert_eq!(invest.min_withdrawal, Uint128::new(100));
+        assert_eq!(invest.unbonding_period, DAY * 14);
+        assert_eq!(invest.unbonding_epoch, Duration::Time(0));
+        assert_eq!(invest.commission_bps, 1_000);
+
+        // the bounds are checked on every update, which changes nothing if they fail
+        let bad_updates = [
+            (
+                ExecuteMsg::UpdateConfig {
+                    exit_tax: Some(Decimal::percent(20)),
+                    min_withdrawal: Some(Uint128::new(1)),
+                    unbonding_period: None,
+                    unbonding_epoch: None,
+                    keeper_bounty_bps: None,
+                    commission_bps: None,
+                    treasury: None,
+                    exit_tax_mode: None,
+                    buffer_target_bps: None,
+                    instant_unbond_fee_bps: None,
+                },
+                ContractError::ExitTaxTooHigh {
+                    max: Decimal::percent(10),
+                },
+            ),
+            (
+                ExecuteMsg::UpdateConfig {
+                    exit_tax: None,
+                    min_withdrawal: None,
+                    unbonding_period: Some(Duration::Time(0)),
+                    unbonding_epoch: None,
+                    keeper_bounty_bps: None,
+                    commission_bps: None,
+                    treasury: None,
+                    exit_tax_mode: None,
+                    buffer_target_bps: None,
+                    instant_unbond_fee_bps: None,
+                },
+                ContractError::ZeroUnbondingPeriod {},
+            ),
+            (
+                ExecuteMsg::UpdateConfig {
+                    exit_tax: None,
+                    min_withdrawal: None,
+                    unbonding_period: None,
+                    unbonding_epoch: None,
+                    keeper_bounty_bps: Some(9_500),
+                    commission_bps: None,
+                    treasury: None,
+                    exit_tax_mode: None,
+                    buffer_target_bps: None,
+                    instant_unbond_fee_bps: None,
+                },
+                ContractError::FeesTooHigh {
+                    keeper_bounty_bps: 9_500,
+                    commission_bps: 1_000,
+                },
+            ),
+            (
+                ExecuteMsg::UpdateConfig {
+                    exit_tax: None,
+                    min_withdrawal: Some(Uint128::new(1_000_001)),
+                    unbonding_period: None,
+                    unbonding_epoch: None,
+                    keeper_bounty_bps: None,
+                    commission_bps: None,
+                    treasury: None,
+                    exit_tax_mode: None,
+                    buffer_target_bps: None,
+                    instant_unbond_fee_bps: None,
+                },
+                ContractError::MinWithdrawalTooHigh {
+                    max: Uint128::new(1_000_000),
+                },
+            ),
+            (
+                ExecuteMsg::UpdateConfig {
+                    exit_tax: None,
+                    min_withdrawal: None,
+                    unbonding_period: Some(Duration::Time(3 * 24 * 60 * 60 - 1)),
+                    unbonding_epoch: None,
+                    keeper_bounty_bps: None,
+                    commission_bps: None,
+                    treasury: None,
+                    exit_tax_mode: None,
+                    buffer_target_bps: None,
+                    instant_unbond_fee_bps: None,
+                },
+                ContractError::UnbondingPeriodTooShort { min: DAY * 3 },
+            ),
+            (
+                ExecuteMsg::UpdateConfig {
+                    exit_tax: None,
+                    min_withdrawal: None,
+                    unbonding_period: Some(Duration::Height(100_000)),
+                    unbonding_epoch: None,
+                    keeper_bounty_bps: None,
+                    commission_bps: None,
+                    treasury: None,
+                    exit_tax_mode: None,
+                    buffer_target_bps: None,
+                    instant_unbond_fee_bps: None,
+                },
+                ContractError::UnbondingPeriodTooShort { min: DAY * 3 },
+            ),
+            (
+                ExecuteMsg::UpdateConfig {
+                    exit_tax: None,
+                    min_withdrawal: None,
+                    unbonding_period: Some(Duration::Time(u64::MAX)),
+                    unbonding_epoch: None,
+                    keeper_bounty_bps: None,
+                    commission_bps: None,
+                    treasury: None,
+                    exit_tax_mode: None,
+                    buffer_target_bps: None,
+                    instant_unbond_fee_bps: None,
+                },
+                ContractError::UnbondingPeriodTooLong {
+                    max: MAX_UNBONDING_PERIOD,
+                },
+            ),
+            (
+                ExecuteMsg::UpdateConfig {
+                    exit_tax: None,
+                    min_withdrawal: None,
+                    unbonding_period: None,
+                    unbonding_epoch: Some(Duration::Time(MAX_UNBONDING_EPOCH + 1)),
+                    keeper_bounty_bps: None,
+                    commission_bps: None,
+                    treasury: None,
+                    exit_tax_mode: None,
+                    buffer_target_bps: None,
+                    instant_unbond_fee_bps: None,
+                },
+                ContractError::UnbondingEpochTooLong {
+                    max: MAX_UNBONDING_EPOCH,
+                },
+            ),
+        ];
+        for (update, expected) in bad_updates {
+            let info = mock_info(&creator, &[]);
+            let err = execute(deps.as_mut(), mock_env(), info, update).unwrap_err();
+            assert_eq!(err, expected);
+        }
+        assert_eq!(query_investment(deps.as_ref()).unwrap(), invest);
+    }
+
+    #[test]
+    fn ownership_transfer_takes_two_steps() {
+        let mut deps = mock_dependencies();
+        set_validator(&mut deps.querier);
+
+        let creator = String::from("creator");
+        let info = mock_info(&creator, &[]);
+        instantiate(deps.as_mut(), mock_env(), info, default_instantiate(2, 50)).unwrap();
+
+        let transfer = |new_owner: Option<&str>| ExecuteMsg::TransferOwnership {
+            new_owner: new_owner.map(String::from),
+        };
+        let accept = ExecuteMsg::AcceptOwnership {};
+
+        // nothing to accept yet
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            accept.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::NoPendingOwner {});
+
+        // only the owner can propose
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            transfer(Some("alice")),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(&creator, &[]),
+            transfer(Some("alice")),
+        )
+        .unwrap();
+        assert_eq!(res.events[0].ty, "transfer_ownership");
+        assert_eq!(res.events[0].attributes[1].value, "alice");
+
+        // the proposal changes nothing yet, and only alice can accept it
+        let invest = query_investment(deps.as_ref()).unwrap();
+        assert_eq!(invest.owner, creator);
+        assert_eq!(invest.pending_owner, Some("alice".to_string()));
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            accept.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+
+        // a withdrawn proposal can't be accepted
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(&creator, &[]),
+            transfer(None),
+        )
+        .unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            accept.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::NoPendingOwner {});
+
+        // propose again, and alice takes over
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(&creator, &[]),
+            transfer(Some("alice")),
+        )
+        .unwrap();
+        let res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), accept).unwrap();
+        assert_eq!(res.events[0].ty, "accept_ownership");
+        let invest = query_investment(deps.as_ref()).unwrap();
+        assert_eq!(invest.owner, "alice");
+        assert_eq!(invest.pending_owner, None);
+
+        // the previous owner has lost its rights
+        let update = ExecuteMsg::UpdateConfig {
+            exit_tax: Some(Decimal::zero()),
+            min_withdrawal: None,
+            unbonding_period: None,
+            unbonding_epoch: None,
+            keeper_bounty_bps: None,
+            commission_bps: None,
+            treasury: None,
+            exit_tax_mode: None,
+            buffer_target_bps: None,
+            instant_unbond_fee_bps: None,
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(&creator, &[]),
+            update.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), update).unwrap();
+    }
+
+    const TREASURY: &str = "treasury";
+
+    /// bob bonds 1000 at a 1.0 ratio and unbonds 500 of them at 10% tax
+    fn unbond_with_tax(mode: ExitTaxMode) -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+        let mut deps = mock_dependencies();
+        set_validator(&mut deps.querier);
+
+        let instantiate_msg = InstantiateMsg {
+            treasury: Some(TREASURY.to_string()),
+            exit_tax_mode: mode,
+            ..default_instantiate(10, 50)
+        };
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let info = mock_info("bob", &coins(1000, "ustake"));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Bond {}).unwrap();
+        set_delegation(&mut deps.querier, 1000, "ustake");
+
+        let unbond_msg = ExecuteMsg::Unbond {
+            amount: Uint128::new(500),
+        };
+        let info = mock_info("bob", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, unbond_msg).unwrap();
+        assert_eq!(res.attributes[4].value, "50");
+        assert_eq!(res.attributes[5].value, mode.as_str());
+        deps
+    }
+
+    #[test]
+    fn exit_tax_to_treasury() {
+        let mut deps = unbond_with_tax(ExitTaxMode::Treasury);
+
+        // the treasury holds the tax in derivative tokens, which stay in the pool
+        assert_eq!(get_balance(deps.as_ref(), TREASURY), Uint128::new(50));
+        let pending = query_pending_epoch(deps.as_ref()).unwrap().epoch.unwrap();
+        assert_eq!(pending.tokens, Uint128::new(450));
+
+        let res = process_epoch(deps.as_mut(), mock_env()).unwrap();
+        assert_eq!(res.attributes[3].value, "450");
+        let invest = query_investment(deps.as_ref()).unwrap();
+        assert_eq!(invest.token_supply, Uint128::new(550));
+        assert_eq!(invest.nominal_value, Decimal::one());
+        assert_eq!(get_claims(deps.as_ref(), TREASURY), vec![]);
+    }
+
+    #[test]
+    fn exit_tax_in_native_tokens() {
+        let mut deps = unbond_with_tax(ExitTaxMode::Native);
+
+        // the tax is unbonded with the rest, as a claim of the treasury
+        assert_eq!(get_balance(deps.as_ref(), TREASURY), Uint128::zero());
+        let pending = query_pending_epoch(deps.as_ref()).unwrap().epoch.unwrap();
+        assert_eq!(pending.tokens, Uint128::new(500));
+
+        let env = mock_env();
+        let res = process_epoch(deps.as_mut(), env.clone()).unwrap();
+        assert_eq!(res.attributes[3].value, "500");
+        set_delegation(&mut deps.querier, 500, "ustake");
+        let invest = query_investment(deps.as_ref()).unwrap();
+        assert_eq!(invest.token_supply, Uint128::new(500));
+        assert_eq!(invest.nominal_value, Decimal::one());
+
+        let claims = get_claims(deps.as_ref(), TREASURY);
+        assert_eq!(claims[0].tokens, Uint128::new(50));
+        assert_eq!(claims[0].amount, Some(Uint128::new(50)));
+        assert_eq!(
+            get_claims(deps.as_ref(), "bob")[0].amount,
+            Some(Uint128::new(450))
+        );
+
+        // and it is paid out in native tokens once the claim matures
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(500, "ustake"));
+        let claim_ready = later(&env, DAY * 3);
+        let info = mock_info(TREASURY, &[]);
+        let res = execute(
+            deps.as_mut(),
+            claim_ready,
+            info,
+            ExecuteMsg::Claim { recipient: None },
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: TREASURY.to_string(),
+                amount: coins(50, "ustake"),
+            })
+        );
+    }
+
+    #[test]
+    fn exit_tax_burnt() {
+        let mut deps = unbond_with_tax(ExitTaxMode::Burn);
+
+        // the tax is gone right away, so every remaining token is worth more
+        assert_eq!(get_balance(deps.as_ref(), TREASURY), Uint128::zero());
+        assert_eq!(
+            get_balance(deps.as_ref(), MOCK_CONTRACT_ADDR),
+            Uint128::new(450)
+        );
+        let invest = query_investment(deps.as_ref()).unwrap();
+        assert_eq!(invest.token_supply, Uint128::new(950));
+        assert_eq!(invest.nominal_value, Decimal::from_ratio(1000u128, 950u128));
+        assert_eq!(
+            query_token_info(deps.as_ref()).unwrap().total_supply,
+            Uint128::new(950)
+        );
+        let history = query_rate_history(deps.as_ref(), None, None).unwrap();
+        assert_eq!(history.snapshots.len(), 1);
+        assert_eq!(history.snapshots[0].rate, invest.nominal_value);
+
+        // which includes the tokens bob is unbonding: 450 * 1000 / 950
+        let res = process_epoch(deps.as_mut(), mock_env()).unwrap();
+        assert_eq!(res.attributes[2].value, "450");
+        assert_eq!(res.attributes[3].value, "473");
+        let invest = query_investment(deps.as_ref()).unwrap();
+        assert_eq!(invest.token_supply, Uint128::new(500));
+        assert_eq!(invest.staked_tokens, coin(527, "ustake"));
+
+        // the mode can be switched by the owner
+        let update = ExecuteMsg::UpdateConfig {
+            exit_tax: None,
+            min_withdrawal: None,
+            unbonding_period: None,
+            unbonding_epoch: None,
+            keeper_bounty_bps: None,
+            commission_bps: None,
+            treasury: Some("new-treasury".to_string()),
+            exit_tax_mode: Some(ExitTaxMode::Treasury),
+            buffer_target_bps: None,
+            instant_unbond_fee_bps: None,
+        };
+        let info = mock_info("creator", &[]);
+        execute(deps.as_mut(), mock_env(), info, update).unwrap();
+        let invest = query_investment(deps.as_ref()).unwrap();
+        assert_eq!(invest.treasury, "new-treasury");
+        assert_eq!(invest.exit_tax_mode, ExitTaxMode::Treasury);
+    }
+
+    #[test]
+    fn instant_unbond_from_buffer() {
+        let mut deps = mock_dependencies();
+        set_validator(&mut deps.querier);
+
+        // keep 10% liquid, and charge 3% for using it
+        let creator = String::from("creator");
+        let instantiate_msg = InstantiateMsg {
+            buffer_target_bps: 1_000,
+            instant_unbond_fee_bps: 300,
+            ..default_instantiate(0, 50)
+        };
+        let info = mock_info(&creator, &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // bonds fill the buffer first, the tokens are priced on everything
+        let bob = String::from("bob");
+        let alice = String::from("alice");
+        for (staker, bonded) in [(&bob, 900), (&alice, 1800)] {
+            let info = mock_info(staker, &coins(1000, "ustake"));
+            let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Bond {}).unwrap();
+            assert_eq!(
+                res.messages[0].msg,
+                CosmosMsg::Staking(StakingMsg::Delegate {
+                    validator: DEFAULT_VALIDATOR.to_string(),
+                    amount: coin(900, "ustake"),
+                })
+            );
+            assert_eq!(get_balance(deps.as_ref(), staker), Uint128::new(1000));
+            set_delegation(&mut deps.querier, bonded, "ustake");
+        }
+        let buffer = query_buffer(deps.as_ref(), mock_env()).unwrap();
+        assert_eq!(
+            buffer,
+            BufferResponse {
+                available: Uint128::new(200),
+                refilling: Uint128::zero(),
+                target: Uint128::new(200),
+                utilisation: Decimal::zero(),
+            }
+        );
+        let invest = query_investment(deps.as_ref()).unwrap();
+        assert_eq!(invest.staked_tokens, coin(1800, "ustake"));
+        assert_eq!(invest.nominal_value, Decimal::one());
+
+        // bob takes out 200 right away, less 3%
+        let instant = |amount| ExecuteMsg::InstantUnbond {
+            amount: Uint128::new(amount),
+        };
+        let info = mock_info(&bob, &[]);
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), instant(200)).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: bob.clone(),
+                amount: coins(194, "ustake"),
+            })
+        );
+        assert_eq!(get_balance(deps.as_ref(), &bob), Uint128::new(800));
+
+        // the fee stays with the holders, and the buffer is almost used up
+        let invest = query_investment(deps.as_ref()).unwrap();
+        assert_eq!(invest.token_supply, Uint128::new(1800));
+        assert_eq!(
+            invest.nominal_value,
+            Decimal::from_ratio(1806u128, 1800u128)
+        );
+        let history = query_rate_history(deps.as_ref(), None, None).unwrap();
+        assert_eq!(history.snapshots.len(), 1);
+        assert_eq!(history.snapshots[0].rate, invest.nominal_value);
+        let err = execute(deps.as_mut(), mock_env(), info, instant(100)).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InsufficientBuffer {
+                available: Uint128::new(6)
+            }
+        );
+        let buffer = query_buffer(deps.as_ref(), mock_env()).unwrap();
+        assert_eq!(buffer.target, Uint128::new(180));
+        assert_eq!(buffer.utilisation, Decimal::from_ratio(174u128, 180u128));
+
+        // the next epoch tops it up: 300 * 1806 / 1800 for alice, and 10% of 1505 less 6
+        let unbond_msg = ExecuteMsg::Unbond {
+            amount: Uint128::new(300),
+        };
+        let info = mock_info(&alice, &[]);
+        execute(deps.as_mut(), mock_env(), info.clone(), unbond_msg).unwrap();
+        let env = mock_env();
+        let res = process_epoch(deps.as_mut(), env.clone()).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Staking(StakingMsg::Undelegate {
+                validator: DEFAULT_VALIDATOR.to_string(),
+                amount: coin(445, "ustake"),
+            })
+        );
+        assert_eq!(res.attributes[3].value, "301");
+        assert_eq!(res.attributes[4].value, "144");
+        set_delegation(&mut deps.querier, 1355, "ustake");
+        let buffer = query_buffer(deps.as_ref(), env.clone()).unwrap();
+        assert_eq!(buffer.available, Uint128::new(6));
+        assert_eq!(buffer.refilling, Uint128::new(144));
+
+        // once it has arrived, the claim is paid without touching the buffer
+        let matured = later(&env, DAY * 3);
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(451, "ustake"));
+        let buffer = query_buffer(deps.as_ref(), matured.clone()).unwrap();
+        assert_eq!(
+            buffer,
+            BufferResponse {
+                available: Uint128::new(150),
+                refilling: Uint128::zero(),
+                target: Uint128::new(150),
+                utilisation: Decimal::zero(),
+            }
+        );
+        let res = execute(
+            deps.as_mut(),
+            matured.clone(),
+            info,
+            ExecuteMsg::Claim { recipient: None },
+        )
+        .unwrap();
+        assert_eq!(res.attributes[2].value, "301");
+
+        // and the buffer is not mistaken for rewards
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(150, "ustake"));
+        let rebond_msg = ExecuteMsg::_BondAllTokens { keeper: bob };
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        let res = execute(deps.as_mut(), matured, info, rebond_msg).unwrap();
+        assert_eq!(res.messages.len(), 0);
+        let invest = query_investment(deps.as_ref()).unwrap();
+        assert_eq!(
+            invest.nominal_value,
+            Decimal::from_ratio(1505u128, 1500u128)
+        );
+    }
+
+    #[test]
+    fn last_holder_unbonds_with_a_buffer() {
+        let mut deps = mock_dependencies();
+        set_validator(&mut deps.querier);
+
+        let instantiate_msg = InstantiateMsg {
+            buffer_target_bps: 1_000,
+            ..default_instantiate(0, 50)
+        };
+        let info = mock_info("creator", &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // 100 stay liquid, 900 are delegated
+        let info = mock_info("bob", &coins(1000, "ustake"));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Bond {}).unwrap();
+        set_delegation(&mut deps.querier, 900, "ustake");
+
+        // bob's tokens are worth more than the delegations, the rest comes from the buffer
+        let unbond_msg = ExecuteMsg::Unbond {
+            amount: Uint128::new(1000),
+        };
+        let info = mock_info("bob", &[]);
+        execute(deps.as_mut(), mock_env(), info.clone(), unbond_msg).unwrap();
+        let env = mock_env();
+        let res = process_epoch(deps.as_mut(), env.clone()).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(StakingMsg::Undelegate {
+                validator: DEFAULT_VALIDATOR.to_string(),
+                amount: coin(900, "ustake"),
+            })]
+        );
+        assert_eq!(res.attributes[3].value, "1000");
+        assert_eq!(res.attributes[5].value, "100");
+        set_delegation(&mut deps.querier, 0, "ustake");
+        let buffer = query_buffer(deps.as_ref(), env.clone()).unwrap();
+        assert_eq!(buffer.available, Uint128::zero());
+        let supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
+        assert_eq!(supply.issued, Uint128::zero());
+        assert_eq!(supply.claims, Uint128::new(1000));
+
+        // and the whole claim is paid once the undelegation has arrived
+        let matured = later(&env, DAY * 3);
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(1000, "ustake"));
+        let res = execute(
+            deps.as_mut(),
+            matured,
+            info,
+            ExecuteMsg::Claim { recipient: None },
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: "bob".to_string(),
+                amount: coins(1000, "ustake"),
+            })]
+        );
+    }
+
+    #[test]
+    fn exchange_rate_history() {
+        let mut deps = mock_dependencies();
+        set_validator(&mut deps.querier);
+        let creator = String::from("creator");
+        let info = mock_info(&creator, &[]);
+        instantiate(deps.as_mut(), mock_env(), info, default_instantiate(0, 50)).unwrap();
+        let bob = String::from("bob");
+        let info = mock_info(&bob, &coins(1000, "ustake"));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Bond {}).unwrap();
+        set_delegation(&mut deps.querier, 1000, "ustake");
+
+        // 10% rewards after a day, then bob unbonds
+        let env = later(&mock_env(), DAY);
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(100, "ustake"));
+        let rebond_msg = ExecuteMsg::_BondAllTokens {
+            keeper: bob.clone(),
+        };
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        execute(deps.as_mut(), env.clone(), info, rebond_msg).unwrap();
+        set_delegation(&mut deps.querier, 1100, "ustake");
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(0, "ustake"));
+        let info = mock_info(&bob, &[]);
+        let unbond_msg = ExecuteMsg::Unbond {
+            amount: Uint128::new(100),
+        };
+        execute(deps.as_mut(), env.clone(), info, unbond_msg).unwrap();
+
+        // another 10% over the next 10 days
+        let env = later(&env, DAY * 10);
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(110, "ustake"));
+        let rebond_msg = ExecuteMsg::_BondAllTokens { keeper: bob };
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        execute(deps.as_mut(), env.clone(), info, rebond_msg).unwrap();
+
+        let msg = QueryMsg::ExchangeRateHistory {
+            start_after: None,
+            limit: None,
+        };
+        let res = query(deps.as_ref(), env.clone(), msg).unwrap();
+        let history: ExchangeRateHistoryResponse = cosmwasm_std::from_binary(&res).unwrap();
+        let rates: Vec<_> = history
+            .snapshots
+            .iter()
+            .map(|s| (s.seq, s.bonded.u128(), s.issued.u128(), s.rate))
+            .collect();
+        assert_eq!(
+            rates,
+            vec![
+                (0, 1100, 1000, Decimal::percent(110)),
+                (1, 1210, 1000, Decimal::percent(121)),
+            ]
+        );
+
+        // 10% in 10 days is 365% a year
+        let msg = QueryMsg::EstimatedApr {
+            window: 10 * 24 * 60 * 60,
+        };
+        let res = query(deps.as_ref(), env, msg).unwrap();
+        let apr: EstimatedAprResponse = cosmwasm_std::from_binary(&res).unwrap();
+        assert_eq!(apr.apr, Some(Decimal::percent(365)));
+    }
+
+    #[test]
+    fn bond_limits() {
+        let mut deps = mock_dependencies();
+        set_validator(&mut deps.querier);
+        let creator = String::from("creator");
+        let instantiate_msg = InstantiateMsg {
+            max_bonded: Some(Uint128::new(1500)),
+            max_bonded_per_address: Some(Uint128::new(1000)),
+            allowlist: Some(vec!["bob".to_string()]),
+            ..default_instantiate(0, 50)
+        };
+        let info = mock_info(&creator, &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let bond = |deps: DepsMut, sender: &str, amount| {
+            let info = mock_info(sender, &coins(amount, "ustake"));
+            execute(deps, mock_env(), info, ExecuteMsg::Bond {})
+        };
+        let err = bond(deps.as_mut(), "carl", 100).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::NotAllowlisted {
+                address: "carl".to_string()
+            }
+        );
+        let err = bond(deps.as_mut(), "bob", 1200).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::AddressCapReached {
+                headroom: Uint128::new(1000)
+            }
+        );
+        bond(deps.as_mut(), "bob", 800).unwrap();
+        set_delegation(&mut deps.querier, 800, "ustake");
+
+        let headroom = |deps: Deps, address: &str| -> HeadroomResponse {
+            let msg = QueryMsg::Headroom {
+                address: address.to_string(),
+            };
+            cosmwasm_std::from_binary(&query(deps, mock_env(), msg).unwrap()).unwrap()
+        };
+        assert_eq!(
+            headroom(deps.as_ref(), "bob"),
+            HeadroomResponse {
+                allowed: true,
+                total: Some(Uint128::new(700)),
+                address: Some(Uint128::new(200)),
+            }
+        );
+        assert!(!headroom(deps.as_ref(), "carl").allowed);
+
+        // moving tokens elsewhere doesn't make room, unbonding them does
+        let transfer = ExecuteMsg::Transfer {
+            recipient: "carl".to_string(),
+            amount: Uint128::new(500),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), transfer).unwrap();
+        assert_eq!(
+            headroom(deps.as_ref(), "bob").address,
+            Some(Uint128::new(200))
+        );
+        let unbond = ExecuteMsg::Unbond {
+            amount: Uint128::new(300),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), unbond).unwrap();
+        assert_eq!(
+            headroom(deps.as_ref(), "bob").address,
+            Some(Uint128::new(500))
+        );
+
+        // the owner lets carl in, who fills up the rest
+        let update = ExecuteMsg::UpdateAllowlist {
+            add: vec!["carl".to_string()],
+            remove: vec![],
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            update.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        execute(deps.as_mut(), mock_env(), mock_info(&creator, &[]), update).unwrap();
+        let err = bond(deps.as_mut(), "carl", 800).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::BondCapReached {
+                headroom: Uint128::new(700)
+            }
+        );
+        bond(deps.as_mut(), "carl", 700).unwrap();
+        set_delegation(&mut deps.querier, 1500, "ustake");
+        let msg = QueryMsg::Allowlist {
+            start_after: None,
+            limit: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let allowlist: AllowlistResponse = cosmwasm_std::from_binary(&res).unwrap();
+        assert_eq!(allowlist.addresses, vec!["bob", "carl"]);
+
+        // and lifts all limits after the launch
+        let limits = ExecuteMsg::UpdateBondLimits {
+            max_bonded: None,
+            max_bonded_per_address: None,
+            allowlist_enabled: false,
+        };
+        let info = mock_info(&creator, &[]);
+        execute(deps.as_mut(), mock_env(), info, limits).unwrap();
+        bond(deps.as_mut(), "dave", 5000).unwrap();
+        assert_eq!(
+            headroom(deps.as_ref(), "dave"),
+            HeadroomResponse {
+                allowed: true,
+                total: None,
+                address: None,
+            }
+        );
+        let invest = query_investment(deps.as_ref()).unwrap();
+        assert_eq!(invest.max_bonded, None);
+        assert!(!invest.allowlist_enabled);
+    }
 }
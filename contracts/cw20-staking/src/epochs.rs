@@ -0,0 +1,210 @@
+use cosmwasm_std::{
+    coin, Addr, BlockInfo, Deps, DepsMut, Env, MessageInfo, Order as Ordering, Response,
+    StakingMsg, StdError, StdResult, Storage, Uint128,
+};
+use cw20_base::contract::execute_burn;
+use cw_storage_plus::Bound;
+
+use crate::buffer::{buffer_deficit, draw_liquid};
+use crate::error::ContractError;
+use crate::history::record_rate;
+use crate::msg::{ClaimsResponse, EpochClaim, PendingEpochResponse, ProcessedEpochsResponse};
+use crate::slashing::{sync_bonded, track_unbonding};
+use crate::state::{
+    InvestmentInfo, PendingEpoch, ProcessedEpoch, BUFFER_REFILLS, EPOCH_CLAIMS, EPOCH_COUNT,
+    INVESTMENT, PENDING_EPOCH, PROCESSED_EPOCHS, TOTAL_SUPPLY,
+};
+use crate::validators::{get_delegations, total, unbond_allocation};
+
+// settings for pagination
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+
+/// Adds `tokens` unbonded by `address` to the pending epoch, opening a new one if needed.
+/// The tokens must already be held by the contract
+pub fn queue_unbond(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    invest: &InvestmentInfo,
+    address: &Addr,
+    tokens: Uint128,
+) -> StdResult<PendingEpoch> {
+    let mut epoch = match PENDING_EPOCH.may_load(storage)? {
+        Some(epoch) => epoch,
+        None => {
+            let id = EPOCH_COUNT.may_load(storage)?.unwrap_or_default() + 1;
+            EPOCH_COUNT.save(storage, &id)?;
+            PendingEpoch {
+                id,
+                tokens: Uint128::zero(),
+                closes_at: invest.unbonding_epoch.after(block),
+            }
+        }
+    };
+    epoch.tokens += tokens;
+    PENDING_EPOCH.save(storage, &epoch)?;
+    EPOCH_CLAIMS.update(storage, (address, epoch.id), |claim| -> StdResult<_> {
+        Ok(claim.unwrap_or_default() + tokens)
+    })?;
+    Ok(epoch)
+}
+
+/// Burns the tokens queued in the pending epoch, and undelegates what they are worth in one go
+pub fn execute_process_epoch(
+    mut deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let invest = INVESTMENT.load(deps.storage)?;
+    let pending = PENDING_EPOCH
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NoPendingEpoch {})?;
+    if !pending.closes_at.is_expired(&env.block) {
+        return Err(ContractError::EpochNotClosed {
+            closes_at: pending.closes_at,
+        });
+    }
+
+    // bonded is the total number of tokens we have delegated from this address
+    let delegations = get_delegations(&deps.querier, &env.contract.address)?;
+    let bonded = total(&delegations);
+
+    // calculate how many native tokens the epoch is worth and update supply
+    let mut supply = TOTAL_SUPPLY.load(deps.storage)?;
+    // supply caches the (expected) results of get_delegations(), unless we were slashed
+    let slashed = sync_bonded(deps.storage, &env.block, &mut supply, bonded)?;
+    let unbond = pending.tokens.multiply_ratio(supply.value(), supply.issued);
+    let value = supply.value() - unbond;
+    // the epoch may be worth more than the delegations (eg. when the last holders leave),
+    // the rest is paid from the liquid buffer
+    let from_buffer = unbond.saturating_sub(bonded);
+    draw_liquid(deps.storage, &mut supply, from_buffer)?;
+    let remaining = bonded - (unbond - from_buffer);
+    // top up the buffer with the same undelegation, as far as the delegations allow
+    let refill = buffer_deficit(&invest, &supply, value).min(remaining);
+    supply.bonded = remaining - refill;
+    supply.issued = supply
+        .issued
+        .checked_sub(pending.tokens)
+        .map_err(StdError::overflow)?;
+    supply.claims += unbond;
+    supply.refilling += refill;
+    TOTAL_SUPPLY.save(deps.storage, &supply)?;
+    record_rate(deps.storage, &env.block, &supply)?;
+
+    // the contract holds the queued tokens, burn them as self
+    let sub_info = MessageInfo {
+        sender: env.contract.address.clone(),
+        funds: vec![],
+    };
+    execute_burn(deps.branch(), env.clone(), sub_info, pending.tokens)?;
+
+    let epoch = ProcessedEpoch {
+        id: pending.id,
+        tokens: pending.tokens,
+        unbonded: unbond,
+        claimable: unbond,
+        buffer_refill: refill,
+        release_at: invest.unbonding_period.after(&env.block),
+    };
+    PROCESSED_EPOCHS.save(deps.storage, epoch.id, &epoch)?;
+    if !refill.is_zero() {
+        BUFFER_REFILLS.save(deps.storage, epoch.id, &refill)?;
+    }
+    track_unbonding(deps.storage, &env.block, epoch.id)?;
+    PENDING_EPOCH.remove(deps.storage);
+
+    // unbond them, from the most overweight validators first
+    let to_undelegate = unbond - from_buffer + refill;
+    let undelegate = unbond_allocation(&invest.validators, &delegations, to_undelegate)?
+        .into_iter()
+        .map(|(validator, amount)| StakingMsg::Undelegate {
+            validator,
+            amount: coin(amount.u128(), &invest.bond_denom),
+        });
+    let res = Response::new()
+        .add_messages(undelegate)
+        .add_events(slashed)
+        .add_attribute("action", "process_unbonding_epoch")
+        .add_attribute("epoch", epoch.id.to_string())
+        .add_attribute("burnt", epoch.tokens)
+        .add_attribute("unbonded", unbond)
+        .add_attribute("buffer_refill", refill)
+        .add_attribute("from_buffer", from_buffer);
+    Ok(res)
+}
+
+/// What a share of `tokens` in this epoch is worth in native tokens
+fn claim_amount(epoch: &ProcessedEpoch, tokens: Uint128) -> Uint128 {
+    tokens.multiply_ratio(epoch.claimable, epoch.tokens)
+}
+
+/// Removes the claims of `address` from all released epochs and returns what they pay out.
+/// Only whole claims are paid, and no more than `cap` in total
+pub fn claim_epochs(
+    storage: &mut dyn Storage,
+    address: &Addr,
+    block: &BlockInfo,
+    cap: Uint128,
+) -> StdResult<Uint128> {
+    let claims = EPOCH_CLAIMS
+        .prefix(address)
+        .range(storage, None, None, Ordering::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let mut to_send = Uint128::zero();
+    for (id, tokens) in claims {
+        let epoch = match PROCESSED_EPOCHS.may_load(storage, id)? {
+            Some(epoch) if epoch.release_at.is_expired(block) => epoch,
+            _ => continue,
+        };
+        let amount = claim_amount(&epoch, tokens);
+        if to_send + amount > cap {
+            break;
+        }
+        to_send += amount;
+        EPOCH_CLAIMS.remove(storage, (address, id));
+    }
+    Ok(to_send)
+}
+
+/// Claims are stored per epoch, this returns what they are worth now
+pub fn query_claims(deps: Deps, address: String) -> StdResult<ClaimsResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let claims = EPOCH_CLAIMS
+        .prefix(&address)
+        .range(deps.storage, None, None, Ordering::Ascending)
+        .map(|item| {
+            let (id, tokens) = item?;
+            let epoch = PROCESSED_EPOCHS.may_load(deps.storage, id)?;
+            Ok(EpochClaim {
+                epoch: id,
+                tokens,
+                amount: epoch.as_ref().map(|e| claim_amount(e, tokens)),
+                release_at: epoch.map(|e| e.release_at),
+            })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(ClaimsResponse { claims })
+}
+
+pub fn query_pending_epoch(deps: Deps) -> StdResult<PendingEpochResponse> {
+    let epoch = PENDING_EPOCH.may_load(deps.storage)?;
+    Ok(PendingEpochResponse { epoch })
+}
+
+pub fn query_processed_epochs(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<ProcessedEpochsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let epochs = PROCESSED_EPOCHS
+        .range(deps.storage, start, None, Ordering::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, epoch)| epoch))
+        .collect::<StdResult<_>>()?;
+    Ok(ProcessedEpochsResponse { epochs })
+}
@@ -1,4 +1,5 @@
-use cosmwasm_std::{StdError, Uint128};
+use cosmwasm_std::{Decimal, StdError, Uint128};
+use cw_utils::{Duration, Expiration};
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -12,6 +13,81 @@ pub enum ContractError {
     #[error("Validator '{validator}' not in current validator set")]
     NotInValidatorSet { validator: String },
 
+    #[error("Validator set must not be empty")]
+    EmptyValidatorSet {},
+
+    #[error("Validator '{validator}' is listed twice")]
+    DuplicateValidator { validator: String },
+
+    #[error("Validator '{validator}' must have a non-zero weight")]
+    ZeroWeight { validator: String },
+
+    #[error("Validator '{validator}' is not in the validator set of this contract")]
+    NotInSet { validator: String },
+
+    #[error("Cannot redelegate to the same validator")]
+    SameValidator {},
+
+    #[error("Can only redelegate {max} from validator '{validator}' right now")]
+    RedelegateTooMuch { validator: String, max: Uint128 },
+
+    #[error("Exit tax must not exceed {max}")]
+    ExitTaxTooHigh { max: Decimal },
+
+    #[error("Unbonding period must not be zero")]
+    ZeroUnbondingPeriod {},
+
+    #[error("Unbonding period must be at least the chain's unbonding time ({min})")]
+    UnbondingPeriodTooShort { min: Duration },
+
+    #[error("Unbonding period must not exceed {max} seconds (or blocks)")]
+    UnbondingPeriodTooLong { max: u64 },
+
+    #[error("Unbonding epoch must not exceed {max} seconds (or blocks)")]
+    UnbondingEpochTooLong { max: u64 },
+
+    #[error("Minimum withdrawal must not exceed {max}")]
+    MinWithdrawalTooHigh { max: Uint128 },
+
+    #[error("No ownership transfer pending")]
+    NoPendingOwner {},
+
+    #[error("Buffer target must not exceed {max_bps} bps")]
+    BufferTargetTooHigh { max_bps: u16 },
+
+    #[error("Instant unbond fee must not exceed {max_bps} bps")]
+    InstantUnbondFeeTooHigh { max_bps: u16 },
+
+    #[error("Only {available} available for instant unbonds")]
+    InsufficientBuffer { available: Uint128 },
+
+    #[error("Keeper bounty ({keeper_bounty_bps} bps) and commission ({commission_bps} bps) exceed all rewards")]
+    FeesTooHigh {
+        keeper_bounty_bps: u16,
+        commission_bps: u16,
+    },
+
+    #[error("Proposal {proposal_id} is not open for votes through this contract")]
+    UnknownProposal { proposal_id: u64 },
+
+    #[error("Voting on proposal {proposal_id} has ended")]
+    VotingClosed { proposal_id: u64 },
+
+    #[error("Only holders can vote")]
+    NoVotingPower {},
+
+    #[error("No holder with a balance voted on proposal {proposal_id}")]
+    NoVotes { proposal_id: u64 },
+
+    #[error("Address {address} is not allowed to bond")]
+    NotAllowlisted { address: String },
+
+    #[error("Bond cap reached, only {headroom} more can be bonded")]
+    BondCapReached { headroom: Uint128 },
+
+    #[error("Bond cap per address reached, only {headroom} more can be bonded")]
+    AddressCapReached { headroom: Uint128 },
+
     #[error("Different denominations in bonds: '{denom1}' vs. '{denom2}'")]
     DifferentBondDenom { denom1: String, denom2: String },
 
@@ -30,6 +106,12 @@ pub enum ContractError {
     #[error("No claims that can be released currently")]
     NothingToClaim {},
 
+    #[error("No unbond requests to process")]
+    NoPendingEpoch {},
+
+    #[error("Unbonding epoch is open until {closes_at}")]
+    EpochNotClosed { closes_at: Expiration },
+
     #[error("Cannot set to own account")]
     CannotSetOwnAccount {},
 
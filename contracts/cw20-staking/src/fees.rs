@@ -0,0 +1,106 @@
+use cosmwasm_std::Uint128;
+
+use crate::error::ContractError;
+use crate::state::InvestmentInfo;
+
+/// Fees are given in basis points, this is 100%
+pub const BPS_DENOMINATOR: u16 = 10_000;
+
+/// How reinvested rewards are split
+#[derive(Clone, Debug, PartialEq)]
+pub struct RewardSplit {
+    /// paid to whoever called `Reinvest`
+    pub keeper_bounty: Uint128,
+    /// paid to the owner
+    pub commission: Uint128,
+    /// delegated for the holders
+    pub bonded: Uint128,
+}
+
+/// Both fees are taken from the same rewards, so together they must not exceed them
+pub fn validate_fees(keeper_bounty_bps: u16, commission_bps: u16) -> Result<(), ContractError> {
+    if u32::from(keeper_bounty_bps) + u32::from(commission_bps) > u32::from(BPS_DENOMINATOR) {
+        return Err(ContractError::FeesTooHigh {
+            keeper_bounty_bps,
+            commission_bps,
+        });
+    }
+    Ok(())
+}
+
+pub fn bps_of(amount: Uint128, bps: u16) -> Uint128 {
+    amount.multiply_ratio(bps, BPS_DENOMINATOR)
+}
+
+/// Sets the keeper bounty and commission aside from `rewards`, the remainder is bonded
+pub fn split_rewards(invest: &InvestmentInfo, rewards: Uint128) -> RewardSplit {
+    let keeper_bounty = bps_of(rewards, invest.keeper_bounty_bps);
+    let commission = bps_of(rewards, invest.commission_bps);
+    RewardSplit {
+        keeper_bounty,
+        commission,
+        bonded: rewards - keeper_bounty - commission,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::ExitTaxMode;
+    use cosmwasm_std::{Addr, Decimal};
+    use cw_utils::DAY;
+
+    fn invest(keeper_bounty_bps: u16, commission_bps: u16) -> InvestmentInfo {
+        InvestmentInfo {
+            owner: Addr::unchecked("owner"),
+            bond_denom: "ustake".to_string(),
+            unbonding_period: DAY * 21,
+            min_unbonding_period: DAY * 21,
+            unbonding_epoch: DAY,
+            exit_tax: Decimal::zero(),
+            treasury: Addr::unchecked("owner"),
+            exit_tax_mode: ExitTaxMode::Treasury,
+            validators: vec![],
+            min_withdrawal: Uint128::zero(),
+            keeper_bounty_bps,
+            commission_bps,
+            buffer_target_bps: 0,
+            instant_unbond_fee_bps: 0,
+            max_bonded: None,
+            max_bonded_per_address: None,
+            allowlist_enabled: false,
+        }
+    }
+
+    #[test]
+    fn rewards_are_split_by_bps() {
+        let split = split_rewards(&invest(50, 1000), Uint128::new(10_000));
+        assert_eq!(
+            split,
+            RewardSplit {
+                keeper_bounty: Uint128::new(50),
+                commission: Uint128::new(1000),
+                bonded: Uint128::new(8950),
+            }
+        );
+
+        // fees round down, in favour of the holders
+        let split = split_rewards(&invest(50, 1000), Uint128::new(199));
+        assert_eq!(split.keeper_bounty, Uint128::zero());
+        assert_eq!(split.commission, Uint128::new(19));
+        assert_eq!(split.bonded, Uint128::new(180));
+
+        // all of it can go to fees, but not more
+        let split = split_rewards(&invest(4000, 6000), Uint128::new(333));
+        assert_eq!(split.bonded, Uint128::new(1));
+        validate_fees(4000, 6000).unwrap();
+        let err = validate_fees(4001, 6000).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::FeesTooHigh {
+                keeper_bounty_bps: 4001,
+                commission_bps: 6000
+            }
+        );
+    }
+}
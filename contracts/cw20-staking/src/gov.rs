@@ -0,0 +1,349 @@
+use cosmwasm_std::{
+    Addr, BlockInfo, Decimal, Deps, DepsMut, Env, GovMsg, MessageInfo, Order as Ordering, Response,
+    StdError, StdResult, Storage, Uint128, VoteOption, WeightedVoteOption,
+};
+use cw20_base::state::BALANCES;
+use cw_utils::Expiration;
+
+use crate::error::ContractError;
+use crate::msg::{ProposalResponse, VoteResponse, VoteTally};
+use crate::state::{Proposal, INVESTMENT, OPEN_VOTES, PROPOSALS, TALLIES, VOTES};
+
+/// Opens a chain proposal for votes (owner only). Registering it again moves the end of voting,
+/// as long as it has not ended yet
+pub fn execute_register_proposal(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    proposal_id: u64,
+    voting_ends: Expiration,
+) -> Result<Response, ContractError> {
+    let invest = INVESTMENT.load(deps.storage)?;
+    if info.sender != invest.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if voting_ends.is_expired(&env.block) {
+        return Err(ContractError::VotingClosed { proposal_id });
+    }
+    // the tallies stop following balances once voting has ended, so it can't be reopened
+    if let Some(proposal) = PROPOSALS.may_load(deps.storage, proposal_id)? {
+        if proposal.voting_ends.is_expired(&env.block) {
+            return Err(ContractError::VotingClosed { proposal_id });
+        }
+    }
+    PROPOSALS.save(deps.storage, proposal_id, &Proposal { voting_ends })?;
+
+    let res = Response::new()
+        .add_attribute("action", "register_proposal")
+        .add_attribute("proposal_id", proposal_id.to_string())
+        .add_attribute("voting_ends", voting_ends.to_string());
+    Ok(res)
+}
+
+fn open_proposal(
+    storage: &dyn Storage,
+    env: &Env,
+    proposal_id: u64,
+) -> Result<Proposal, ContractError> {
+    let proposal = PROPOSALS
+        .may_load(storage, proposal_id)?
+        .ok_or(ContractError::UnknownProposal { proposal_id })?;
+    if proposal.voting_ends.is_expired(&env.block) {
+        return Err(ContractError::VotingClosed { proposal_id });
+    }
+    Ok(proposal)
+}
+
+pub fn execute_vote(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    proposal_id: u64,
+    vote: VoteOption,
+) -> Result<Response, ContractError> {
+    open_proposal(deps.storage, &env, proposal_id)?;
+    let balance = BALANCES
+        .may_load(deps.storage, &info.sender)?
+        .unwrap_or_default();
+    if balance.is_zero() {
+        return Err(ContractError::NoVotingPower {});
+    }
+    if let Some(previous) = VOTES.may_load(deps.storage, (proposal_id, &info.sender))? {
+        let counted = OPEN_VOTES
+            .may_load(deps.storage, (&info.sender, proposal_id))?
+            .unwrap_or_default();
+        update_tally(
+            deps.storage,
+            proposal_id,
+            &previous,
+            counted,
+            Uint128::zero(),
+        )?;
+    }
+    update_tally(deps.storage, proposal_id, &vote, Uint128::zero(), balance)?;
+    OPEN_VOTES.save(deps.storage, (&info.sender, proposal_id), &balance)?;
+    VOTES.save(deps.storage, (proposal_id, &info.sender), &vote)?;
+
+    let res = Response::new()
+        .add_attribute("action", "vote")
+        .add_attribute("proposal_id", proposal_id.to_string())
+        .add_attribute("voter", info.sender)
+        .add_attribute("option", option_name(&vote));
+    Ok(res)
+}
+
+/// Votes with all delegations at once, weighted by the balances of the voters right now.
+/// Tokens only count for whoever holds them when the vote is cast, so they cannot vote twice
+pub fn execute_cast_vote(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    proposal_id: u64,
+) -> Result<Response, ContractError> {
+    open_proposal(deps.storage, &env, proposal_id)?;
+    let tally = tally(deps.storage, proposal_id)?;
+    let options = weighted_options(&tally);
+    if options.is_empty() {
+        return Err(ContractError::NoVotes { proposal_id });
+    }
+
+    let attributes = tally
+        .iter()
+        .map(|t| (option_name(&t.option), t.amount.to_string()));
+    let res = Response::new()
+        .add_message(GovMsg::VoteWeighted {
+            proposal_id,
+            options,
+        })
+        .add_attribute("action", "cast_vote")
+        .add_attribute("proposal_id", proposal_id.to_string())
+        .add_attributes(attributes);
+    Ok(res)
+}
+
+fn option_name(option: &VoteOption) -> &'static str {
+    match option {
+        VoteOption::Yes => "yes",
+        VoteOption::No => "no",
+        VoteOption::Abstain => "abstain",
+        VoteOption::NoWithVeto => "no_with_veto",
+    }
+}
+
+/// Replaces `removed` tokens with `added` ones in the running total of `option`
+fn update_tally(
+    storage: &mut dyn Storage,
+    proposal_id: u64,
+    option: &VoteOption,
+    removed: Uint128,
+    added: Uint128,
+) -> StdResult<()> {
+    TALLIES.update(
+        storage,
+        (proposal_id, option_name(option)),
+        |amount| -> StdResult<_> {
+            (amount.unwrap_or_default() + added)
+                .checked_sub(removed)
+                .map_err(StdError::overflow)
+        },
+    )?;
+    Ok(())
+}
+
+/// Recounts the votes of `addresses` on open proposals with their current balances. This must
+/// follow every balance change, so the tallies stay the sum of the voters' balances without
+/// going over all votes. Votes on closed proposals are dropped from `OPEN_VOTES` on the way
+pub fn update_votes(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    addresses: &[&Addr],
+) -> StdResult<()> {
+    for address in addresses {
+        let votes = OPEN_VOTES
+            .prefix(address)
+            .range(storage, None, None, Ordering::Ascending)
+            .collect::<StdResult<Vec<_>>>()?;
+        if votes.is_empty() {
+            continue;
+        }
+        let balance = BALANCES.may_load(storage, address)?.unwrap_or_default();
+        for (proposal_id, counted) in votes {
+            let proposal = PROPOSALS.load(storage, proposal_id)?;
+            if proposal.voting_ends.is_expired(block) {
+                OPEN_VOTES.remove(storage, (address, proposal_id));
+                continue;
+            }
+            let vote = VOTES.load(storage, (proposal_id, address))?;
+            update_tally(storage, proposal_id, &vote, counted, balance)?;
+            OPEN_VOTES.save(storage, (address, proposal_id), &balance)?;
+        }
+    }
+    Ok(())
+}
+
+/// The balances of the voters by option, as of the last balance change before voting ended
+fn tally(storage: &dyn Storage, proposal_id: u64) -> StdResult<Vec<VoteTally>> {
+    vec![
+        VoteOption::Yes,
+        VoteOption::No,
+        VoteOption::Abstain,
+        VoteOption::NoWithVeto,
+    ]
+    .into_iter()
+    .map(|option| {
+        let amount = TALLIES
+            .may_load(storage, (proposal_id, option_name(&option)))?
+            .unwrap_or_default();
+        Ok(VoteTally { option, amount })
+    })
+    .collect()
+}
+
+/// The chain wants the weights to add up to exactly one, so the last option gets what the
+/// others left over after rounding
+fn weighted_options(tally: &[VoteTally]) -> Vec<WeightedVoteOption> {
+    let total: Uint128 = tally.iter().map(|t| t.amount).sum();
+    let voted: Vec<_> = tally.iter().filter(|t| !t.amount.is_zero()).collect();
+    let mut remaining = Decimal::one();
+    voted
+        .iter()
+        .enumerate()
+        .map(|(i, t)| {
+            let weight = if i + 1 == voted.len() {
+                remaining
+            } else {
+                Decimal::from_ratio(t.amount, total)
+            };
+            remaining -= weight;
+            WeightedVoteOption {
+                option: t.option.clone(),
+                weight,
+            }
+        })
+        .collect()
+}
+
+pub fn query_proposal(deps: Deps, proposal_id: u64) -> StdResult<ProposalResponse> {
+    let proposal = PROPOSALS.load(deps.storage, proposal_id)?;
+    Ok(ProposalResponse {
+        proposal_id,
+        voting_ends: proposal.voting_ends,
+        tally: tally(deps.storage, proposal_id)?,
+    })
+}
+
+pub fn query_vote(deps: Deps, proposal_id: u64, address: String) -> StdResult<VoteResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let vote = VOTES.may_load(deps.storage, (proposal_id, &address))?;
+    Ok(VoteResponse { vote })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+
+    fn tally(amounts: [u128; 4]) -> Vec<VoteTally> {
+        let options = vec![
+            VoteOption::Yes,
+            VoteOption::No,
+            VoteOption::Abstain,
+            VoteOption::NoWithVeto,
+        ];
+        options
+            .into_iter()
+            .zip(amounts.iter())
+            .map(|(option, amount)| VoteTally {
+                option,
+                amount: Uint128::new(*amount),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn weights_add_up_to_one() {
+        assert_eq!(weighted_options(&tally([0, 0, 0, 0])), vec![]);
+
+        // options without votes are left out
+        let options = weighted_options(&tally([300, 0, 100, 0]));
+        assert_eq!(
+            options,
+            vec![
+                WeightedVoteOption {
+                    option: VoteOption::Yes,
+                    weight: Decimal::percent(75),
+                },
+                WeightedVoteOption {
+                    option: VoteOption::Abstain,
+                    weight: Decimal::percent(25),
+                },
+            ]
+        );
+
+        // thirds round down, the last one takes the rest
+        let options = weighted_options(&tally([1, 1, 0, 1]));
+        let third = Decimal::from_ratio(1u128, 3u128);
+        let weights: Vec<_> = options.iter().map(|o| o.weight).collect();
+        assert_eq!(weights, vec![third, third, Decimal::one() - third - third]);
+        assert_eq!(options[2].option, VoteOption::NoWithVeto);
+    }
+
+    #[test]
+    fn tallies_follow_balances_while_voting_is_open() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let voting_ends = Expiration::AtTime(env.block.time.plus_seconds(100));
+        PROPOSALS
+            .save(&mut deps.storage, 7, &Proposal { voting_ends })
+            .unwrap();
+        let set_balance = |storage: &mut dyn Storage, address: &str, amount: u128| {
+            let address = Addr::unchecked(address);
+            BALANCES
+                .save(storage, &address, &Uint128::new(amount))
+                .unwrap();
+        };
+        let amounts = |deps: Deps| -> Vec<u128> {
+            let proposal = query_proposal(deps, 7).unwrap();
+            proposal.tally.iter().map(|t| t.amount.u128()).collect()
+        };
+
+        set_balance(&mut deps.storage, "bob", 600);
+        set_balance(&mut deps.storage, "carl", 400);
+        let info = mock_info("bob", &[]);
+        execute_vote(deps.as_mut(), env.clone(), info, 7, VoteOption::Yes).unwrap();
+        let info = mock_info("carl", &[]);
+        execute_vote(deps.as_mut(), env.clone(), info, 7, VoteOption::No).unwrap();
+        assert_eq!(amounts(deps.as_ref()), vec![600, 400, 0, 0]);
+
+        // bob sends 100 to carl, and 50 to dave, who didn't vote
+        set_balance(&mut deps.storage, "bob", 450);
+        set_balance(&mut deps.storage, "carl", 500);
+        set_balance(&mut deps.storage, "dave", 50);
+        let (bob, carl, dave) = (
+            Addr::unchecked("bob"),
+            Addr::unchecked("carl"),
+            Addr::unchecked("dave"),
+        );
+        update_votes(&mut deps.storage, &env.block, &[&bob, &carl, &dave]).unwrap();
+        assert_eq!(amounts(deps.as_ref()), vec![450, 500, 0, 0]);
+
+        // changing the vote moves the whole balance
+        let info = mock_info("bob", &[]);
+        execute_vote(deps.as_mut(), env.clone(), info, 7, VoteOption::Abstain).unwrap();
+        assert_eq!(amounts(deps.as_ref()), vec![0, 500, 450, 0]);
+
+        // once voting has ended, the tally stays as it was and closed votes are dropped
+        env.block.time = env.block.time.plus_seconds(100);
+        set_balance(&mut deps.storage, "carl", 0);
+        update_votes(&mut deps.storage, &env.block, &[&carl]).unwrap();
+        assert_eq!(amounts(deps.as_ref()), vec![0, 500, 450, 0]);
+        assert_eq!(
+            OPEN_VOTES.may_load(&deps.storage, (&carl, 7)).unwrap(),
+            None
+        );
+        assert_eq!(
+            OPEN_VOTES.load(&deps.storage, (&bob, 7)).unwrap(),
+            Uint128::new(450)
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use cosmwasm_std::{
+    BlockInfo, Decimal, Deps, Env, Order as Ordering, StdResult, Storage, Timestamp,
+};
+use cw_storage_plus::Bound;
+
+use crate::msg::{EstimatedAprResponse, ExchangeRateHistoryResponse, ExchangeRateSnapshot};
+use crate::state::{RateSnapshot, Supply, RATE_COUNT, RATE_HISTORY};
+
+/// Only this many snapshots are kept, older ones are dropped as new ones come in
+pub const MAX_RATE_SNAPSHOTS: u64 = 100;
+
+// settings for pagination
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+
+const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+
+/// Records the current exchange rate, dropping the oldest snapshot once the history is full.
+/// A block only keeps its last snapshot, so many calls in one block can't push out the history
+pub fn record_rate(storage: &mut dyn Storage, block: &BlockInfo, supply: &Supply) -> StdResult<()> {
+    let seq = RATE_COUNT.may_load(storage)?.unwrap_or_default();
+    let snapshot = RateSnapshot {
+        time: block.time,
+        bonded: supply.value(),
+        issued: supply.issued,
+    };
+    if let Some(latest) = seq.checked_sub(1) {
+        if RATE_HISTORY.load(storage, latest)?.time == block.time {
+            return RATE_HISTORY.save(storage, latest, &snapshot);
+        }
+    }
+    RATE_HISTORY.save(storage, seq, &snapshot)?;
+    if seq >= MAX_RATE_SNAPSHOTS {
+        RATE_HISTORY.remove(storage, seq - MAX_RATE_SNAPSHOTS);
+    }
+    RATE_COUNT.save(storage, &(seq + 1))
+}
+
+fn rate(snapshot: &RateSnapshot) -> Decimal {
+    if snapshot.issued.is_zero() {
+        Decimal::one()
+    } else {
+        Decimal::from_ratio(snapshot.bonded, snapshot.issued)
+    }
+}
+
+fn to_response(seq: u64, snapshot: RateSnapshot) -> ExchangeRateSnapshot {
+    ExchangeRateSnapshot {
+        seq,
+        rate: rate(&snapshot),
+        time: snapshot.time,
+        bonded: snapshot.bonded,
+        issued: snapshot.issued,
+    }
+}
+
+pub fn query_rate_history(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<ExchangeRateHistoryResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let snapshots = RATE_HISTORY
+        .range(deps.storage, start, None, Ordering::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(seq, snapshot)| to_response(seq, snapshot)))
+        .collect::<StdResult<_>>()?;
+    Ok(ExchangeRateHistoryResponse { snapshots })
+}
+
+/// Annualises the growth of the exchange rate from the first snapshot within the last `window`
+/// seconds to the latest one. Losses show as zero, and there is no estimate if everything was
+/// slashed at the start or the growth doesn't fit into a `Decimal`
+pub fn query_estimated_apr(deps: Deps, env: Env, window: u64) -> StdResult<EstimatedAprResponse> {
+    let since = Timestamp::from_seconds(env.block.time.seconds().saturating_sub(window));
+    let mut snapshots = RATE_HISTORY
+        .range(deps.storage, None, None, Ordering::Ascending)
+        .filter(|item| match item {
+            Ok((_, snapshot)) => snapshot.time >= since,
+            Err(_) => true,
+        });
+    let first = snapshots.next().transpose()?;
+    let last = snapshots.last().transpose()?;
+
+    let (first, last) = match (first, last) {
+        (Some((_, first)), Some((_, last))) if last.time > first.time => (first, last),
+        _ => return Ok(EstimatedAprResponse { apr: None }),
+    };
+    let elapsed = last.time.seconds() - first.time.seconds();
+    let growth = match rate(&last).checked_div(rate(&first)) {
+        Ok(growth) => growth,
+        Err(_) => return Ok(EstimatedAprResponse { apr: None }),
+    };
+    let apr = if growth > Decimal::one() {
+        (growth - Decimal::one())
+            .checked_mul(Decimal::from_ratio(SECONDS_PER_YEAR, elapsed))
+            .ok()
+    } else {
+        Some(Decimal::zero())
+    };
+    Ok(EstimatedAprResponse { apr })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env};
+    use cosmwasm_std::Uint128;
+
+    fn supply(bonded: u128, issued: u128) -> Supply {
+        Supply {
+            bonded: Uint128::new(bonded),
+            issued: Uint128::new(issued),
+            ..Supply::default()
+        }
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut deps = mock_dependencies();
+        let mut block = mock_env().block;
+        for i in 0..MAX_RATE_SNAPSHOTS + 5 {
+            block.time = block.time.plus_seconds(60);
+            record_rate(&mut deps.storage, &block, &supply(1000 + i as u128, 1000)).unwrap();
+        }
+
+        // the oldest 5 are gone
+        let history = query_rate_history(deps.as_ref(), None, Some(2)).unwrap();
+        let seqs: Vec<_> = history.snapshots.iter().map(|s| s.seq).collect();
+        assert_eq!(seqs, vec![5, 6]);
+        assert_eq!(history.snapshots[0].bonded, Uint128::new(1005));
+        assert_eq!(history.snapshots[0].rate, Decimal::permille(1005));
+
+        let history =
+            query_rate_history(deps.as_ref(), Some(MAX_RATE_SNAPSHOTS + 2), None).unwrap();
+        let seqs: Vec<_> = history.snapshots.iter().map(|s| s.seq).collect();
+        assert_eq!(seqs, vec![MAX_RATE_SNAPSHOTS + 3, MAX_RATE_SNAPSHOTS + 4]);
+    }
+
+    #[test]
+    fn apr_is_annualised_over_the_window() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+
+        // no history, no estimate
+        let res = query_estimated_apr(deps.as_ref(), env.clone(), 1000).unwrap();
+        assert_eq!(res.apr, None);
+
+        // an old snapshot outside the window, then 1% growth over 1/10 of a year
+        record_rate(&mut deps.storage, &env.block, &supply(500, 1000)).unwrap();
+        env.block.time = env.block.time.plus_seconds(SECONDS_PER_YEAR);
+        record_rate(&mut deps.storage, &env.block, &supply(1000, 1000)).unwrap();
+        let res = query_estimated_apr(deps.as_ref(), env.clone(), 1000).unwrap();
+        assert_eq!(res.apr, None);
+        env.block.time = env.block.time.plus_seconds(SECONDS_PER_YEAR / 10);
+        record_rate(&mut deps.storage, &env.block, &supply(1010, 1000)).unwrap();
+        let window = SECONDS_PER_YEAR / 10;
+        let res = query_estimated_apr(deps.as_ref(), env.clone(), window).unwrap();
+        assert_eq!(res.apr, Some(Decimal::percent(10)));
+
+        // a longer window starts at the first snapshot: 102% over 1.1 years
+        let res = query_estimated_apr(deps.as_ref(), env.clone(), SECONDS_PER_YEAR * 2).unwrap();
+        assert_eq!(
+            res.apr,
+            Some(Decimal::from_ratio(102u128, 100u128) * Decimal::from_ratio(10u128, 11u128))
+        );
+
+        // a slash shows as no yield
+        env.block.time = env.block.time.plus_seconds(60);
+        record_rate(&mut deps.storage, &env.block, &supply(900, 1000)).unwrap();
+        let res = query_estimated_apr(deps.as_ref(), env, window).unwrap();
+        assert_eq!(res.apr, Some(Decimal::zero()));
+    }
+
+    #[test]
+    fn one_snapshot_per_block() {
+        let mut deps = mock_dependencies();
+        let mut block = mock_env().block;
+        record_rate(&mut deps.storage, &block, &supply(1000, 1000)).unwrap();
+        block.time = block.time.plus_seconds(5);
+        for i in 0..MAX_RATE_SNAPSHOTS + 5 {
+            record_rate(&mut deps.storage, &block, &supply(1000 + i as u128, 1000)).unwrap();
+        }
+
+        // the block only keeps its last one, and the earlier history stays
+        let history = query_rate_history(deps.as_ref(), None, None).unwrap();
+        let seqs: Vec<_> = history.snapshots.iter().map(|s| s.seq).collect();
+        assert_eq!(seqs, vec![0, 1]);
+        assert_eq!(history.snapshots[1].bonded, Uint128::new(1104));
+    }
+
+    #[test]
+    fn no_apr_after_a_total_loss() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        record_rate(&mut deps.storage, &env.block, &supply(0, 1000)).unwrap();
+        env.block.time = env.block.time.plus_seconds(60);
+        record_rate(&mut deps.storage, &env.block, &supply(10, 1000)).unwrap();
+        let res = query_estimated_apr(deps.as_ref(), env, 1000).unwrap();
+        assert_eq!(res.apr, None);
+    }
+}
@@ -0,0 +1,751 @@
+#![cfg(test)]
+
+use anyhow::Result as AnyResult;
+use cosmwasm_std::testing::{mock_env, MockApi, MockStorage};
+use cosmwasm_std::{
+    coin, coins, to_binary, Addr, Api, Binary, BlockInfo, CustomQuery, Decimal, Empty, Event,
+    GovMsg, IbcMsg, IbcQuery, Querier, Storage, Uint128, Validator, VoteOption,
+};
+use cw20::{BalanceResponse, Cw20ReceiveMsg, TokenInfoResponse};
+use cw_multi_test::{
+    App, AppBuilder, AppResponse, BankKeeper, Contract, ContractWrapper, CosmosRouter,
+    DistributionKeeper, Executor, FailingModule, Gov, Module, StakeKeeper, StakingInfo,
+    StakingSudo, SudoMsg, WasmKeeper,
+};
+use cw_utils::{Duration, Expiration};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use std::fmt::Debug;
+
+use crate::msg::{
+    BufferResponse, ClaimsResponse, ExecuteMsg, InstantiateMsg, InvestmentResponse,
+    ProposalResponse, QueryMsg, ReceiveMsg, ValidatorAllocation, ValidatorsResponse, VoteTally,
+};
+use crate::state::{ExitTaxMode, ValidatorWeight};
+use crate::ContractError;
+
+const DENOM: &str = "ustake";
+const OWNER: &str = "owner";
+const BOB: &str = "bob";
+const CARL: &str = "carl";
+const INITIAL_FUNDS: u128 = 1_000_000;
+const VALIDATORS: [&str; 2] = ["validator1", "validator2"];
+const UNBONDING_PERIOD: u64 = 60;
+const YEAR: u64 = 365 * 24 * 60 * 60;
+
+/// Accepts all governance messages, and reports weighted votes in a `proposal_vote` event
+struct GovStub;
+
+impl Module for GovStub {
+    type ExecT = GovMsg;
+    type QueryT = Empty;
+    type SudoT = Empty;
+
+    fn execute<ExecC, QueryC>(
+        &self,
+        _api: &dyn Api,
+        _storage: &mut dyn Storage,
+        _router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+        _block: &BlockInfo,
+        sender: Addr,
+        msg: GovMsg,
+    ) -> AnyResult<AppResponse>
+    where
+        ExecC: Debug + Clone + PartialEq + JsonSchema + DeserializeOwned + 'static,
+        QueryC: CustomQuery + DeserializeOwned + 'static,
+    {
+        let mut res = AppResponse::default();
+        if let GovMsg::VoteWeighted {
+            proposal_id,
+            options,
+        } = msg
+        {
+            let options: Vec<_> = options
+                .iter()
+                .map(|o| format!("{:?}:{}", o.option, o.weight))
+                .collect();
+            res.events.push(
+                Event::new("proposal_vote")
+                    .add_attribute("voter", sender)
+                    .add_attribute("proposal_id", proposal_id.to_string())
+                    .add_attribute("options", options.join(",")),
+            );
+        }
+        Ok(res)
+    }
+
+    fn sudo<ExecC, QueryC>(
+        &self,
+        _api: &dyn Api,
+        _storage: &mut dyn Storage,
+        _router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+        _block: &BlockInfo,
+        _msg: Empty,
+    ) -> AnyResult<AppResponse>
+    where
+        ExecC: Debug + Clone + PartialEq + JsonSchema + DeserializeOwned + 'static,
+        QueryC: CustomQuery + DeserializeOwned + 'static,
+    {
+        Ok(AppResponse::default())
+    }
+
+    fn query(
+        &self,
+        _api: &dyn Api,
+        _storage: &dyn Storage,
+        _querier: &dyn Querier,
+        _block: &BlockInfo,
+        _request: Empty,
+    ) -> AnyResult<Binary> {
+        Ok(Binary::default())
+    }
+}
+
+impl Gov for GovStub {}
+
+type StakingApp = App<
+    BankKeeper,
+    MockApi,
+    MockStorage,
+    FailingModule<Empty, Empty, Empty>,
+    WasmKeeper<Empty, Empty>,
+    StakeKeeper,
+    DistributionKeeper,
+    FailingModule<IbcMsg, IbcQuery, Empty>,
+    GovStub,
+>;
+
+pub fn contract_staking() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        crate::contract::execute,
+        crate::contract::instantiate,
+        crate::contract::query,
+    );
+    Box::new(contract)
+}
+
+fn weights(validators: &[(&str, u64)]) -> Vec<ValidatorWeight> {
+    validators
+        .iter()
+        .map(|(address, weight)| ValidatorWeight {
+            address: address.to_string(),
+            weight: *weight,
+        })
+        .collect()
+}
+
+/// Sets up a chain with two validators, and the contract bonding to the first
+fn setup() -> (StakingApp, Addr) {
+    setup_funded(&[BOB])
+}
+
+/// Like `setup`, with all `holders` funded
+fn setup_funded(holders: &[&str]) -> (StakingApp, Addr) {
+    let mut app = AppBuilder::new()
+        .with_gov(GovStub)
+        .build(|router, api, storage| {
+            let block = mock_env().block;
+            router
+                .staking
+                .setup(
+                    storage,
+                    StakingInfo {
+                        bonded_denom: DENOM.to_string(),
+                        unbonding_time: UNBONDING_PERIOD,
+                        apr: Decimal::percent(10),
+                    },
+                )
+                .unwrap();
+            for validator in VALIDATORS {
+                let validator = Validator {
+                    address: validator.to_string(),
+                    commission: Decimal::percent(5),
+                    max_commission: Decimal::percent(20),
+                    max_change_rate: Decimal::percent(1),
+                };
+                router
+                    .staking
+                    .add_validator(api, storage, &block, validator)
+                    .unwrap();
+            }
+            for &holder in holders {
+                router
+                    .bank
+                    .init_balance(
+                        storage,
+                        &Addr::unchecked(holder),
+                        coins(INITIAL_FUNDS, DENOM),
+                    )
+                    .unwrap();
+            }
+        });
+
+    let code_id = app.store_code(contract_staking());
+    let msg = InstantiateMsg {
+        name: "Cool Derivative".to_string(),
+        symbol: "DRV".to_string(),
+        decimals: 6,
+        validators: weights(&[(VALIDATORS[0], 1)]),
+        unbonding_period: Duration::Time(UNBONDING_PERIOD),
+        unbonding_epoch: Duration::Time(0),
+        exit_tax: Decimal::zero(),
+        min_withdrawal: Uint128::new(10),
+        keeper_bounty_bps: 0,
+        commission_bps: 0,
+        treasury: None,
+        exit_tax_mode: ExitTaxMode::Treasury,
+        buffer_target_bps: 0,
+        instant_unbond_fee_bps: 0,
+        max_bonded: None,
+        max_bonded_per_address: None,
+        allowlist: None,
+    };
+    let contract = app
+        .instantiate_contract(code_id, Addr::unchecked(OWNER), &msg, &[], "staking", None)
+        .unwrap();
+    (app, contract)
+}
+
+fn delegations(app: &StakingApp, contract: &Addr) -> Vec<(String, u128)> {
+    app.wrap()
+        .query_all_delegations(contract)
+        .unwrap()
+        .into_iter()
+        .map(|d| (d.validator, d.amount.amount.u128()))
+        .collect()
+}
+
+#[test]
+fn owner_rotates_validators() {
+    let (mut app, contract) = setup();
+    let bond = |app: &mut StakingApp, amount| {
+        app.execute_contract(
+            Addr::unchecked(BOB),
+            contract.clone(),
+            &ExecuteMsg::Bond {},
+            &coins(amount, DENOM),
+        )
+        .unwrap();
+    };
+    bond(&mut app, 1000);
+    assert_eq!(
+        delegations(&app, &contract),
+        vec![(VALIDATORS[0].to_string(), 1000)]
+    );
+
+    // validator1 misbehaves, so only validator2 should be used from now on
+    let set = ExecuteMsg::SetValidators {
+        validators: weights(&[(VALIDATORS[1], 1)]),
+    };
+    let err = app
+        .execute_contract(Addr::unchecked(BOB), contract.clone(), &set, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &set, &[])
+        .unwrap();
+
+    // the stake is still on validator1, which should hold nothing
+    let validators: ValidatorsResponse = app
+        .wrap()
+        .query_wasm_smart(&contract, &QueryMsg::Validators {})
+        .unwrap();
+    assert_eq!(
+        validators.validators,
+        vec![
+            ValidatorAllocation {
+                address: VALIDATORS[1].to_string(),
+                weight: 1,
+                target: Uint128::new(1000),
+                bonded: Uint128::zero(),
+            },
+            ValidatorAllocation {
+                address: VALIDATORS[0].to_string(),
+                weight: 0,
+                target: Uint128::zero(),
+                bonded: Uint128::new(1000),
+            },
+        ]
+    );
+
+    // it can only be moved into the set, and no more than is delegated
+    let redelegate = |from: &str, to: &str, amount| ExecuteMsg::Redelegate {
+        from: from.to_string(),
+        to: to.to_string(),
+        amount: Uint128::new(amount),
+    };
+    let owner = Addr::unchecked(OWNER);
+    let err = app
+        .execute_contract(
+            owner.clone(),
+            contract.clone(),
+            &redelegate(VALIDATORS[1], VALIDATORS[0], 1000),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::NotInSet {
+            validator: VALIDATORS[0].to_string()
+        },
+        err.downcast().unwrap()
+    );
+    let err = app
+        .execute_contract(
+            owner.clone(),
+            contract.clone(),
+            &redelegate(VALIDATORS[0], VALIDATORS[1], 1001),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::RedelegateTooMuch {
+            validator: VALIDATORS[0].to_string(),
+            max: Uint128::new(1000)
+        },
+        err.downcast().unwrap()
+    );
+    app.execute_contract(
+        owner,
+        contract.clone(),
+        &redelegate(VALIDATORS[0], VALIDATORS[1], 1000),
+        &[],
+    )
+    .unwrap();
+    assert_eq!(
+        delegations(&app, &contract),
+        vec![(VALIDATORS[1].to_string(), 1000)]
+    );
+
+    // the stored supply still matches, so bonding and unbonding go on as before
+    bond(&mut app, 500);
+    let unbond = ExecuteMsg::Unbond {
+        amount: Uint128::new(300),
+    };
+    app.execute_contract(Addr::unchecked(BOB), contract.clone(), &unbond, &[])
+        .unwrap();
+    let process = ExecuteMsg::ProcessUnbondingEpoch {};
+    app.execute_contract(Addr::unchecked(BOB), contract.clone(), &process, &[])
+        .unwrap();
+    assert_eq!(
+        delegations(&app, &contract),
+        vec![(VALIDATORS[1].to_string(), 1200)]
+    );
+    let balance = app.wrap().query_balance(BOB, DENOM).unwrap();
+    assert_eq!(balance, coin(INITIAL_FUNDS - 1500, DENOM));
+}
+
+#[test]
+fn holders_vote_through_the_contract() {
+    let (mut app, contract) = setup();
+    let bob = Addr::unchecked(BOB);
+    let carl = Addr::unchecked("carl");
+    app.execute_contract(
+        bob.clone(),
+        contract.clone(),
+        &ExecuteMsg::Bond {},
+        &coins(1000, DENOM),
+    )
+    .unwrap();
+    let transfer = |amount| ExecuteMsg::Transfer {
+        recipient: carl.to_string(),
+        amount: Uint128::new(amount),
+    };
+    app.execute_contract(bob.clone(), contract.clone(), &transfer(400), &[])
+        .unwrap();
+
+    // only registered proposals, registered by the owner
+    let vote = |vote| ExecuteMsg::Vote {
+        proposal_id: 7,
+        vote,
+    };
+    let err = app
+        .execute_contract(bob.clone(), contract.clone(), &vote(VoteOption::Yes), &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::UnknownProposal { proposal_id: 7 },
+        err.downcast().unwrap()
+    );
+    let voting_ends = Expiration::AtTime(app.block_info().time.plus_seconds(100));
+    let register = ExecuteMsg::RegisterProposal {
+        proposal_id: 7,
+        voting_ends,
+    };
+    let err = app
+        .execute_contract(bob.clone(), contract.clone(), &register, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &register, &[])
+        .unwrap();
+
+    // holders vote, others can't
+    app.execute_contract(bob.clone(), contract.clone(), &vote(VoteOption::Yes), &[])
+        .unwrap();
+    app.execute_contract(carl.clone(), contract.clone(), &vote(VoteOption::No), &[])
+        .unwrap();
+    let err = app
+        .execute_contract(
+            Addr::unchecked("dave"),
+            contract.clone(),
+            &vote(VoteOption::No),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::NoVotingPower {}, err.downcast().unwrap());
+
+    // tokens count for whoever holds them when the vote is cast
+    app.execute_contract(bob.clone(), contract.clone(), &transfer(100), &[])
+        .unwrap();
+    let proposal: ProposalResponse = app
+        .wrap()
+        .query_wasm_smart(&contract, &QueryMsg::Proposal { proposal_id: 7 })
+        .unwrap();
+    let tally = |option, amount| VoteTally {
+        option,
+        amount: Uint128::new(amount),
+    };
+    assert_eq!(
+        proposal.tally,
+        vec![
+            tally(VoteOption::Yes, 500),
+            tally(VoteOption::No, 500),
+            tally(VoteOption::Abstain, 0),
+            tally(VoteOption::NoWithVeto, 0),
+        ]
+    );
+
+    // anyone can cast the vote, with the whole delegation
+    let cast = ExecuteMsg::CastVote { proposal_id: 7 };
+    let res = app
+        .execute_contract(Addr::unchecked("dave"), contract.clone(), &cast, &[])
+        .unwrap();
+    let vote_event = res.events.iter().find(|e| e.ty == "proposal_vote").unwrap();
+    let attrs: Vec<_> = vote_event
+        .attributes
+        .iter()
+        .map(|a| (a.key.as_str(), a.value.as_str()))
+        .collect();
+    assert_eq!(
+        attrs,
+        vec![
+            ("voter", contract.as_str()),
+            ("proposal_id", "7"),
+            ("options", "Yes:0.5,No:0.5")
+        ]
+    );
+
+    // bob changes their vote, and it is cast again
+    app.execute_contract(bob, contract.clone(), &vote(VoteOption::Abstain), &[])
+        .unwrap();
+    let res = app
+        .execute_contract(carl.clone(), contract.clone(), &cast, &[])
+        .unwrap();
+    let vote_event = res.events.iter().find(|e| e.ty == "proposal_vote").unwrap();
+    assert_eq!(vote_event.attributes[2].value, "No:0.5,Abstain:0.5");
+
+    // until voting ends
+    app.update_block(|block| block.time = block.time.plus_seconds(100));
+    let err = app
+        .execute_contract(carl.clone(), contract.clone(), &cast, &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::VotingClosed { proposal_id: 7 },
+        err.downcast().unwrap()
+    );
+    let err = app
+        .execute_contract(carl, contract, &vote(VoteOption::Yes), &[])
+        .unwrap_err();
+    assert_eq!(
+        ContractError::VotingClosed { proposal_id: 7 },
+        err.downcast().unwrap()
+    );
+}
+
+#[test]
+fn unbond_through_send_and_claim_elsewhere() {
+    let (mut app, contract) = setup();
+    let vault = Addr::unchecked(BOB);
+    app.execute_contract(
+        vault.clone(),
+        contract.clone(),
+        &ExecuteMsg::Bond {},
+        &coins(1000, DENOM),
+    )
+    .unwrap();
+
+    // only the contract's own tokens can be received
+    let fake = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: vault.to_string(),
+        amount: Uint128::new(500),
+        msg: to_binary(&ReceiveMsg::Unbond { recipient: None }).unwrap(),
+    });
+    let err = app
+        .execute_contract(vault.clone(), contract.clone(), &fake, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+    // the vault sends tokens to unbond, for a user and for itself
+    let send = |amount, recipient: Option<&str>| ExecuteMsg::Send {
+        contract: contract.to_string(),
+        amount: Uint128::new(amount),
+        msg: to_binary(&ReceiveMsg::Unbond {
+            recipient: recipient.map(String::from),
+        })
+        .unwrap(),
+    };
+    app.execute_contract(
+        vault.clone(),
+        contract.clone(),
+        &send(400, Some("carl")),
+        &[],
+    )
+    .unwrap();
+    app.execute_contract(vault.clone(), contract.clone(), &send(100, None), &[])
+        .unwrap();
+    let process = ExecuteMsg::ProcessUnbondingEpoch {};
+    app.execute_contract(vault.clone(), contract.clone(), &process, &[])
+        .unwrap();
+    let claims = |app: &StakingApp, address: &str| -> Vec<(u128, Option<u128>)> {
+        let res: ClaimsResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &contract,
+                &QueryMsg::Claims {
+                    address: address.to_string(),
+                },
+            )
+            .unwrap();
+        res.claims
+            .into_iter()
+            .map(|c| (c.tokens.u128(), c.amount.map(|a| a.u128())))
+            .collect()
+    };
+    assert_eq!(claims(&app, "carl"), vec![(400, Some(400))]);
+    assert_eq!(claims(&app, BOB), vec![(100, Some(100))]);
+
+    // once unbonded, a keeper pushes the claim out to the user
+    app.update_block(|block| block.time = block.time.plus_seconds(UNBONDING_PERIOD));
+    app.sudo(SudoMsg::Staking(StakingSudo::ProcessQueue {}))
+        .unwrap();
+    let claim_for = ExecuteMsg::ClaimFor {
+        address: "carl".to_string(),
+    };
+    let keeper = Addr::unchecked("keeper");
+    app.execute_contract(keeper.clone(), contract.clone(), &claim_for, &[])
+        .unwrap();
+    let balance = app.wrap().query_balance("carl", DENOM).unwrap();
+    assert_eq!(balance, coin(400, DENOM));
+    let balance = app.wrap().query_balance("keeper", DENOM).unwrap();
+    assert_eq!(balance, coin(0, DENOM));
+    let err = app
+        .execute_contract(keeper, contract.clone(), &claim_for, &[])
+        .unwrap_err();
+    assert_eq!(ContractError::NothingToClaim {}, err.downcast().unwrap());
+
+    // and the vault claims to somewhere else
+    let claim = ExecuteMsg::Claim {
+        recipient: Some("payout".to_string()),
+    };
+    app.execute_contract(vault, contract, &claim, &[]).unwrap();
+    let balance = app.wrap().query_balance("payout", DENOM).unwrap();
+    assert_eq!(balance, coin(100, DENOM));
+    let balance = app.wrap().query_balance(BOB, DENOM).unwrap();
+    assert_eq!(balance, coin(INITIAL_FUNDS - 1000, DENOM));
+}
+
+fn investment(app: &StakingApp, contract: &Addr) -> InvestmentResponse {
+    app.wrap()
+        .query_wasm_smart(contract, &QueryMsg::Investment {})
+        .unwrap()
+}
+
+fn native_balance(app: &StakingApp, address: &str) -> u128 {
+    app.wrap()
+        .query_balance(address, DENOM)
+        .unwrap()
+        .amount
+        .u128()
+}
+
+fn claimable(app: &StakingApp, contract: &Addr, address: &str) -> u128 {
+    let res: ClaimsResponse = app
+        .wrap()
+        .query_wasm_smart(
+            contract,
+            &QueryMsg::Claims {
+                address: address.to_string(),
+            },
+        )
+        .unwrap();
+    res.claims
+        .iter()
+        .filter_map(|c| c.amount)
+        .sum::<Uint128>()
+        .u128()
+}
+
+/// Checks the books of the contract against the chain, and returns the exchange rate
+fn check_invariants(app: &StakingApp, contract: &Addr) -> Decimal {
+    let invest = investment(app, contract);
+    let token: TokenInfoResponse = app
+        .wrap()
+        .query_wasm_smart(contract, &QueryMsg::TokenInfo {})
+        .unwrap();
+    let buffer: BufferResponse = app
+        .wrap()
+        .query_wasm_smart(contract, &QueryMsg::Buffer {})
+        .unwrap();
+
+    // the supply is what the holders (and the contract, for queued unbonds) have
+    assert_eq!(invest.token_supply, token.total_supply);
+    let held: Uint128 = [BOB, CARL, contract.as_str()]
+        .iter()
+        .map(|address| {
+            let res: BalanceResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contract,
+                    &QueryMsg::Balance {
+                        address: address.to_string(),
+                    },
+                )
+                .unwrap();
+            res.balance
+        })
+        .sum();
+    assert_eq!(held, token.total_supply);
+
+    // what the contract thinks it has bonded is what is delegated
+    let delegated: u128 = delegations(app, contract).iter().map(|(_, a)| a).sum();
+    assert_eq!(invest.staked_tokens, coin(delegated, DENOM));
+    assert!(native_balance(app, contract.as_str()) >= buffer.available.u128());
+
+    // and the exchange rate is all backing over the supply
+    if !token.total_supply.is_zero() {
+        let backing = invest.staked_tokens.amount + buffer.available + buffer.refilling;
+        assert_eq!(
+            invest.nominal_value,
+            Decimal::from_ratio(backing, token.total_supply)
+        );
+    }
+    invest.nominal_value
+}
+
+#[test]
+fn full_lifecycle_against_staking_modules() {
+    let (mut app, contract) = setup_funded(&[BOB, CARL]);
+    let bond = |app: &mut StakingApp, holder: &str, amount| {
+        app.execute_contract(
+            Addr::unchecked(holder),
+            contract.clone(),
+            &ExecuteMsg::Bond {},
+            &coins(amount, DENOM),
+        )
+        .unwrap();
+    };
+    let unbond = |app: &mut StakingApp, holder: &str, amount| {
+        let msg = ExecuteMsg::Unbond {
+            amount: Uint128::new(amount),
+        };
+        app.execute_contract(Addr::unchecked(holder), contract.clone(), &msg, &[])
+            .unwrap();
+        let process = ExecuteMsg::ProcessUnbondingEpoch {};
+        app.execute_contract(Addr::unchecked(holder), contract.clone(), &process, &[])
+            .unwrap();
+    };
+    let reinvest = |app: &mut StakingApp| {
+        app.execute_contract(
+            Addr::unchecked("keeper"),
+            contract.clone(),
+            &ExecuteMsg::Reinvest {},
+            &[],
+        )
+        .unwrap();
+    };
+    let claim = |app: &mut StakingApp, holder: &str| {
+        app.execute_contract(
+            Addr::unchecked(holder),
+            contract.clone(),
+            &ExecuteMsg::Claim { recipient: None },
+            &[],
+        )
+    };
+    let advance = |app: &mut StakingApp, seconds| {
+        app.update_block(|block| {
+            block.time = block.time.plus_seconds(seconds);
+            block.height += seconds / 5;
+        });
+        app.sudo(SudoMsg::Staking(StakingSudo::ProcessQueue {}))
+            .unwrap();
+    };
+
+    // both bond at par
+    bond(&mut app, BOB, 500_000);
+    bond(&mut app, CARL, 300_000);
+    assert_eq!(check_invariants(&app, &contract), Decimal::one());
+
+    // rewards accrue on chain, but only count once they are reinvested
+    advance(&mut app, YEAR);
+    let pending = app
+        .wrap()
+        .query_delegation(&contract, VALIDATORS[0])
+        .unwrap()
+        .unwrap()
+        .accumulated_rewards;
+    // 10% less 5% validator commission
+    assert_eq!(pending, coins(76_000, DENOM));
+    assert_eq!(check_invariants(&app, &contract), Decimal::one());
+    reinvest(&mut app);
+    assert_eq!(
+        delegations(&app, &contract),
+        vec![(VALIDATORS[0].to_string(), 876_000)]
+    );
+    assert_eq!(native_balance(&app, contract.as_str()), 0);
+    let rate = check_invariants(&app, &contract);
+    assert_eq!(rate, Decimal::from_ratio(876_000u128, 800_000u128));
+
+    // unbonding keeps the rate, and the claim is worth the tokens at that rate
+    unbond(&mut app, BOB, 200_000);
+    assert_eq!(check_invariants(&app, &contract), rate);
+    assert_eq!(claimable(&app, &contract, BOB), 219_000);
+    assert_eq!(
+        delegations(&app, &contract),
+        vec![(VALIDATORS[0].to_string(), 657_000)]
+    );
+
+    // nothing arrives before the unbonding period is over
+    advance(&mut app, UNBONDING_PERIOD - 1);
+    assert_eq!(native_balance(&app, contract.as_str()), 0);
+    let err = claim(&mut app, BOB).unwrap_err();
+    assert_eq!(ContractError::BalanceTooSmall {}, err.downcast().unwrap());
+
+    // then it can be claimed
+    advance(&mut app, 1);
+    assert_eq!(native_balance(&app, contract.as_str()), 219_000);
+    claim(&mut app, BOB).unwrap();
+    assert_eq!(native_balance(&app, BOB), INITIAL_FUNDS - 500_000 + 219_000);
+    assert_eq!(claimable(&app, &contract, BOB), 0);
+    assert_eq!(native_balance(&app, contract.as_str()), 0);
+    assert_eq!(check_invariants(&app, &contract), rate);
+
+    // while the rest keep earning
+    advance(&mut app, YEAR);
+    reinvest(&mut app);
+    let new_rate = check_invariants(&app, &contract);
+    assert!(new_rate > rate);
+    let delegated = delegations(&app, &contract)[0].1;
+    assert_eq!(new_rate, Decimal::from_ratio(delegated, 600_000u128));
+
+    // carl leaves with all rewards earned since bonding
+    unbond(&mut app, CARL, 300_000);
+    let owed = claimable(&app, &contract, CARL);
+    assert_eq!(owed, (Uint128::new(300_000) * new_rate).u128());
+    assert!(owed > 300_000);
+    assert!(check_invariants(&app, &contract) >= new_rate);
+    advance(&mut app, UNBONDING_PERIOD);
+    claim(&mut app, CARL).unwrap();
+    assert_eq!(native_balance(&app, CARL), INITIAL_FUNDS - 300_000 + owed);
+
+    // only the remaining tokens of bob are left, still fully backed
+    let invest = investment(&app, &contract);
+    assert_eq!(invest.token_supply, Uint128::new(300_000));
+    assert!(check_invariants(&app, &contract) >= new_rate);
+}
@@ -1,6 +1,16 @@
+pub mod buffer;
+pub mod config;
 pub mod contract;
+pub mod epochs;
 mod error;
+pub mod fees;
+pub mod gov;
+pub mod history;
+mod integration_test;
+pub mod limits;
 pub mod msg;
+pub mod slashing;
 pub mod state;
+pub mod validators;
 
 pub use crate::error::ContractError;
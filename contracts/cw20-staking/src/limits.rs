@@ -0,0 +1,179 @@
+use cosmwasm_std::{
+    Addr, Deps, DepsMut, Empty, Event, MessageInfo, Order as Ordering, Response, StdResult,
+    Storage, Uint128,
+};
+use cw_storage_plus::Bound;
+
+use crate::error::ContractError;
+use crate::msg::{AllowlistResponse, HeadroomResponse};
+use crate::state::{InvestmentInfo, Supply, ALLOWLIST, BONDED_BY, INVESTMENT, TOTAL_SUPPLY};
+
+// settings for pagination
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+
+/// How much more can be bonded in total, and by `address`. `None` means there is no cap
+fn headroom(
+    storage: &dyn Storage,
+    invest: &InvestmentInfo,
+    supply: &Supply,
+    address: &Addr,
+) -> StdResult<(Option<Uint128>, Option<Uint128>)> {
+    let total = invest
+        .max_bonded
+        .map(|max| max.saturating_sub(supply.value()));
+    let per_address = match invest.max_bonded_per_address {
+        Some(max) => {
+            let bonded = BONDED_BY.may_load(storage, address)?.unwrap_or_default();
+            Some(max.saturating_sub(bonded))
+        }
+        None => None,
+    };
+    Ok((total, per_address))
+}
+
+/// Adds `amount` native tokens to what `address` has bonded
+pub fn record_bond(storage: &mut dyn Storage, address: &Addr, amount: Uint128) -> StdResult<()> {
+    BONDED_BY.update(storage, address, |bonded| -> StdResult<_> {
+        Ok(bonded.unwrap_or_default() + amount)
+    })?;
+    Ok(())
+}
+
+/// Takes what `tokens` derivative tokens are worth now off what `address` has bonded
+pub fn record_unbond(
+    storage: &mut dyn Storage,
+    supply: &Supply,
+    address: &Addr,
+    tokens: Uint128,
+) -> StdResult<()> {
+    let bonded = match BONDED_BY.may_load(storage, address)? {
+        Some(bonded) => bonded,
+        None => return Ok(()),
+    };
+    let worth = if supply.issued.is_zero() {
+        Uint128::zero()
+    } else {
+        tokens.multiply_ratio(supply.value(), supply.issued)
+    };
+    let left = bonded.saturating_sub(worth);
+    if left.is_zero() {
+        BONDED_BY.remove(storage, address);
+    } else {
+        BONDED_BY.save(storage, address, &left)?;
+    }
+    Ok(())
+}
+
+fn is_allowed(storage: &dyn Storage, invest: &InvestmentInfo, address: &Addr) -> bool {
+    !invest.allowlist_enabled || ALLOWLIST.has(storage, address)
+}
+
+/// Checks `address` may bond `amount` more native tokens into `supply`
+pub fn check_bond(
+    storage: &dyn Storage,
+    invest: &InvestmentInfo,
+    supply: &Supply,
+    address: &Addr,
+    amount: Uint128,
+) -> Result<(), ContractError> {
+    if !is_allowed(storage, invest, address) {
+        return Err(ContractError::NotAllowlisted {
+            address: address.to_string(),
+        });
+    }
+    let (total, per_address) = headroom(storage, invest, supply, address)?;
+    match (total, per_address) {
+        (Some(headroom), _) if amount > headroom => Err(ContractError::BondCapReached { headroom }),
+        (_, Some(headroom)) if amount > headroom => {
+            Err(ContractError::AddressCapReached { headroom })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Sets all bonding limits at once (owner only), `None` removes a cap
+pub fn execute_update_bond_limits(
+    deps: DepsMut,
+    info: MessageInfo,
+    max_bonded: Option<Uint128>,
+    max_bonded_per_address: Option<Uint128>,
+    allowlist_enabled: bool,
+) -> Result<Response, ContractError> {
+    let mut invest = INVESTMENT.load(deps.storage)?;
+    if info.sender != invest.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    invest.max_bonded = max_bonded;
+    invest.max_bonded_per_address = max_bonded_per_address;
+    invest.allowlist_enabled = allowlist_enabled;
+    INVESTMENT.save(deps.storage, &invest)?;
+
+    let cap = |cap: Option<Uint128>| cap.map_or_else(|| "none".to_string(), |c| c.to_string());
+    let event = Event::new("update_bond_limits")
+        .add_attribute("max_bonded", cap(max_bonded))
+        .add_attribute("max_bonded_per_address", cap(max_bonded_per_address))
+        .add_attribute("allowlist_enabled", allowlist_enabled.to_string());
+    let res = Response::new()
+        .add_event(event)
+        .add_attribute("action", "update_bond_limits");
+    Ok(res)
+}
+
+/// Adds and removes addresses on the allowlist (owner only)
+pub fn execute_update_allowlist(
+    deps: DepsMut,
+    info: MessageInfo,
+    add: Vec<String>,
+    remove: Vec<String>,
+) -> Result<Response, ContractError> {
+    let invest = INVESTMENT.load(deps.storage)?;
+    if info.sender != invest.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    for address in &add {
+        let address = deps.api.addr_validate(address)?;
+        ALLOWLIST.save(deps.storage, &address, &Empty {})?;
+    }
+    for address in &remove {
+        let address = deps.api.addr_validate(address)?;
+        ALLOWLIST.remove(deps.storage, &address);
+    }
+
+    let res = Response::new()
+        .add_attribute("action", "update_allowlist")
+        .add_attribute("added", add.len().to_string())
+        .add_attribute("removed", remove.len().to_string());
+    Ok(res)
+}
+
+pub fn query_headroom(deps: Deps, address: String) -> StdResult<HeadroomResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let invest = INVESTMENT.load(deps.storage)?;
+    let supply = TOTAL_SUPPLY.load(deps.storage)?;
+    let (total, per_address) = headroom(deps.storage, &invest, &supply, &address)?;
+    Ok(HeadroomResponse {
+        allowed: is_allowed(deps.storage, &invest, &address),
+        total,
+        address: per_address,
+    })
+}
+
+pub fn query_allowlist(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<AllowlistResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after
+        .map(|address| deps.api.addr_validate(&address))
+        .transpose()?;
+    let start = start.as_ref().map(Bound::exclusive);
+
+    let addresses = ALLOWLIST
+        .keys(deps.storage, start, None, Ordering::Ascending)
+        .take(limit)
+        .map(|item| item.map(String::from))
+        .collect::<StdResult<_>>()?;
+    Ok(AllowlistResponse { addresses })
+}
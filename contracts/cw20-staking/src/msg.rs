@@ -1,11 +1,12 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Binary, Coin, Decimal, Uint128};
-use cw20::Expiration;
-pub use cw_controllers::ClaimsResponse;
+use cosmwasm_std::{Binary, Coin, Decimal, Timestamp, Uint128, VoteOption};
+use cw20::{Cw20ReceiveMsg, Expiration};
 use cw_utils::Duration;
 
+use crate::state::{ExitTaxMode, FeesPaid, PendingEpoch, ProcessedEpoch, ValidatorWeight};
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
     /// name of the derivative token
@@ -15,17 +16,38 @@ pub struct InstantiateMsg {
     /// decimal places of the derivative token (for UI)
     pub decimals: u8,
 
-    /// This is the validator that all tokens will be bonded to
-    pub validator: String,
+    /// These are the validators that all tokens will be bonded to, split by weight
+    pub validators: Vec<ValidatorWeight>,
     /// This is the unbonding period of the native staking module
     /// We need this to only allow claims to be redeemed after the money has arrived
     pub unbonding_period: Duration,
+    /// Unbond requests are collected for this long, and then unbonded together. This keeps
+    /// the number of unbonding entries on chain low
+    pub unbonding_epoch: Duration,
 
-    /// this is how much the owner takes as a cut when someone unbonds
+    /// this is how much the owner takes as a cut when someone unbonds (at most 10%)
     pub exit_tax: Decimal,
+    /// receives the exit tax, defaults to the owner
+    pub treasury: Option<String>,
+    /// how the exit tax is taken
+    pub exit_tax_mode: ExitTaxMode,
     /// This is the minimum amount we will pull out to reinvest, as well as a minimum
     /// that can be unbonded (to avoid needless staking tx)
     pub min_withdrawal: Uint128,
+    /// Share of reinvested rewards paid to whoever calls Reinvest, in basis points
+    pub keeper_bounty_bps: u16,
+    /// Share of reinvested rewards the owner takes as a cut, in basis points
+    pub commission_bps: u16,
+    /// Share of all backing kept liquid for instant unbonds, in basis points (at most 5000)
+    pub buffer_target_bps: u16,
+    /// Fee on instant unbonds, in basis points
+    pub instant_unbond_fee_bps: u16,
+    /// Caps all backing, bonds that would go above it fail
+    pub max_bonded: Option<Uint128>,
+    /// Caps what each address has bonded (less what it unbonded), bonds that would go above it fail
+    pub max_bonded_per_address: Option<Uint128>,
+    /// If given, only these addresses may bond
+    pub allowlist: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -33,20 +55,88 @@ pub struct InstantiateMsg {
 pub enum ExecuteMsg {
     /// Bond will bond all staking tokens sent with the message and release derivative tokens
     Bond {},
-    /// Unbond will "burn" the given amount of derivative tokens and send the unbonded
-    /// staking tokens to the message sender (after exit tax is deducted)
+    /// Unbond will queue the given amount of derivative tokens (after exit tax is deducted) for
+    /// unbonding in the current epoch. They are burnt once the epoch is processed, and the
+    /// unbonded staking tokens can be claimed after the unbonding period
     Unbond { amount: Uint128 },
+    /// InstantUnbond burns the given amount of derivative tokens and pays out what they are worth
+    /// (after the instant unbond fee) right away, from the liquid buffer
+    InstantUnbond { amount: Uint128 },
     /// Claim is used to claim your native tokens that you previously "unbonded"
-    /// after the chain-defined waiting period (eg. 3 weeks)
-    Claim {},
+    /// after the chain-defined waiting period (eg. 3 weeks). They are sent to `recipient`
+    /// if given
+    Claim { recipient: Option<String> },
+    /// ClaimFor pays out the matured claims of `address` to it. Anyone can call this
+    ClaimFor { address: String },
+    /// ProcessUnbondingEpoch unbonds everything queued in the current epoch at once, once
+    /// the epoch has closed. Anyone can call this
+    ProcessUnbondingEpoch {},
     /// Reinvest will check for all accumulated rewards, withdraw them, and
-    /// re-bond them to the same validator. Anyone can call this, which updates
-    /// the value of the token (how much under custody).
+    /// re-bond them to the validators. Anyone can call this, which updates
+    /// the value of the token (how much under custody), and is paid the keeper bounty for it.
     Reinvest {},
     /// _BondAllTokens can only be called by the contract itself, after all rewards have been
     /// withdrawn. This is an example of using "callbacks" in message flows.
-    /// This can only be invoked by the contract itself as a return from Reinvest
-    _BondAllTokens {},
+    /// This can only be invoked by the contract itself as a return from Reinvest, which passes
+    /// on who called it
+    _BondAllTokens { keeper: String },
+    /// SetValidators replaces the validator set (owner only). Stake is not moved, but new bonds
+    /// and unbonds follow the new targets, and validators outside the set are drained first
+    SetValidators { validators: Vec<ValidatorWeight> },
+    /// Redelegate moves stake from one validator to another one in the set (owner only), eg. to
+    /// rotate away from a misbehaving validator. The chain only allows moving what was not
+    /// itself redelegated recently
+    Redelegate {
+        from: String,
+        to: String,
+        amount: Uint128,
+    },
+
+    /// UpdateConfig changes the given settings (owner only). The same bounds apply as
+    /// on instantiation
+    UpdateConfig {
+        exit_tax: Option<Decimal>,
+        min_withdrawal: Option<Uint128>,
+        unbonding_period: Option<Duration>,
+        unbonding_epoch: Option<Duration>,
+        keeper_bounty_bps: Option<u16>,
+        commission_bps: Option<u16>,
+        treasury: Option<String>,
+        exit_tax_mode: Option<ExitTaxMode>,
+        buffer_target_bps: Option<u16>,
+        instant_unbond_fee_bps: Option<u16>,
+    },
+    /// TransferOwnership proposes a new owner (owner only), who must accept it.
+    /// None withdraws the proposal
+    TransferOwnership { new_owner: Option<String> },
+    /// AcceptOwnership completes a transfer, called by the proposed owner
+    AcceptOwnership {},
+    /// UpdateBondLimits sets the caps and turns the allowlist on or off (owner only).
+    /// A cap of None removes it
+    UpdateBondLimits {
+        max_bonded: Option<Uint128>,
+        max_bonded_per_address: Option<Uint128>,
+        allowlist_enabled: bool,
+    },
+    /// UpdateAllowlist adds and removes addresses that may bond (owner only)
+    UpdateAllowlist {
+        add: Vec<String>,
+        remove: Vec<String>,
+    },
+    /// This accepts derivative tokens sent to the contract with a properly-encoded ReceiveMsg
+    Receive(Cw20ReceiveMsg),
+    /// RegisterProposal opens a chain governance proposal for holders to vote on through the
+    /// contract (owner only). `voting_ends` should match the voting period on chain
+    RegisterProposal {
+        proposal_id: u64,
+        voting_ends: Expiration,
+    },
+    /// Vote records how the sender wants to vote on a registered proposal. It replaces any
+    /// earlier vote
+    Vote { proposal_id: u64, vote: VoteOption },
+    /// CastVote votes with the whole delegation, split by the current balances of all who voted.
+    /// Anyone can call it, as often as needed until voting ends
+    CastVote { proposal_id: u64 },
 
     /// Implements CW20. Transfer is a base message to move tokens to another account without triggering actions
     Transfer { recipient: String, amount: Uint128 },
@@ -94,13 +184,51 @@ pub enum ExecuteMsg {
     BurnFrom { owner: String, amount: Uint128 },
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveMsg {
+    /// Unbond queues the sent tokens like `ExecuteMsg::Unbond`. The claim goes to `recipient`,
+    /// or to the sender if not given
+    Unbond { recipient: Option<String> },
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
-    /// Claims shows the number of tokens this address can access when they are done unbonding
+    /// Claims shows the number of tokens this address can access when they are done unbonding,
+    /// by epoch
     Claims { address: String },
+    /// PendingEpoch shows the epoch currently collecting unbond requests, if any
+    PendingEpoch {},
+    /// ProcessedEpochs lists the epochs that have been unbonded, by id
+    ProcessedEpochs {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
     /// Investment shows metadata on the staking info of the contract
     Investment {},
+    /// Buffer shows the liquid buffer for instant unbonds
+    Buffer {},
+    /// Validators shows how much is delegated to each validator, and how much should be
+    Validators {},
+    /// ExchangeRateHistory lists the recent exchange rate snapshots, oldest first
+    ExchangeRateHistory {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// EstimatedApr annualises the exchange rate growth over the last `window` seconds
+    EstimatedApr { window: u64 },
+    /// Proposal shows a registered proposal, and how the votes on it would be cast now
+    Proposal { proposal_id: u64 },
+    /// Vote shows what this address voted on a proposal, if anything
+    Vote { proposal_id: u64, address: String },
+    /// Headroom shows how much more can be bonded, in total and by this address
+    Headroom { address: String },
+    /// Allowlist lists the addresses that may bond while the allowlist is enabled
+    Allowlist {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
 
     /// Implements CW20. Returns the current balance of the given address, 0 if unset.
     Balance { address: String },
@@ -118,13 +246,152 @@ pub struct InvestmentResponse {
     // ratio of staked_tokens / token_supply (or how many native tokens that one derivative token is nominally worth)
     pub nominal_value: Decimal,
 
-    /// owner created the contract and takes a cut
+    /// owner created the contract (or took it over) and takes a cut
     pub owner: String,
+    /// proposed as the next owner, until it accepts
+    pub pending_owner: Option<String>,
     /// this is how much the owner takes as a cut when someone unbonds
     pub exit_tax: Decimal,
-    /// All tokens are bonded to this validator
-    pub validator: String,
+    /// receives the exit tax
+    pub treasury: String,
+    /// how the exit tax is taken
+    pub exit_tax_mode: ExitTaxMode,
+    /// All tokens are bonded to these validators, split by weight
+    pub validators: Vec<ValidatorWeight>,
+    /// Claims can be redeemed this long after their epoch was processed
+    pub unbonding_period: Duration,
+    /// Unbond requests are collected for this long
+    pub unbonding_epoch: Duration,
     /// This is the minimum amount we will pull out to reinvest, as well as a minimum
     /// that can be unbonded (to avoid needless staking tx)
     pub min_withdrawal: Uint128,
+    /// Share of reinvested rewards paid to whoever calls Reinvest, in basis points
+    pub keeper_bounty_bps: u16,
+    /// Share of reinvested rewards the owner takes as a cut, in basis points
+    pub commission_bps: u16,
+    /// Fees paid out of reinvested rewards so far, in staking tokens
+    pub fees_paid: FeesPaid,
+    /// Share of all backing kept liquid for instant unbonds, in basis points
+    pub buffer_target_bps: u16,
+    /// Fee on instant unbonds, in basis points
+    pub instant_unbond_fee_bps: u16,
+    /// Cap on all backing, if any
+    pub max_bonded: Option<Uint128>,
+    /// Cap on what each address has bonded, less what it unbonded, if any
+    pub max_bonded_per_address: Option<Uint128>,
+    /// Only addresses on the allowlist may bond
+    pub allowlist_enabled: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HeadroomResponse {
+    /// whether the address may bond at all
+    pub allowed: bool,
+    /// native tokens that can still be bonded in total, None if uncapped
+    pub total: Option<Uint128>,
+    /// native tokens the address can still bond, None if uncapped
+    pub address: Option<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AllowlistResponse {
+    pub addresses: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BufferResponse {
+    /// native tokens available for instant unbonds
+    pub available: Uint128,
+    /// native tokens unbonding to top up the buffer
+    pub refilling: Uint128,
+    /// what the buffer should hold, given all backing
+    pub target: Uint128,
+    /// share of the target that is paid out and not yet refilled
+    pub utilisation: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ClaimsResponse {
+    pub claims: Vec<EpochClaim>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EpochClaim {
+    pub epoch: u64,
+    /// derivative tokens unbonded in this epoch
+    pub tokens: Uint128,
+    /// native tokens this pays out, once the epoch has been processed
+    pub amount: Option<Uint128>,
+    /// when this can be claimed, once the epoch has been processed
+    pub release_at: Option<Expiration>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingEpochResponse {
+    pub epoch: Option<PendingEpoch>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ProcessedEpochsResponse {
+    pub epochs: Vec<ProcessedEpoch>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExchangeRateHistoryResponse {
+    pub snapshots: Vec<ExchangeRateSnapshot>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExchangeRateSnapshot {
+    /// pass this as `start_after` to page on
+    pub seq: u64,
+    pub time: Timestamp,
+    /// all native tokens backing the derivative tokens
+    pub bonded: Uint128,
+    /// derivative tokens issued
+    pub issued: Uint128,
+    /// bonded / issued, or 1 if nothing is issued
+    pub rate: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EstimatedAprResponse {
+    /// None unless there are two snapshots at different times in the window
+    pub apr: Option<Decimal>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ProposalResponse {
+    pub proposal_id: u64,
+    pub voting_ends: Expiration,
+    /// the current balances of those who voted, by option
+    pub tally: Vec<VoteTally>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VoteTally {
+    pub option: VoteOption,
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VoteResponse {
+    pub vote: Option<VoteOption>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ValidatorsResponse {
+    /// The validator set, followed by any other validators that still hold delegations
+    pub validators: Vec<ValidatorAllocation>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ValidatorAllocation {
+    pub address: String,
+    /// Zero for validators outside the set
+    pub weight: u64,
+    /// What this validator should hold, given its weight and the total bonded
+    pub target: Uint128,
+    /// What is actually delegated to this validator
+    pub bonded: Uint128,
 }
@@ -0,0 +1,174 @@
+use cosmwasm_std::{
+    BlockInfo, Decimal, Empty, Event, Order as Ordering, StdError, StdResult, Storage, Uint128,
+};
+
+use crate::error::ContractError;
+use crate::state::{ProcessedEpoch, Supply, BUFFER_REFILLS, PROCESSED_EPOCHS, UNBONDING_EPOCHS};
+
+/// Epochs in `UNBONDING_EPOCHS` that are not released yet. Released ones are dropped from it
+fn still_unbonding(storage: &mut dyn Storage, block: &BlockInfo) -> StdResult<Vec<ProcessedEpoch>> {
+    let ids = UNBONDING_EPOCHS
+        .keys(storage, None, None, Ordering::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut unbonding = vec![];
+    for id in ids {
+        let epoch = PROCESSED_EPOCHS.load(storage, id)?;
+        if epoch.release_at.is_expired(block) {
+            UNBONDING_EPOCHS.remove(storage, id);
+        } else {
+            unbonding.push(epoch);
+        }
+    }
+    Ok(unbonding)
+}
+
+/// Marks a newly processed epoch as unbonding, and drops the ones released since
+pub fn track_unbonding(storage: &mut dyn Storage, block: &BlockInfo, id: u64) -> StdResult<()> {
+    still_unbonding(storage, block)?;
+    UNBONDING_EPOCHS.save(storage, id, &Empty {})
+}
+
+/// Compares the stored supply with what is actually delegated. If we have less than we think,
+/// a validator was slashed: `bonded` is lowered to what is left, which spreads the loss over all
+/// holders, and the claims and buffer refills of epochs that are still unbonding are cut by the
+/// same fraction, as the chain slashes unbonding stake as well. Released epochs have their
+/// tokens already, so they keep their value. Returns the `slashing_detected` event in this case.
+pub fn sync_bonded(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    supply: &mut Supply,
+    queried: Uint128,
+) -> Result<Option<Event>, ContractError> {
+    if queried > supply.bonded {
+        return Err(ContractError::BondedMismatch {
+            stored: supply.bonded,
+            queried,
+        });
+    }
+    if queried == supply.bonded {
+        return Ok(None);
+    }
+
+    let remaining = Decimal::from_ratio(queried, supply.bonded);
+    let mut claims_cut = Uint128::zero();
+    let mut refill_cut = Uint128::zero();
+    for mut epoch in still_unbonding(storage, block)? {
+        let claimable = epoch.claimable * remaining;
+        claims_cut += epoch.claimable - claimable;
+        epoch.claimable = claimable;
+        PROCESSED_EPOCHS.save(storage, epoch.id, &epoch)?;
+        if let Some(refill) = BUFFER_REFILLS.may_load(storage, epoch.id)? {
+            let left = refill * remaining;
+            refill_cut += refill - left;
+            BUFFER_REFILLS.save(storage, epoch.id, &left)?;
+        }
+    }
+
+    let event = Event::new("slashing_detected")
+        .add_attribute("stored_bonded", supply.bonded)
+        .add_attribute("queried_bonded", queried)
+        .add_attribute("loss", supply.bonded - queried)
+        .add_attribute("claims_haircut", claims_cut);
+    supply.bonded = queried;
+    supply.claims = supply
+        .claims
+        .checked_sub(claims_cut)
+        .map_err(StdError::overflow)?;
+    supply.refilling = supply
+        .refilling
+        .checked_sub(refill_cut)
+        .map_err(StdError::overflow)?;
+    Ok(Some(event))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+    use cw_utils::Duration;
+
+    fn process(storage: &mut dyn Storage, block: &BlockInfo, id: u64, claims: u128, refill: u128) {
+        let epoch = ProcessedEpoch {
+            id,
+            tokens: Uint128::new(claims),
+            unbonded: Uint128::new(claims),
+            claimable: Uint128::new(claims),
+            buffer_refill: Uint128::new(refill),
+            release_at: Duration::Time(100).after(block),
+        };
+        PROCESSED_EPOCHS.save(storage, id, &epoch).unwrap();
+        BUFFER_REFILLS
+            .save(storage, id, &Uint128::new(refill))
+            .unwrap();
+        track_unbonding(storage, block, id).unwrap();
+    }
+
+    #[test]
+    fn slash_lowers_bonded_and_claims() {
+        let mut storage = MockStorage::new();
+        let mut block = mock_env().block;
+        let mut supply = Supply {
+            issued: Uint128::new(1000),
+            bonded: Uint128::new(2000),
+            claims: Uint128::new(500),
+            refilling: Uint128::new(100),
+            ..Supply::default()
+        };
+        // a claim of 200 has matured, 300 (and a refill of 100) are still unbonding
+        process(&mut storage, &block, 1, 200, 0);
+        block.time = block.time.plus_seconds(100);
+        process(&mut storage, &block, 2, 300, 100);
+
+        // nothing happens while the numbers match
+        let event = sync_bonded(&mut storage, &block, &mut supply, Uint128::new(2000)).unwrap();
+        assert_eq!(event, None);
+
+        // lose 10%
+        let event = sync_bonded(&mut storage, &block, &mut supply, Uint128::new(1800)).unwrap();
+        let event = event.unwrap();
+        assert_eq!(event.ty, "slashing_detected");
+        assert_eq!(event.attributes[2].value, "200");
+        assert_eq!(event.attributes[3].value, "30");
+        assert_eq!(
+            supply,
+            Supply {
+                issued: Uint128::new(1000),
+                bonded: Uint128::new(1800),
+                claims: Uint128::new(470),
+                refilling: Uint128::new(90),
+                ..Supply::default()
+            }
+        );
+
+        // only the unbonding epoch is cut, the matured one has its tokens already
+        let claimable =
+            |storage: &MockStorage, id| PROCESSED_EPOCHS.load(storage, id).unwrap().claimable;
+        assert_eq!(claimable(&storage, 1), Uint128::new(200));
+        assert_eq!(claimable(&storage, 2), Uint128::new(270));
+        let refill = BUFFER_REFILLS.load(&storage, 2).unwrap();
+        assert_eq!(refill, Uint128::new(90));
+        let unbonding: Vec<_> = UNBONDING_EPOCHS
+            .keys(&storage, None, None, Ordering::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(unbonding, vec![2]);
+
+        // once that one is released too, slashes leave all claims alone
+        block.time = block.time.plus_seconds(100);
+        let event = sync_bonded(&mut storage, &block, &mut supply, Uint128::new(900)).unwrap();
+        assert_eq!(event.unwrap().attributes[3].value, "0");
+        assert_eq!(supply.claims, Uint128::new(470));
+        assert_eq!(supply.refilling, Uint128::new(90));
+        assert_eq!(claimable(&storage, 2), Uint128::new(270));
+
+        // more than expected can't be explained by slashing
+        let err = sync_bonded(&mut storage, &block, &mut supply, Uint128::new(901)).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::BondedMismatch {
+                stored: Uint128::new(900),
+                queried: Uint128::new(901)
+            }
+        );
+    }
+}
@@ -1,31 +1,82 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Addr, Decimal, Uint128};
-use cw_controllers::Claims;
-use cw_storage_plus::Item;
-use cw_utils::Duration;
+use cosmwasm_std::{Addr, Decimal, Empty, Timestamp, Uint128, VoteOption};
+use cw_storage_plus::{Item, Map};
+use cw_utils::{Duration, Expiration};
 
-pub const CLAIMS: Claims = Claims::new("claims");
-
-/// Investment info is fixed at instantiation, and is used to control the function of the contract
+/// Investment info is set at instantiation, and is used to control the function of the contract.
+/// The owner can update most of it later
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InvestmentInfo {
-    /// Owner created the contract and takes a cut
+    /// Owner created the contract (or took it over) and takes a cut
     pub owner: Addr,
     /// This is the denomination we can stake (and only one we accept for payments)
     pub bond_denom: String,
     /// This is the unbonding period of the native staking module
     /// We need this to only allow claims to be redeemed after the money has arrived
     pub unbonding_period: Duration,
+    /// The unbonding period given on instantiate, which should be the chain's unbonding time.
+    /// Updates can't go below it, or claims would be paid before their tokens arrived
+    pub min_unbonding_period: Duration,
+    /// Unbond requests are collected for this long, and then unbonded together
+    pub unbonding_epoch: Duration,
     /// This is how much the owner takes as a cut when someone unbonds
     pub exit_tax: Decimal,
-    /// All tokens are bonded to this validator
+    /// Receives the exit tax
+    pub treasury: Addr,
+    /// How the exit tax is taken
+    pub exit_tax_mode: ExitTaxMode,
+    /// All tokens are bonded to these validators, split by their weights
     /// FIXME: address validation doesn't work for validator addresses
-    pub validator: String,
+    pub validators: Vec<ValidatorWeight>,
     /// This is the minimum amount we will pull out to reinvest, as well as a minimum
     /// that can be unbonded (to avoid needless staking tx)
     pub min_withdrawal: Uint128,
+    /// Share of reinvested rewards paid to whoever called `Reinvest`, in basis points
+    pub keeper_bounty_bps: u16,
+    /// Share of reinvested rewards the owner takes as a cut, in basis points
+    pub commission_bps: u16,
+    /// Share of all backing kept liquid for instant unbonds, in basis points
+    pub buffer_target_bps: u16,
+    /// Fee on instant unbonds, in basis points. It stays in the buffer
+    pub instant_unbond_fee_bps: u16,
+    /// Bonds may not take all backing above this
+    pub max_bonded: Option<Uint128>,
+    /// Bonds may not take what an address has bonded (less what it unbonded) above this
+    pub max_bonded_per_address: Option<Uint128>,
+    /// Only addresses on the allowlist may bond
+    pub allowlist_enabled: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitTaxMode {
+    /// the tax is sent to the treasury in derivative tokens
+    Treasury,
+    /// the tax is unbonded with the rest, and the treasury can claim it in native tokens
+    /// once the claim matures
+    Native,
+    /// the tax is burnt, which raises the value of all remaining tokens
+    Burn,
+}
+
+impl ExitTaxMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExitTaxMode::Treasury => "treasury",
+            ExitTaxMode::Native => "native",
+            ExitTaxMode::Burn => "burn",
+        }
+    }
+}
+
+/// A validator we delegate to, and its share of all delegations
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ValidatorWeight {
+    pub address: String,
+    /// The validator's target share is its weight divided by the sum of all weights
+    pub weight: u64,
 }
 
 /// Supply is dynamic and tracks the current supply of staked and ERC20 tokens.
@@ -33,11 +84,107 @@ pub struct InvestmentInfo {
 pub struct Supply {
     /// issued is how many derivative tokens this contract has issued
     pub issued: Uint128,
-    /// bonded is how many native tokens exist bonded to the validator
+    /// bonded is how many native tokens exist bonded to the validators
     pub bonded: Uint128,
     /// claims is how many tokens need to be reserved paying back those who unbonded
     pub claims: Uint128,
+    /// buffer is how many native tokens are kept liquid for instant unbonds
+    pub buffer: Uint128,
+    /// refilling is how many native tokens are unbonding to top up the buffer
+    pub refilling: Uint128,
+}
+
+impl Supply {
+    /// All native tokens backing the derivative tokens, liquid or not
+    pub fn value(&self) -> Uint128 {
+        self.bonded + self.buffer + self.refilling
+    }
+}
+
+/// Fees paid out of reinvested rewards so far
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct FeesPaid {
+    pub keeper_bounties: Uint128,
+    pub commission: Uint128,
+}
+
+/// The epoch currently collecting unbond requests. The unbonded tokens are held by the contract
+/// until the epoch is processed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingEpoch {
+    pub id: u64,
+    /// derivative tokens to unbond in this epoch
+    pub tokens: Uint128,
+    /// once this has passed, anyone can process the epoch
+    pub closes_at: Expiration,
 }
 
+/// An epoch that has been unbonded. Its claims are paid out pro rata to the tokens unbonded.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ProcessedEpoch {
+    pub id: u64,
+    /// derivative tokens unbonded in this epoch
+    pub tokens: Uint128,
+    /// native tokens undelegated for them
+    pub unbonded: Uint128,
+    /// native tokens the claims are worth, `unbonded` less what was slashed while unbonding
+    pub claimable: Uint128,
+    /// native tokens undelegated along with them, to top up the buffer
+    pub buffer_refill: Uint128,
+    /// when the undelegated tokens have arrived and can be claimed
+    pub release_at: Expiration,
+}
+
+/// The exchange rate at some point in time, as recorded on reinvesting and processing epochs
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RateSnapshot {
+    pub time: Timestamp,
+    /// all native tokens backing the derivative tokens (`Supply::value`)
+    pub bonded: Uint128,
+    /// derivative tokens issued
+    pub issued: Uint128,
+}
+
+/// A chain governance proposal holders can vote on through the contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Proposal {
+    /// votes are taken until then, the owner sets it from the chain proposal
+    pub voting_ends: Expiration,
+}
+
+/// Proposed by the owner, becomes the owner once it accepts
+pub const PENDING_OWNER: Item<Addr> = Item::new("pending_owner");
 pub const INVESTMENT: Item<InvestmentInfo> = Item::new("invest");
 pub const TOTAL_SUPPLY: Item<Supply> = Item::new("total_supply");
+pub const FEES_PAID: Item<FeesPaid> = Item::new("fees_paid");
+
+pub const PENDING_EPOCH: Item<PendingEpoch> = Item::new("pending_epoch");
+pub const EPOCH_COUNT: Item<u64> = Item::new("epoch_count");
+pub const PROCESSED_EPOCHS: Map<u64, ProcessedEpoch> = Map::new("processed_epochs");
+/// Processed epochs that may still be unbonding, only these are cut on slashing (see `slashing`)
+pub const UNBONDING_EPOCHS: Map<u64, Empty> = Map::new("unbonding_epochs");
+/// Buffer refills in native tokens by epoch, until they are released into the buffer
+pub const BUFFER_REFILLS: Map<u64, Uint128> = Map::new("buffer_refills");
+/// Derivative tokens unbonded by each address in each epoch, until they are claimed
+pub const EPOCH_CLAIMS: Map<(&Addr, u64), Uint128> = Map::new("epoch_claims");
+/// Recent exchange rate snapshots by sequence number, only the latest are kept (see `history`)
+pub const RATE_HISTORY: Map<u64, RateSnapshot> = Map::new("rate_history");
+/// Sequence number of the next snapshot
+pub const RATE_COUNT: Item<u64> = Item::new("rate_count");
+
+/// Chain proposals registered by the owner, by proposal id
+pub const PROPOSALS: Map<u64, Proposal> = Map::new("proposals");
+/// What each holder voted on each proposal. It is weighted by their balance when cast
+pub const VOTES: Map<(u64, &Addr), VoteOption> = Map::new("votes");
+/// Running totals of the balances of the voters, by proposal and option (see `gov`)
+pub const TALLIES: Map<(u64, &str), Uint128> = Map::new("tallies");
+/// The balance each vote on a proposal that is still open counts with in `TALLIES`.
+/// Closed proposals are dropped once the voter's balance changes
+pub const OPEN_VOTES: Map<(&Addr, u64), Uint128> = Map::new("open_votes");
+
+/// Native tokens bonded by each address, less what it has unbonded since. The per-address cap
+/// is checked against this, so moving the tokens elsewhere doesn't make room (see `limits`)
+pub const BONDED_BY: Map<&Addr, Uint128> = Map::new("bonded_by");
+
+/// Addresses that may bond while the allowlist is enabled
+pub const ALLOWLIST: Map<&Addr, Empty> = Map::new("allowlist");
@@ -0,0 +1,313 @@
+use std::collections::HashSet;
+
+use cosmwasm_std::{
+    coin, Addr, Delegation, Deps, DepsMut, Env, MessageInfo, QuerierWrapper, Response, StakingMsg,
+    StdResult, Uint128,
+};
+
+use crate::error::ContractError;
+use crate::msg::{ValidatorAllocation, ValidatorsResponse};
+use crate::state::{ValidatorWeight, INVESTMENT};
+
+/// How much is delegated to (or should move from or to) each validator
+pub type Allocation = Vec<(String, Uint128)>;
+
+/// Ensures the set is not empty, has no duplicates or zero weights, and that every validator
+/// is registered on chain
+pub fn validate_validators(
+    querier: &QuerierWrapper,
+    validators: &[ValidatorWeight],
+) -> Result<(), ContractError> {
+    if validators.is_empty() {
+        return Err(ContractError::EmptyValidatorSet {});
+    }
+    let registered = querier.query_all_validators()?;
+    let mut seen = HashSet::new();
+    for v in validators {
+        if !seen.insert(v.address.as_str()) {
+            return Err(ContractError::DuplicateValidator {
+                validator: v.address.clone(),
+            });
+        }
+        if v.weight == 0 {
+            return Err(ContractError::ZeroWeight {
+                validator: v.address.clone(),
+            });
+        }
+        if !registered.iter().any(|r| r.address == v.address) {
+            return Err(ContractError::NotInValidatorSet {
+                validator: v.address.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// get_delegations returns all delegations from contract, by validator
+/// it ensures they are all the same denom
+pub fn get_delegations(
+    querier: &QuerierWrapper,
+    contract: &Addr,
+) -> Result<Allocation, ContractError> {
+    let bonds = querier.query_all_delegations(contract)?;
+    if let Some(first) = bonds.first() {
+        let denom = first.amount.denom.as_str();
+        if let Some(d) = bonds.iter().find(|d| d.amount.denom != denom) {
+            return Err(ContractError::DifferentBondDenom {
+                denom1: denom.into(),
+                denom2: d.amount.denom.to_string(),
+            });
+        }
+    }
+    Ok(by_validator(bonds))
+}
+
+fn by_validator(bonds: Vec<Delegation>) -> Allocation {
+    bonds
+        .into_iter()
+        .map(|d| (d.validator, d.amount.amount))
+        .collect()
+}
+
+pub fn total(allocation: &[(String, Uint128)]) -> Uint128 {
+    allocation.iter().map(|(_, amount)| amount).sum()
+}
+
+fn delegated(delegations: &[(String, Uint128)], validator: &str) -> Uint128 {
+    delegations
+        .iter()
+        .find(|(v, _)| v == validator)
+        .map(|(_, amount)| *amount)
+        .unwrap_or_default()
+}
+
+/// What each validator should hold out of `total`. Validators outside the set should hold nothing
+fn target(validators: &[ValidatorWeight], validator: &str, total: Uint128) -> Uint128 {
+    let weights: u64 = validators.iter().map(|v| v.weight).sum();
+    validators
+        .iter()
+        .find(|v| v.address == validator)
+        .map(|v| total.multiply_ratio(v.weight, weights))
+        .unwrap_or_default()
+}
+
+/// Splits a new delegation of `amount`, filling up the most underweight validators first
+pub fn bond_allocation(
+    validators: &[ValidatorWeight],
+    delegations: &[(String, Uint128)],
+    amount: Uint128,
+) -> Allocation {
+    let after = total(delegations) + amount;
+    let gaps = validators
+        .iter()
+        .map(|v| {
+            let missing = target(validators, &v.address, after)
+                .saturating_sub(delegated(delegations, &v.address));
+            (v.address.clone(), missing)
+        })
+        .collect();
+    fill(gaps, amount)
+}
+
+/// Splits an undelegation of `amount`, draining the most overweight validators first
+pub fn unbond_allocation(
+    validators: &[ValidatorWeight],
+    delegations: &[(String, Uint128)],
+    amount: Uint128,
+) -> StdResult<Allocation> {
+    let after = total(delegations).checked_sub(amount)?;
+    let gaps = delegations
+        .iter()
+        .map(|(v, bonded)| {
+            let excess = bonded.saturating_sub(target(validators, v, after));
+            (v.clone(), excess)
+        })
+        .collect();
+    Ok(fill(gaps, amount))
+}
+
+/// Takes `amount` from the largest gaps first. The targets are rounded down, so when bonding the
+/// gaps may fall a few tokens short, which then go to the largest gap as well.
+fn fill(mut gaps: Allocation, amount: Uint128) -> Allocation {
+    // stable, so ties keep the order of the set
+    gaps.sort_by(|(_, a), (_, b)| b.cmp(a));
+    let mut left = amount;
+    let mut allocation: Allocation = vec![];
+    for (validator, gap) in gaps.iter() {
+        let take = (*gap).min(left);
+        if take.is_zero() {
+            break;
+        }
+        allocation.push((validator.clone(), take));
+        left -= take;
+    }
+    if !left.is_zero() {
+        match allocation.first_mut() {
+            Some((_, take)) => *take += left,
+            None => allocation.extend(gaps.into_iter().take(1).map(|(v, _)| (v, left))),
+        }
+    }
+    allocation
+}
+
+pub fn execute_set_validators(
+    deps: DepsMut,
+    info: MessageInfo,
+    validators: Vec<ValidatorWeight>,
+) -> Result<Response, ContractError> {
+    let mut invest = INVESTMENT.load(deps.storage)?;
+    if info.sender != invest.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    validate_validators(&deps.querier, &validators)?;
+    let addresses: Vec<_> = validators.iter().map(|v| v.address.as_str()).collect();
+    let res = Response::new()
+        .add_attribute("action", "set_validators")
+        .add_attribute("validators", addresses.join(","));
+    invest.validators = validators;
+    INVESTMENT.save(deps.storage, &invest)?;
+    Ok(res)
+}
+
+pub fn execute_redelegate(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    from: String,
+    to: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let invest = INVESTMENT.load(deps.storage)?;
+    if info.sender != invest.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if amount.is_zero() {
+        return Err(ContractError::InvalidZeroAmount {});
+    }
+    if from == to {
+        return Err(ContractError::SameValidator {});
+    }
+    if !invest.validators.iter().any(|v| v.address == to) {
+        return Err(ContractError::NotInSet { validator: to });
+    }
+    // stake that arrived by redelegation can't move again until it has matured
+    let max = deps
+        .querier
+        .query_delegation(&env.contract.address, &from)?
+        .map(|d| d.can_redelegate.amount)
+        .unwrap_or_default();
+    if amount > max {
+        return Err(ContractError::RedelegateTooMuch {
+            validator: from,
+            max,
+        });
+    }
+
+    // this doesn't change how much is bonded, so the supply stays as it is
+    let res = Response::new()
+        .add_message(StakingMsg::Redelegate {
+            src_validator: from.clone(),
+            dst_validator: to.clone(),
+            amount: coin(amount.u128(), &invest.bond_denom),
+        })
+        .add_attribute("action", "redelegate")
+        .add_attribute("from", from)
+        .add_attribute("to", to)
+        .add_attribute("amount", amount);
+    Ok(res)
+}
+
+pub fn query_validators(deps: Deps, env: Env) -> StdResult<ValidatorsResponse> {
+    let invest = INVESTMENT.load(deps.storage)?;
+    let delegations = by_validator(deps.querier.query_all_delegations(env.contract.address)?);
+    let bonded = total(&delegations);
+
+    let in_set = invest.validators.iter().map(|v| ValidatorAllocation {
+        address: v.address.clone(),
+        weight: v.weight,
+        target: target(&invest.validators, &v.address, bonded),
+        bonded: delegated(&delegations, &v.address),
+    });
+    let others = delegations
+        .iter()
+        .filter(|(d, _)| !invest.validators.iter().any(|v| &v.address == d))
+        .map(|(d, amount)| ValidatorAllocation {
+            address: d.clone(),
+            weight: 0,
+            target: Uint128::zero(),
+            bonded: *amount,
+        });
+    Ok(ValidatorsResponse {
+        validators: in_set.chain(others).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(weights: &[(&str, u64)]) -> Vec<ValidatorWeight> {
+        weights
+            .iter()
+            .map(|(address, weight)| ValidatorWeight {
+                address: address.to_string(),
+                weight: *weight,
+            })
+            .collect()
+    }
+
+    fn alloc(amounts: &[(&str, u128)]) -> Allocation {
+        amounts
+            .iter()
+            .map(|(v, amount)| (v.to_string(), Uint128::new(*amount)))
+            .collect()
+    }
+
+    #[test]
+    fn bonds_fill_underweight_validators_first() {
+        let validators = set(&[("alice", 1), ("bob", 2), ("carl", 1)]);
+
+        // an empty set is filled up to the targets, rounding leftovers go to the largest gap
+        let bonds = bond_allocation(&validators, &[], Uint128::new(1003));
+        assert_eq!(bonds, alloc(&[("bob", 503), ("alice", 250), ("carl", 250)]));
+
+        // bob is on target (2000 of 4000), alice is below, so only she gets new stake
+        let delegations = alloc(&[("alice", 500), ("bob", 2000), ("carl", 1000)]);
+        let bonds = bond_allocation(&validators, &delegations, Uint128::new(500));
+        assert_eq!(bonds, alloc(&[("alice", 500)]));
+        // even if rounding leaves no gap at all
+        let delegations = alloc(&[("alice", 1000), ("bob", 2000), ("carl", 1000)]);
+        let bonds = bond_allocation(&validators, &delegations, Uint128::new(1));
+        assert_eq!(bonds, alloc(&[("alice", 1)]));
+
+        // a larger bond is spread over every gap towards 1500 / 3000 / 1500
+        let delegations = alloc(&[("alice", 500), ("bob", 2000), ("carl", 1000)]);
+        let bonds = bond_allocation(&validators, &delegations, Uint128::new(2500));
+        assert_eq!(
+            bonds,
+            alloc(&[("alice", 1000), ("bob", 1000), ("carl", 500)])
+        );
+    }
+
+    #[test]
+    fn unbonds_drain_overweight_validators_first() {
+        let validators = set(&[("alice", 1), ("bob", 1)]);
+
+        // bob is overweight, so he goes first, until both reach the new targets
+        let delegations = alloc(&[("alice", 1500), ("bob", 2000)]);
+        let unbonds = unbond_allocation(&validators, &delegations, Uint128::new(400)).unwrap();
+        assert_eq!(unbonds, alloc(&[("bob", 400)]));
+        let unbonds = unbond_allocation(&validators, &delegations, Uint128::new(1000)).unwrap();
+        assert_eq!(unbonds, alloc(&[("bob", 750), ("alice", 250)]));
+
+        // validators outside the set are drained first
+        let delegations = alloc(&[("alice", 1000), ("bob", 1000), ("carl", 300)]);
+        let unbonds = unbond_allocation(&validators, &delegations, Uint128::new(200)).unwrap();
+        assert_eq!(unbonds, alloc(&[("carl", 200)]));
+
+        // everything can be unbonded, but not more
+        let unbonds = unbond_allocation(&validators, &delegations, Uint128::new(2300)).unwrap();
+        assert_eq!(total(&unbonds), Uint128::new(2300));
+        unbond_allocation(&validators, &delegations, Uint128::new(2301)).unwrap_err();
+    }
+}